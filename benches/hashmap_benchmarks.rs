@@ -1,11 +1,15 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::hash::{BuildHasherDefault, Hasher};
 use std::time::Duration;
 
+use hashmap::bloom_map::BloomMap;
+use hashmap::incremental_map::IncrementalMap;
 use hashmap::workloads::{
-    generators, HashMapBehavior, KeyDistributionWorkload, KeyPattern, LoadFactorWorkload,
-    OperationMixWorkload,
+    generators, ChurnWorkload, HashMapBehavior, KeyDistributionWorkload, KeyPattern,
+    LoadFactorWorkload, OperationMixWorkload, ZipfSampler,
 };
-use hashmap::{chaining, open_addressing};
+use hashmap::{chaining, open_addressing, open_addressing_compact, CapacityPolicy, FixedSeedState};
 
 // Benchmark scenarios
 fn bench_load_factor<M: HashMapBehavior<String, String>>(c: &mut Criterion) {
@@ -13,20 +17,20 @@ fn bench_load_factor<M: HashMapBehavior<String, String>>(c: &mut Criterion) {
     group.measurement_time(Duration::from_secs(10));
 
     for size in [1000, 10_000, 100_000].iter() {
-        let workload = LoadFactorWorkload {
-            size: *size,
-            value_size: 50,
-        };
-        group.bench_with_input(
-            BenchmarkId::new(
-                format!("{}_sequential_insert", std::any::type_name::<M>()),
-                size,
-            ),
-            &workload,
-            |b, workload| {
-                b.iter(|| generators::run_load_factor_workload::<M>(workload));
-            },
-        );
+        for (preallocate, name) in [(false, "sequential_insert"), (true, "preallocated_insert")] {
+            let workload = LoadFactorWorkload {
+                size: *size,
+                value_size: 50,
+                preallocate,
+            };
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}_{}", std::any::type_name::<M>(), name), size),
+                &workload,
+                |b, workload| {
+                    b.iter(|| generators::run_load_factor_workload::<M>(workload));
+                },
+            );
+        }
     }
     group.finish();
 }
@@ -39,6 +43,14 @@ fn bench_key_distribution<M: HashMapBehavior<String, String>>(c: &mut Criterion)
         (KeyPattern::Uniform, "uniform"),
         (KeyPattern::Clustered, "clustered"),
         (KeyPattern::Sequential, "sequential"),
+        (KeyPattern::Zipfian { s: 1.0 }, "zipfian"),
+        (
+            KeyPattern::Gaussian {
+                mean: 500.0,
+                stddev: 50.0,
+            },
+            "gaussian",
+        ),
     ];
 
     for (pattern, name) in patterns.iter() {
@@ -61,18 +73,22 @@ fn bench_operation_mix<M: HashMapBehavior<String, String>>(c: &mut Criterion) {
     group.measurement_time(Duration::from_secs(10));
 
     let workloads = [
-        (90, 5, "read_heavy"),
-        (5, 90, "write_heavy"),
-        (33, 33, "balanced"),
-        (80, 15, "typical_web"),
+        (90, 5, 0, "read_heavy"),
+        (5, 90, 0, "write_heavy"),
+        (33, 33, 0, "balanced"),
+        (80, 15, 0, "typical_web"),
+        (20, 10, 65, "update_heavy"),
     ];
 
-    for (read_pct, write_pct, name) in workloads.iter() {
+    for (read_pct, write_pct, update_pct, name) in workloads.iter() {
         let workload = OperationMixWorkload {
             initial_size: 1000,
+            key_space: 1000,
             operations: 10_000,
             read_pct: *read_pct,
             write_pct: *write_pct,
+            update_pct: *update_pct,
+            value_size: 50,
         };
 
         group.bench_function(format!("{}_{}", std::any::type_name::<M>(), name), |b| {
@@ -83,16 +99,487 @@ fn bench_operation_mix<M: HashMapBehavior<String, String>>(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_churn<M: HashMapBehavior<String, String>>(c: &mut Criterion) {
+    let mut group = c.benchmark_group("churn");
+    group.measurement_time(Duration::from_secs(10));
+
+    let workload = ChurnWorkload {
+        initial_size: 1000,
+        rounds: 20,
+    };
+    group.bench_function(format!("{}_churn", std::any::type_name::<M>()), |b| {
+        b.iter(|| generators::run_churn_workload::<M>(&workload));
+    });
+
+    group.finish();
+}
+
+// Bucket layout comparison for chaining. Run with `--save-baseline` before a
+// layout change and `--baseline` after it to compare the two
+fn bench_chaining_buckets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chaining_buckets");
+    group.measurement_time(Duration::from_secs(10));
+
+    for (read_pct, write_pct, name) in [(90, 5, "read_heavy"), (5, 90, "write_heavy")] {
+        let workload = OperationMixWorkload {
+            initial_size: 10_000,
+            key_space: 10_000,
+            operations: 50_000,
+            read_pct,
+            write_pct,
+            update_pct: 0,
+            value_size: 50,
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                generators::run_operation_mix_workload::<chaining::HashMap<_, _, FixedSeedState>>(
+                    &workload,
+                )
+            });
+        });
+    }
+
+    group.finish();
+}
+
+// Growing chaining from its minimum capacity to 1M string entries, which is
+// dominated by the rehash on each doubling
+fn bench_chaining_resize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chaining_resize");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(30));
+
+    let workload = LoadFactorWorkload {
+        size: 1_000_000,
+        value_size: 50,
+        preallocate: false,
+    };
+    group.bench_function("grow_to_1m", |b| {
+        b.iter(|| {
+            generators::run_load_factor_workload::<chaining::HashMap<_, _, FixedSeedState>>(
+                &workload,
+            )
+        });
+    });
+
+    group.finish();
+}
+
+// Clustered-key inserts and lookups on the compact map, where long probe runs
+// make key comparisons the dominant cost
+fn bench_compact_probe(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compact_probe");
+    group.measurement_time(Duration::from_secs(10));
+
+    let workload = KeyDistributionWorkload {
+        size: 50_000,
+        pattern: KeyPattern::Clustered,
+    };
+    group.bench_function("clustered_insert", |b| {
+        b.iter(|| {
+            generators::run_key_distribution_workload::<
+                open_addressing_compact::HashMap<_, _, FixedSeedState>,
+            >(&workload)
+        });
+    });
+
+    let map = generators::run_key_distribution_workload::<
+        open_addressing_compact::HashMap<_, _, FixedSeedState>,
+    >(&workload);
+    let keys: Vec<String> = (0..workload.size)
+        .map(|i| format!("cluster_{}_{}", i / (workload.size / 10).max(1), i))
+        .chain((0..workload.size).map(|i| format!("cluster_missing_{}", i)))
+        .collect();
+    group.bench_function("clustered_lookup", |b| {
+        b.iter(|| keys.iter().filter(|key| map.get_ref(key).is_some()).count());
+    });
+
+    group.finish();
+}
+
+// Operation mix on the compact map, which exercises the status-bit probe loop
+fn bench_compact_operation_mix(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compact_operation_mix");
+    group.measurement_time(Duration::from_secs(10));
+
+    for (read_pct, write_pct, name) in [(33, 33, "balanced"), (5, 90, "write_heavy")] {
+        let workload = OperationMixWorkload {
+            initial_size: 10_000,
+            key_space: 10_000,
+            operations: 50_000,
+            read_pct,
+            write_pct,
+            update_pct: 0,
+            value_size: 16,
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                generators::run_operation_mix_workload::<
+                    open_addressing_compact::HashMap<_, _, FixedSeedState>,
+                >(&workload)
+            });
+        });
+    }
+
+    group.finish();
+}
+
+// Zipfian reads against chaining with and without move-to-front promotion.
+// The promoting runs read through `get_promoted`; plain `get` never reorders
+// and is measured too as the baseline shared read
+fn bench_move_to_front(c: &mut Criterion) {
+    let mut group = c.benchmark_group("move_to_front");
+    group.measurement_time(Duration::from_secs(10));
+
+    let key_space = 10_000;
+    let sampler = ZipfSampler::new(key_space, 1.0);
+    let mut rng = rand::thread_rng();
+    let reads: Vec<String> = (0..100_000)
+        .map(|_| format!("key_{}", sampler.sample(&mut rng)))
+        .collect();
+
+    group.bench_function("get", |b| {
+        b.iter_batched(
+            || {
+                let mut map = chaining::HashMap::with_seed(key_space, 0);
+                for i in 0..key_space {
+                    map.insert(format!("key_{}", i), i).unwrap();
+                }
+                map
+            },
+            |map| {
+                let mut total = 0;
+                for key in &reads {
+                    total += map.get(key.clone()).unwrap();
+                }
+                total
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
+    for (enabled, name) in [(false, "stable_order"), (true, "promote_on_read")] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let mut map = chaining::HashMap::with_seed(key_space, 0);
+                    for i in 0..key_space {
+                        map.insert(format!("key_{}", i), i).unwrap();
+                    }
+                    map.set_move_to_front(enabled);
+                    map
+                },
+                |mut map| {
+                    let mut total = 0;
+                    for key in &reads {
+                        total += *map.get_promoted(key).unwrap();
+                    }
+                    total
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+// The single insert that pushes a map past its load factor limit, which is
+// the worst-case latency of a growing map. The stop-the-world map rehashes
+// every entry on that insert; the incremental map only allocates the new
+// table and moves a bounded number of entries
+fn bench_resize_pause(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resize_pause");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(10));
+
+    let capacity = 1 << 18;
+    // Both maps grow on the insert that finds the table at 70% load
+    let entries = (capacity as f64 * 0.7).ceil() as u64;
+
+    group.bench_function("stop_the_world", |b| {
+        b.iter_batched(
+            || {
+                let mut map = open_addressing::HashMap::with_seed(capacity, 0);
+                for i in 0..entries {
+                    map.insert(i, i).unwrap();
+                }
+                map
+            },
+            |mut map| {
+                map.insert(entries, entries).unwrap();
+                map
+            },
+            BatchSize::PerIteration,
+        );
+    });
+    group.bench_function("incremental", |b| {
+        b.iter_batched(
+            || {
+                let mut map = IncrementalMap::with_seed(capacity, 0);
+                for i in 0..entries {
+                    map.insert(i, i).unwrap();
+                }
+                map
+            },
+            |mut map| {
+                map.insert(entries, entries).unwrap();
+                map
+            },
+            BatchSize::PerIteration,
+        );
+    });
+
+    group.finish();
+}
+
+// Hashes a u64 to itself, the kind of weak hash that leaves every pattern in
+// the keys' low bits for the table to deal with
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 << 8) | byte as u64;
+        }
+    }
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}
+
+// Sequential keys under the identity hash, for both capacity policies. Plain
+// sequential ids land in distinct slots either way; ids spaced 64 apart, like
+// aligned addresses, share their low bits, so a power-of-two table folds them
+// onto a fraction of its slots while a prime one spreads them out
+fn bench_capacity_policy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("capacity_policy");
+    group.measurement_time(Duration::from_secs(10));
+
+    let size = 20_000u64;
+    for (stride, pattern) in [(1, "sequential"), (64, "sequential_stride_64")] {
+        let keys: Vec<u64> = (0..size).map(|i| i * stride).collect();
+        for policy in [CapacityPolicy::PowerOfTwo, CapacityPolicy::Prime] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", policy), pattern),
+                &keys,
+                |b, keys| {
+                    b.iter(|| {
+                        let mut map = open_addressing::HashMap::with_hasher(
+                            16,
+                            BuildHasherDefault::<IdentityHasher>::default(),
+                        );
+                        map.set_capacity_policy(policy);
+                        for &key in keys {
+                            map.insert(key, key).unwrap();
+                        }
+                        keys.iter().filter(|key| map.contains_key(key)).count()
+                    });
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+// Lookups where nine in ten keys are absent, on a table at its load factor
+// limit so misses walk long probe runs. The Bloom filter turns most misses
+// away before they reach the table
+fn bench_bloom_misses(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bloom_misses");
+    group.measurement_time(Duration::from_secs(10));
+
+    let size = 45_000u64;
+    // every tenth lookup is for a key in the map
+    let lookups: Vec<u64> = (0..size)
+        .map(|i| if i % 10 == 0 { i } else { size + i })
+        .collect();
+
+    let mut plain = open_addressing::HashMap::with_seed(1 << 16, 0);
+    let mut bloom = BloomMap::with_seed(1 << 16, 0);
+    for i in 0..size {
+        plain.insert(i, i).unwrap();
+        bloom.insert(i, i).unwrap();
+    }
+
+    group.bench_function("open_addressing", |b| {
+        b.iter(|| lookups.iter().filter(|key| plain.contains_key(key)).count());
+    });
+    group.bench_function("bloom_map", |b| {
+        b.iter(|| lookups.iter().filter(|key| bloom.contains_key(key)).count());
+    });
+
+    group.finish();
+}
+
+// Lookup throughput after a growing number of churn rounds, each of which
+// deletes a tenth of the keys and inserts as many fresh ones. The live count
+// stays fixed while tombstones pile up, so any slowdown across rounds is the
+// cost of probing past them before the table cleans itself up
+fn bench_tombstone_churn<M: HashMapBehavior<u64, u64>>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group("tombstone_churn");
+    group.measurement_time(Duration::from_secs(10));
+
+    let size = 10_000u64;
+    let per_round = size / 10;
+    for rounds in [0, 5, 20, 80] {
+        let mut map = M::new(1 << 15);
+        for key in 0..size {
+            map.insert(key, key).unwrap();
+        }
+        // keys live in a sliding window that moves up by `per_round` each round
+        for round in 0..rounds {
+            for key in round * per_round..(round + 1) * per_round {
+                map.delete(key).unwrap();
+                map.insert(key + size, key).unwrap();
+            }
+        }
+        let live = rounds * per_round..rounds * per_round + size;
+        // as many misses as hits, for keys that were never inserted
+        let lookups: Vec<u64> = live.clone().chain(live.map(|key| key + 2 * size)).collect();
+
+        group.throughput(Throughput::Elements(lookups.len() as u64));
+        group.bench_with_input(BenchmarkId::new(name, rounds), &lookups, |b, lookups| {
+            b.iter(|| {
+                lookups
+                    .iter()
+                    .filter(|&&key| map.get(key).is_some())
+                    .count()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+// Filling an empty map with 100k pairs through `extend`, which reserves once
+// from the size hint, against inserting them one at a time and growing as
+// the table fills
+fn bench_extend<M: HashMapBehavior<u64, u64> + Extend<(u64, u64)>>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group("extend");
+    group.measurement_time(Duration::from_secs(10));
+
+    let size = 100_000u64;
+    group.bench_function(BenchmarkId::new(name, "extend"), |b| {
+        b.iter(|| {
+            let mut map = M::new(16);
+            map.extend((0..size).map(|i| (i, i)));
+            map.len()
+        });
+    });
+    group.bench_function(BenchmarkId::new(name, "insert_loop"), |b| {
+        b.iter(|| {
+            let mut map = M::new(16);
+            for i in 0..size {
+                map.insert(i, i).unwrap();
+            }
+            map.len()
+        });
+    });
+
+    group.finish();
+}
+
+// The read-heavy mix over 1KiB values, reading through `get`, which clones
+// every value it returns, and through `get_ref`, which borrows it. `get` also
+// takes its key by value, so it pays for a key clone as well, which is what
+// a caller holding a borrowed key would pay too
+fn bench_read_path<M: HashMapBehavior<String, String>>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group("read_path");
+    group.measurement_time(Duration::from_secs(10));
+
+    let key_space = 1000;
+    let value = "v".repeat(1024);
+    let keys: Vec<String> = (0..key_space).map(|i| format!("key_{}", i)).collect();
+    let mut map = M::new(key_space);
+    for key in &keys {
+        map.insert(key.clone(), value.clone()).unwrap();
+    }
+    // nine in ten operations read; the rest overwrite a value in place, so
+    // the map looks the same on every iteration
+    let mut rng = StdRng::seed_from_u64(0);
+    let ops: Vec<(bool, usize)> = (0..10_000)
+        .map(|_| (rng.gen::<u8>() % 100 < 90, rng.gen::<usize>() % key_space))
+        .collect();
+
+    group.bench_function(BenchmarkId::new(name, "get"), |b| {
+        b.iter(|| {
+            let mut read = 0;
+            for &(is_read, key_idx) in &ops {
+                if is_read {
+                    read += map.get(keys[key_idx].clone()).map_or(0, |v| v.len());
+                } else {
+                    map.insert(keys[key_idx].clone(), value.clone()).unwrap();
+                }
+            }
+            read
+        });
+    });
+    group.bench_function(BenchmarkId::new(name, "get_ref"), |b| {
+        b.iter(|| {
+            let mut read = 0;
+            for &(is_read, key_idx) in &ops {
+                if is_read {
+                    read += map.get_ref(&keys[key_idx]).map_or(0, |v| v.len());
+                } else {
+                    map.insert(keys[key_idx].clone(), value.clone()).unwrap();
+                }
+            }
+            read
+        });
+    });
+
+    group.finish();
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
+    // Run benchmarks for the standard library baseline
+    bench_load_factor::<std::collections::HashMap<_, _>>(c);
+    bench_key_distribution::<std::collections::HashMap<_, _>>(c);
+    bench_operation_mix::<std::collections::HashMap<_, _>>(c);
+    bench_churn::<std::collections::HashMap<_, _>>(c);
+
     // Run benchmarks for chained implementation
-    bench_load_factor::<chaining::HashMap<_, _>>(c);
-    bench_key_distribution::<chaining::HashMap<_, _>>(c);
-    bench_operation_mix::<chaining::HashMap<_, _>>(c);
+    bench_load_factor::<chaining::HashMap<_, _, FixedSeedState>>(c);
+    bench_key_distribution::<chaining::HashMap<_, _, FixedSeedState>>(c);
+    bench_operation_mix::<chaining::HashMap<_, _, FixedSeedState>>(c);
+    bench_churn::<chaining::HashMap<_, _, FixedSeedState>>(c);
+    bench_chaining_buckets(c);
+    bench_move_to_front(c);
+    bench_chaining_resize(c);
+    bench_compact_probe(c);
+    bench_compact_operation_mix(c);
 
     // Run benchmarks for open addressing implementation
-    bench_load_factor::<open_addressing::HashMap<_, _>>(c);
-    bench_key_distribution::<open_addressing::HashMap<_, _>>(c);
-    bench_operation_mix::<open_addressing::HashMap<_, _>>(c);
+    bench_load_factor::<open_addressing::HashMap<_, _, FixedSeedState>>(c);
+    bench_key_distribution::<open_addressing::HashMap<_, _, FixedSeedState>>(c);
+    bench_operation_mix::<open_addressing::HashMap<_, _, FixedSeedState>>(c);
+    bench_churn::<open_addressing::HashMap<_, _, FixedSeedState>>(c);
+    bench_resize_pause(c);
+    bench_capacity_policy(c);
+    bench_bloom_misses(c);
+    bench_tombstone_churn::<open_addressing::HashMap<_, _, FixedSeedState>>(c, "open_addressing");
+    bench_tombstone_churn::<open_addressing_compact::HashMap<_, _, FixedSeedState>>(
+        c,
+        "open_addressing_compact",
+    );
+    bench_extend::<chaining::HashMap<_, _, FixedSeedState>>(c, "chaining");
+    bench_extend::<open_addressing::HashMap<_, _, FixedSeedState>>(c, "open_addressing");
+    bench_extend::<open_addressing_compact::HashMap<_, _, FixedSeedState>>(
+        c,
+        "open_addressing_compact",
+    );
+    bench_read_path::<chaining::HashMap<_, _, FixedSeedState>>(c, "chaining");
+    bench_read_path::<open_addressing::HashMap<_, _, FixedSeedState>>(c, "open_addressing");
+    bench_read_path::<open_addressing_compact::HashMap<_, _, FixedSeedState>>(
+        c,
+        "open_addressing_compact",
+    );
 }
 
 criterion_group!(
@@ -0,0 +1,26 @@
+#!/bin/bash
+# Checks that the maps still build, lint and pass their tests without the
+# std feature. Exits non-zero on the first failure
+set -e
+
+echo "Building the library without std..."
+cargo build --lib --no-default-features
+
+echo "Linting the library without std..."
+cargo clippy --lib --no-default-features -- -D warnings
+
+echo "Testing the library without std..."
+cargo test --lib --no-default-features
+
+# A host build still links std for the dependencies, so a bare-metal target
+# is the only way to catch a dependency that pulls it in. Add it with
+# `rustup target add thumbv7em-none-eabihf`
+TARGET=thumbv7em-none-eabihf
+if rustup target list --installed 2>/dev/null | grep -qx "$TARGET"; then
+    echo "Building the library for $TARGET..."
+    cargo build --lib --no-default-features --target "$TARGET"
+else
+    echo "Skipping the $TARGET build, the target is not installed"
+fi
+
+echo "no_std checks passed"
@@ -0,0 +1,49 @@
+//  Decodes the input into operations on one of the maps and checks its
+//  invariants after every step. The first byte picks the map, then every
+//  three bytes are an operation, a key and a value. Keys are folded into a
+//  small range so inputs reach collisions, tombstones and resizes quickly.
+//  The maps use a fixed seed so an input always produces the same layout
+#![no_main]
+
+use std::collections::HashMap as StdHashMap;
+
+use hashmap::{chaining, open_addressing, open_addressing_compact};
+use libfuzzer_sys::fuzz_target;
+
+const KEY_SPACE: u8 = 64;
+
+macro_rules! run_ops {
+    ($map:expr, $ops:expr) => {{
+        let mut map = $map;
+        let mut reference = StdHashMap::new();
+        for op in $ops.chunks_exact(3) {
+            let (key, value) = (u32::from(op[1] % KEY_SPACE), u32::from(op[2]));
+            match op[0] % 3 {
+                0 => {
+                    map.insert(key, value).unwrap();
+                    reference.insert(key, value);
+                }
+                1 => assert_eq!(map.get(key), reference.get(&key).copied()),
+                _ => {
+                    map.delete(key).unwrap();
+                    reference.remove(&key);
+                }
+            }
+            if let Err(err) = map.check_invariants() {
+                panic!("invariant broken: {err}");
+            }
+            assert_eq!(map.len(), reference.len());
+        }
+    }};
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&which, ops)) = data.split_first() else {
+        return;
+    };
+    match which % 3 {
+        0 => run_ops!(chaining::HashMap::with_seed(16, 0), ops),
+        1 => run_ops!(open_addressing::HashMap::with_seed(16, 0), ops),
+        _ => run_ops!(open_addressing_compact::HashMap::with_seed(16, 0), ops),
+    }
+});
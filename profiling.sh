@@ -2,9 +2,9 @@
 
 if [ "$#" -lt 1 ]; then
     echo "Usage: $0 <workload> [pattern]"
-    echo "Workloads: load_factor, key_distribution, operation_mix"
-    echo "Patterns for key_distribution: uniform, clustered, sequential"
-    echo "Patterns for operation_mix: read_heavy, write_heavy, balanced, typical_web"
+    echo "Workloads: load_factor, key_distribution, operation_mix, churn"
+    echo "Patterns for key_distribution: uniform, clustered, sequential, zipfian, gaussian"
+    echo "Patterns for operation_mix: read_heavy, write_heavy, balanced, typical_web, update_heavy"
     exit 1
 fi
 
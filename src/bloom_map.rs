@@ -0,0 +1,191 @@
+//  An open addressing map with a counting Bloom filter in front of it. A
+//  lookup for a key the filter has never seen returns straight away instead
+//  of walking a probe run to its end, which pays off when most lookups miss.
+//  The filter costs a byte per counter and a few extra writes per insert and
+//  delete, so it lives in its own type rather than in every map
+use alloc::{vec, vec::Vec};
+use core::hash::BuildHasher;
+
+use crate::open_addressing::{self, Key, Value};
+use crate::{FixedSeedState, HashMapError, RandomState};
+
+//  Counters kept per key the filter is sized for, and how many of them each
+//  key bumps. Ten and four give about a 1% false positive rate at full size
+const COUNTERS_PER_ENTRY: usize = 10;
+const HASHES_PER_KEY: u64 = 4;
+
+//  A Bloom filter with a counter in place of each bit, so keys can be
+//  removed again. A counter that saturates is never decremented, which can
+//  only leave extra positives behind, never a false negative
+struct CountingBloomFilter {
+    counters: Vec<u8>,
+}
+
+impl CountingBloomFilter {
+    fn new(entries: usize) -> Self {
+        Self {
+            counters: vec![0; entries.max(1) * COUNTERS_PER_ENTRY],
+        }
+    }
+
+    //  Derives every counter index from one 64-bit hash by double hashing
+    fn indices(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let len = self.counters.len() as u64;
+        let h1 = hash & 0xffff_ffff;
+        let h2 = (hash >> 32) | 1;
+        (0..HASHES_PER_KEY).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    fn add(&mut self, hash: u64) {
+        for index in self.indices(hash) {
+            self.counters[index] = self.counters[index].saturating_add(1);
+        }
+    }
+
+    fn remove(&mut self, hash: u64) {
+        for index in self.indices(hash) {
+            if self.counters[index] != u8::MAX {
+                self.counters[index] -= 1;
+            }
+        }
+    }
+
+    fn may_contain(&self, hash: u64) -> bool {
+        self.indices(hash).all(|index| self.counters[index] > 0)
+    }
+}
+
+pub struct BloomMap<K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: open_addressing::HashMap<K, V, S>,
+    filter: CountingBloomFilter,
+    //  number of keys the filter is sized for; past it the filter is rebuilt
+    //  twice as large so the false positive rate stays put
+    filter_entries: usize,
+}
+
+impl<K, V> BloomMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V> BloomMap<K, V, FixedSeedState>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn with_seed(capacity: usize, seed: u64) -> Self {
+        Self::with_hasher(capacity, FixedSeedState::new(seed))
+    }
+}
+
+impl<K, V, S> BloomMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        let filter_entries = capacity.max(16);
+        Self {
+            map: open_addressing::HashMap::with_hasher(capacity, hash_builder),
+            filter: CountingBloomFilter::new(filter_entries),
+            filter_entries,
+        }
+    }
+
+    fn filter_hash(&self, key: &K) -> u64 {
+        self.map.hasher().hash_one(key)
+    }
+
+    fn rebuild_filter(&mut self) {
+        self.filter_entries *= 2;
+        let mut filter = CountingBloomFilter::new(self.filter_entries);
+        for (key, _) in self.map.iter() {
+            filter.add(self.map.hasher().hash_one(key));
+        }
+        self.filter = filter;
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        let hash = self.filter_hash(&key);
+        let is_new = !self.contains_key(&key);
+        self.map.insert(key, value)?;
+        if is_new {
+            self.filter.add(hash);
+            if self.map.len() > self.filter_entries {
+                self.rebuild_filter();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: K) -> Option<V> {
+        self.get_ref(&key).cloned()
+    }
+
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        if !self.filter.may_contain(self.filter_hash(key)) {
+            return None;
+        }
+        self.map.get_ref(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_ref(key).is_some()
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        if !self.contains_key(&key) {
+            return Ok(());
+        }
+        let hash = self.filter_hash(&key);
+        self.map.delete(key)?;
+        self.filter.remove(hash);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_has_no_false_negatives() {
+        let mut map = BloomMap::with_seed(16, 0);
+        for i in 0..10_000 {
+            map.insert(i, i * 2).unwrap();
+        }
+        for i in (0..10_000).step_by(3) {
+            map.delete(i).unwrap();
+        }
+        //  every key still in the map passes the filter, through several rebuilds
+        for i in 0..10_000 {
+            let expected = (i % 3 != 0).then_some(i * 2);
+            assert_eq!(map.get(i), expected);
+        }
+        assert_eq!(map.len(), 10_000 - 3_334);
+
+        //  and most absent keys are turned away without a probe
+        let passed = (10_000..20_000)
+            .filter(|key| map.filter.may_contain(map.filter_hash(key)))
+            .count();
+        assert!(passed < 500, "{passed} of 10000 misses passed the filter");
+    }
+}
@@ -0,0 +1,105 @@
+//  A map with a hard cap on its size. Once it holds `max_entries` keys, a
+//  new key is rejected with `HashMapError::Full` instead of evicting
+//  anything, while keys already present can still be updated
+use crate::open_addressing::{self, Key, Value};
+use crate::HashMapError;
+
+pub struct BoundedMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    map: open_addressing::HashMap<K, V>,
+    max_entries: usize,
+}
+
+impl<K, V> BoundedMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            map: open_addressing::HashMap::new(max_entries),
+            max_entries,
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        if self.map.len() >= self.max_entries && !self.map.contains_key(&key) {
+            return Err(HashMapError::Full);
+        }
+        self.map.insert(key, value)
+    }
+
+    pub fn get(&self, key: K) -> Option<V> {
+        self.map.get(key)
+    }
+
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        self.map.get_ref(key)
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.map.get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        self.map.delete(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.map.len() >= self.max_entries
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_new_keys_when_full() {
+        let mut map = BoundedMap::new(3);
+        for i in 0..3 {
+            map.insert(i, i).unwrap();
+        }
+        assert!(map.is_full());
+        assert!(matches!(map.insert(3, 3), Err(HashMapError::Full)));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(3), None);
+
+        //  deleting makes room again
+        map.delete(0).unwrap();
+        map.insert(3, 3).unwrap();
+        assert_eq!(map.get(3), Some(3));
+        assert!(matches!(map.insert(0, 0), Err(HashMapError::Full)));
+    }
+
+    #[test]
+    fn test_allows_updates_when_full() {
+        let mut map = BoundedMap::new(2);
+        map.insert("a".to_string(), 1).unwrap();
+        map.insert("b".to_string(), 2).unwrap();
+        map.insert("a".to_string(), 10).unwrap();
+        *map.get_mut("b".to_string()).unwrap() += 1;
+        assert_eq!(map.get("a".to_string()), Some(10));
+        assert_eq!(map.get("b".to_string()), Some(3));
+        assert_eq!(map.len(), 2);
+    }
+}
@@ -0,0 +1,135 @@
+//  Configures any of the three maps in one call, rather than adding a
+//  constructor for every combination of tuning knobs. Settings left alone
+//  keep the map's own defaults
+//
+//      let map: open_addressing::HashMap<String, u32> = HashMapBuilder::new()
+//          .capacity(1_000)
+//          .load_factor_limit(0.5)
+//          .build();
+use crate::{CapacityPolicy, RandomState};
+
+pub struct HashMapBuilder<S = RandomState> {
+    pub(crate) capacity: usize,
+    pub(crate) load_factor_limit: Option<f64>,
+    pub(crate) growth_factor: Option<f64>,
+    pub(crate) capacity_policy: CapacityPolicy,
+    pub(crate) hash_builder: S,
+}
+
+//  Implemented by each map so `build` can produce it
+pub trait FromBuilder<S> {
+    fn from_builder(builder: HashMapBuilder<S>) -> Self;
+}
+
+impl HashMapBuilder {
+    pub fn new() -> Self {
+        Self {
+            capacity: 0,
+            load_factor_limit: None,
+            growth_factor: None,
+            capacity_policy: CapacityPolicy::default(),
+            hash_builder: RandomState::new(),
+        }
+    }
+}
+
+impl Default for HashMapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> HashMapBuilder<S> {
+    //  Initial number of slots, rounded the same way as `new`
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    //  Load factor at which the table grows. Must be below 1 so an open
+    //  addressing table always keeps a free slot
+    pub fn load_factor_limit(mut self, limit: f64) -> Self {
+        assert!(
+            limit > 0.0 && limit < 1.0,
+            "the load factor limit must be between 0 and 1"
+        );
+        self.load_factor_limit = Some(limit);
+        self
+    }
+
+    pub fn growth_factor(mut self, factor: f64) -> Self {
+        self.growth_factor = Some(factor);
+        self
+    }
+
+    pub fn capacity_policy(mut self, policy: CapacityPolicy) -> Self {
+        self.capacity_policy = policy;
+        self
+    }
+
+    pub fn hasher<T>(self, hash_builder: T) -> HashMapBuilder<T> {
+        HashMapBuilder {
+            capacity: self.capacity,
+            load_factor_limit: self.load_factor_limit,
+            growth_factor: self.growth_factor,
+            capacity_policy: self.capacity_policy,
+            hash_builder,
+        }
+    }
+
+    pub fn build<M: FromBuilder<S>>(self) -> M {
+        M::from_builder(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chaining, open_addressing, open_addressing_compact, FixedSeedState};
+    use core::hash::BuildHasher;
+
+    fn configured() -> HashMapBuilder<FixedSeedState> {
+        HashMapBuilder::new()
+            .capacity(100)
+            .load_factor_limit(0.5)
+            .growth_factor(1.5)
+            .capacity_policy(CapacityPolicy::Prime)
+            .hasher(FixedSeedState::new(7))
+    }
+
+    #[test]
+    fn test_builder_settings_take_effect() {
+        let mut chaining: chaining::HashMap<u32, u32, FixedSeedState> = configured().build();
+        let mut open: open_addressing::HashMap<u32, u32, FixedSeedState> = configured().build();
+        let mut compact: open_addressing_compact::HashMap<u32, u32, FixedSeedState> =
+            configured().build();
+
+        //  128 slots, then rounded up to the next listed prime
+        assert_eq!(chaining.capacity(), 163);
+        assert_eq!(open.capacity(), 163);
+        assert_eq!(compact.capacity(), 163);
+        assert_eq!(open.load_factor_limit(), 0.5);
+        assert_eq!(compact.growth_factor(), 1.5);
+        assert_eq!(chaining.capacity_policy(), CapacityPolicy::Prime);
+        assert_eq!(
+            open.hasher().hash_one(1u32),
+            FixedSeedState::new(7).hash_one(1u32)
+        );
+
+        //  the table grows once half of it is in use
+        for i in 0..82 {
+            chaining.insert(i, i).unwrap();
+            open.insert(i, i).unwrap();
+            compact.insert(i, i).unwrap();
+        }
+        assert_eq!(open.resize_count(), 0);
+        chaining.insert(82, 82).unwrap();
+        open.insert(82, 82).unwrap();
+        compact.insert(82, 82).unwrap();
+        assert_eq!(chaining.resize_count(), 1);
+        assert_eq!(open.resize_count(), 1);
+        assert_eq!(compact.resize_count(), 1);
+        //  163 * 1.5 = 244, rounded up to the next listed prime
+        assert_eq!(compact.capacity(), 331);
+    }
+}
@@ -0,0 +1,77 @@
+//  How a map sizes its table. Every map reduces a hash with a true modulo,
+//  so any capacity works; a prime one mixes the high bits of the hash into
+//  the slot index, which helps when a weak hash leaves patterns in the low bits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CapacityPolicy {
+    //  Start at a power of two and grow by the growth factor, which keeps the
+    //  capacity a power of two at the default factor of 2
+    #[default]
+    PowerOfTwo,
+    //  Round every capacity up to the next prime in PRIMES
+    Prime,
+}
+
+//  Smallest table any map allocates
+pub(crate) const MIN_CAPACITY: usize = 16;
+
+//  Each prime is the first one above double the last, so stepping through
+//  the list grows a table by about the default growth factor. All of them fit
+//  in a 32-bit usize
+const PRIMES: [usize; 28] = [
+    17, 37, 79, 163, 331, 673, 1361, 2729, 5471, 10949, 21911, 43853, 87719, 175447, 350899,
+    701819, 1403641, 2807303, 5614657, 11229331, 22458671, 44917381, 89834777, 179669557,
+    359339171, 718678369, 1437356741, 2874713497,
+];
+
+impl CapacityPolicy {
+    //  The capacity to allocate for a table that needs at least `capacity`
+    //  slots. Past the end of the list a prime table keeps the requested size
+    pub(crate) fn fit(self, capacity: usize) -> usize {
+        match self {
+            CapacityPolicy::PowerOfTwo => capacity,
+            CapacityPolicy::Prime => PRIMES
+                .iter()
+                .copied()
+                .find(|&prime| prime >= capacity)
+                .unwrap_or(capacity),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_listed_prime(capacity: usize) -> bool {
+        PRIMES.contains(&capacity)
+    }
+}
+
+//  The smallest power of two, at least MIN_CAPACITY, that holds `entries`
+//  below `load_factor`, or None if it would overflow
+pub(crate) fn capacity_for(entries: usize, load_factor: f64) -> Option<usize> {
+    let mut capacity = MIN_CAPACITY;
+    while entries as f64 / capacity as f64 >= load_factor {
+        capacity = capacity.checked_mul(2)?;
+    }
+    Some(capacity)
+}
+
+//  `capacity_for` behind each map's public `capacity_for`, which panics on a
+//  load factor that is not positive or a capacity that overflows
+pub(crate) fn capacity_for_entries(entries: usize, load_factor: f64) -> usize {
+    assert!(load_factor > 0.0, "the load factor must be positive");
+    capacity_for(entries, load_factor).expect("capacity overflow")
+}
+
+//  The table size a map starts with when asked for `requested` slots of
+//  `slot_size` bytes: the next power of two, but never below MIN_CAPACITY.
+//  The request is only a hint, so one whose table could never be allocated,
+//  because rounding it up overflows or the table would pass isize::MAX bytes,
+//  starts the map at the floor instead of panicking
+pub(crate) fn initial_capacity(requested: usize, slot_size: usize) -> usize {
+    requested
+        .checked_next_power_of_two()
+        .filter(|&capacity| {
+            capacity
+                .checked_mul(slot_size)
+                .is_some_and(|bytes| bytes <= isize::MAX as usize)
+        })
+        .map_or(MIN_CAPACITY, |capacity| capacity.max(MIN_CAPACITY))
+}
@@ -1,209 +1,1260 @@
 #![allow(dead_code)]
-use std::hash::{Hash, Hasher};
-use std::{fmt::Display, hash::DefaultHasher};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt::{Debug, Display};
+use core::hash::{BuildHasher, Hash};
+use core::ops::AddAssign;
 
-use anyhow::Ok;
+use crate::builder::{FromBuilder, HashMapBuilder};
+use crate::snapshot::Snapshot;
+use crate::{
+    CapacityPolicy, FixedSeedState, HashMapError, OccupiedError, RandomState, TryReserveError,
+};
 
-pub trait Key: Hash + Clone + PartialEq + Display {}
-impl<T> Key for T where T: Hash + Clone + PartialEq + Display {}
+pub trait Key: Hash + Clone + PartialEq + Debug {}
+impl<T> Key for T where T: Hash + Clone + PartialEq + Debug {}
 
 pub trait Value: Clone {}
 impl<T> Value for T where T: Clone {}
 
 const LOAD_FACTOR_LIMIT: f64 = 0.7;
+const DEFAULT_GROWTH_FACTOR: f64 = 2.0;
+//  With treeify enabled, buckets holding more pairs than this are kept
+//  sorted by key
+const TREEIFY_THRESHOLD: usize = 8;
 
-#[derive(Clone)]
-struct Node<K, V>
+//  How a sorted bucket orders its keys. A plain fn pointer, so only
+//  `set_treeify` needs `K: Ord` and every other method works without it
+type KeyOrder<K> = fn(&K, &K) -> Ordering;
+
+//  Collisions are stored contiguously in a vec. Chains are scanned in
+//  insertion order; with treeify enabled, a chain that grows past
+//  TREEIFY_THRESHOLD is kept sorted by key so lookups are a binary search
+enum Bucket<K, V>
 where
     K: Key,
     V: Value,
 {
-    key: K,
-    value: V,
-    next: Option<Box<Node<K, V>>>,
+    Chain(Vec<(K, V)>),
+    Sorted(Vec<(K, V)>, KeyOrder<K>),
 }
 
-impl<K, V> Node<K, V>
+impl<K, V> Clone for Bucket<K, V>
 where
     K: Key,
     V: Value,
 {
-    fn new(key: K, value: V) -> Self {
-        Self {
-            key,
-            value,
-            next: None,
+    fn clone(&self) -> Self {
+        match self {
+            Bucket::Chain(pairs) => Bucket::Chain(pairs.clone()),
+            Bucket::Sorted(pairs, order) => Bucket::Sorted(pairs.clone(), *order),
         }
     }
-}
 
-#[derive(Clone)]
-struct LinkedList<K, V>
-where
-    K: Key,
-    V: Value,
-{
-    head: Option<Box<Node<K, V>>>,
+    //  Keeps this bucket's pair storage whichever kind of bucket `source` is
+    fn clone_from(&mut self, source: &Self) {
+        let mut pairs = core::mem::take(self.pairs_mut());
+        pairs.clone_from(source.pairs());
+        *self = match source {
+            Bucket::Chain(_) => Bucket::Chain(pairs),
+            Bucket::Sorted(_, order) => Bucket::Sorted(pairs, *order),
+        };
+    }
 }
 
-impl<K, V> LinkedList<K, V>
+impl<K, V> Bucket<K, V>
 where
     K: Key,
     V: Value,
 {
     fn new() -> Self {
-        Self { head: None }
+        Bucket::Chain(Vec::new())
+    }
+
+    fn is_sorted(&self) -> bool {
+        matches!(self, Bucket::Sorted(..))
+    }
+
+    fn pairs(&self) -> &Vec<(K, V)> {
+        match self {
+            Bucket::Chain(pairs) | Bucket::Sorted(pairs, _) => pairs,
+        }
+    }
+
+    fn pairs_mut(&mut self) -> &mut Vec<(K, V)> {
+        match self {
+            Bucket::Chain(pairs) | Bucket::Sorted(pairs, _) => pairs,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.pairs().len()
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        match self {
+            Bucket::Chain(pairs) => pairs.iter().position(|(k, _)| k == key),
+            Bucket::Sorted(pairs, order) => pairs.binary_search_by(|(k, _)| order(k, key)).ok(),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        self.find(key).map(|i| &self.pairs()[i].1)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.find(key).map(|i| &mut self.pairs_mut()[i].1)
+    }
+
+    fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
+        self.find(key).map(|i| {
+            let (k, v) = &self.pairs()[i];
+            (k, v)
+        })
     }
 
-    fn get(&self, key: K) -> anyhow::Result<Option<V>> {
-        let mut current = &self.head;
-        while let Some(node) = current {
-            if node.key == key {
-                return Ok(Some(node.value.clone()));
+    fn get_mut_promoted(&mut self, key: &K) -> Option<&mut V> {
+        let i = self.find(key)?;
+        match self {
+            Bucket::Chain(pairs) => {
+                pairs[..=i].rotate_right(1);
+                Some(&mut pairs[0].1)
             }
-            current = &node.next;
+            //  sorted buckets have a fixed order
+            Bucket::Sorted(pairs, _) => Some(&mut pairs[i].1),
         }
-        return Ok(None);
     }
 
-    //  Does an insert on the LinkedList and returns false if not a new insert
+    //  Does an insert on the bucket and returns false if not a new insert
     //  Returns true otherwise
-    fn insert(&mut self, key: K, value: V) -> anyhow::Result<bool> {
-        let mut current = &mut self.head;
-        while let Some(node) = current {
-            if node.key == key {
-                node.value = value;
-                return Ok(false);
+    fn insert(&mut self, key: K, value: V, treeify: Option<KeyOrder<K>>) -> bool {
+        match self.get_mut(&key) {
+            Some(existing) => {
+                *existing = value;
+                false
+            }
+            None => {
+                self.push(key, value, treeify);
+                true
             }
-            current = &mut node.next;
         }
-        *current = Some(Box::new(Node::new(key, value)));
-        Ok(true)
     }
 
-    fn delete(&mut self, key: K) -> anyhow::Result<()> {
-        let mut current = &mut self.head;
-        while let Some(node) = current.take() {
-            if node.key == key {
-                *current = node.next;
-                return Ok(());
+    //  Adds a key known to be absent and returns its value. A full chain is
+    //  sorted first when the map has treeify enabled
+    fn push(&mut self, key: K, value: V, treeify: Option<KeyOrder<K>>) -> &mut V {
+        if let Some(order) = treeify {
+            if matches!(self, Bucket::Chain(pairs) if pairs.len() >= TREEIFY_THRESHOLD) {
+                self.treeify(order);
             }
-            *current = Some(node);
-            current = &mut current.as_mut().unwrap().next;
         }
-        Ok(())
+        match self {
+            Bucket::Chain(pairs) => {
+                pairs.push((key, value));
+                &mut pairs.last_mut().unwrap().1
+            }
+            Bucket::Sorted(pairs, order) => {
+                let i = pairs.binary_search_by(|(k, _)| order(k, &key)).unwrap_err();
+                pairs.insert(i, (key, value));
+                &mut pairs[i].1
+            }
+        }
+    }
+
+    //  Unlinks the pair holding `key` and hands back its contents
+    fn take(&mut self, key: &K) -> Option<(K, V)> {
+        let i = self.find(key)?;
+        Some(self.pairs_mut().remove(i))
+    }
+
+    //  Does a delete on the bucket and returns true if a pair was removed
+    //  Returns false otherwise
+    fn delete(&mut self, key: K) -> bool {
+        self.take(&key).is_some()
+    }
+
+    //  Removes the pairs matching `pred` one at a time as the iterator is
+    //  driven. If `pred` panics, every pair not yet handed out stays put
+    fn extract_if<'a, F>(&'a mut self, mut pred: F) -> impl Iterator<Item = (K, V)> + 'a
+    where
+        F: FnMut(&K, &V) -> bool + 'a,
+    {
+        self.pairs_mut().extract_if(.., move |(k, v)| pred(k, v))
+    }
+
+    fn into_vec(self) -> Vec<(K, V)> {
+        match self {
+            Bucket::Chain(pairs) | Bucket::Sorted(pairs, _) => pairs,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.pairs().iter().map(|(k, v)| (k, v))
     }
 
-    fn iter(&self) -> LinkedListIterator<K, V> {
-        LinkedListIterator {
-            current: self.head.as_deref(),
+    fn treeify(&mut self, order: KeyOrder<K>) {
+        if let Bucket::Chain(pairs) = self {
+            let mut pairs = core::mem::take(pairs);
+            pairs.sort_by(|a, b| order(&a.0, &b.0));
+            *self = Bucket::Sorted(pairs, order);
         }
     }
 }
 
-struct LinkedListIterator<'a, K, V>
+//  Called with the old and new capacity each time the table grows
+type ResizeCallback = Box<dyn FnMut(usize, usize) + Send + Sync>;
+
+pub struct HashMap<K, V, S = RandomState>
 where
     K: Key,
     V: Value,
 {
-    current: Option<&'a Node<K, V>>,
+    buckets: Vec<Bucket<K, V>>,
+    size: usize,
+    capacity: usize,
+    resizes: usize,
+    growth_factor: f64,
+    load_factor_limit: f64,
+    capacity_policy: CapacityPolicy,
+    on_resize: Option<ResizeCallback>,
+    move_to_front: bool,
+    treeify: Option<KeyOrder<K>>,
+    hash_builder: S,
 }
 
-impl<'a, K, V> Iterator for LinkedListIterator<'a, K, V>
+impl<K, V> HashMap<K, V>
 where
     K: Key,
     V: Value,
 {
-    type Item = (K, V);
-    fn next(&mut self) -> Option<Self::Item> {
-        self.current.map(|node| {
-            self.current = node.next.as_deref();
-            (node.key.clone(), node.value.clone())
-        })
+    //  `capacity` is rounded up to a power of two of at least 16. Anything too
+    //  large to ever allocate, up to usize::MAX, starts the map at 16 instead
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    //  The power-of-two capacity that keeps `entries` pairs below `load_factor`,
+    //  for sizing a map up front with `new`
+    pub fn capacity_for(entries: usize, load_factor: f64) -> usize {
+        crate::capacity::capacity_for_entries(entries, load_factor)
+    }
+
+    //  The most bytes `memory_usage` can report for a map made with
+    //  `new(capacity_for(entries, ..))` at the default load factor limit once it
+    //  holds `entries` pairs. Bucket storage depends on how keys collide, so
+    //  unlike the open addressing maps there is no exact figure: this assumes
+    //  one pair per bucket, each in a Vec of the minimum four slots, and
+    //  collisions share a Vec and come in under it
+    pub fn memory_upper_bound(entries: usize) -> usize {
+        Self::capacity_for(entries, LOAD_FACTOR_LIMIT) * core::mem::size_of::<Bucket<K, V>>()
+            + entries * 4 * core::mem::size_of::<(K, V)>()
     }
 }
 
-pub struct HashMap<K, V>
+impl<K, V> HashMap<K, V, FixedSeedState>
 where
     K: Key,
     V: Value,
 {
-    buckets: Vec<LinkedList<K, V>>,
-    size: usize,
-    capacity: usize,
+    //  Same seed and same operations give the same layout on every run
+    pub fn with_seed(capacity: usize, seed: u64) -> Self {
+        Self::with_hasher(capacity, FixedSeedState::new(seed))
+    }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, S> HashMap<K, V, S>
 where
     K: Key,
     V: Value,
+    S: BuildHasher,
 {
-    pub fn new(capacity: usize) -> Self {
-        let initial_capacity = 16.max(capacity.next_power_of_two());
-        let buckets = vec![LinkedList::new(); initial_capacity];
+    //  Like `new`, but keys are hashed with `hash_builder`
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        let initial_capacity =
+            crate::capacity::initial_capacity(capacity, core::mem::size_of::<Bucket<K, V>>());
+        let buckets = vec![Bucket::new(); initial_capacity];
         Self {
             buckets,
             size: 0,
             capacity: initial_capacity,
+            resizes: 0,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            load_factor_limit: LOAD_FACTOR_LIMIT,
+            capacity_policy: CapacityPolicy::default(),
+            on_resize: None,
+            move_to_front: false,
+            treeify: None,
+            hash_builder,
         }
     }
 
     fn hash(&self, key: &K) -> usize {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        hasher.finish() as usize % self.capacity
+        self.hash_builder.hash_one(key) as usize % self.capacity
     }
 
-    pub fn get(&self, key: K) -> anyhow::Result<Option<V>> {
-        let index = self.hash(&key);
+    //  Never reorders a bucket, even with move-to-front enabled, since it only
+    //  borrows the map. Read through `get_promoted` to promote hot keys
+    pub fn get(&self, key: K) -> Option<V> {
+        self.get_ref(&key).cloned()
+    }
+
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        let index = self.hash(key);
+        self.buckets[index].get(key)
+    }
+
+    //  Like `get_ref`, but also hands back the key as stored in the map
+    pub fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
+        self.buckets[self.hash(key)].get_key_value(key)
+    }
+
+    //  Returns the copy of `key` already stored, or stores `key` under a
+    //  default value and returns a copy of it. With a key type that shares its
+    //  contents on clone, such as Arc<str>, every equal key handed in comes
+    //  back as the same allocation
+    pub fn intern(&mut self, key: K) -> Result<K, HashMapError>
+    where
+        V: Default,
+    {
+        if let Some((stored, _)) = self.get_key_value(&key) {
+            return Ok(stored.clone());
+        }
+        self.insert(key.clone(), V::default())?;
+        Ok(key)
+    }
+
+    pub fn get_many<'a>(&self, keys: impl IntoIterator<Item = &'a K>) -> Vec<Option<&V>>
+    where
+        K: 'a,
+    {
+        keys.into_iter().map(|key| self.get_ref(key)).collect()
+    }
+
+    //  The read path for move-to-front: like `get_ref`, but moves the pair to
+    //  the front of its bucket when promotion is enabled. Reordering a bucket
+    //  while other `&V` borrows from `get_ref` may be live would move the
+    //  values out from under them, which is why this takes `&mut self`
+    pub fn get_promoted(&mut self, key: &K) -> Option<&V> {
+        let index = self.hash(key);
+        if self.move_to_front {
+            return self.buckets[index]
+                .get_mut_promoted(key)
+                .map(|value| &*value);
+        }
         self.buckets[index].get(key)
     }
 
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        let index = self.hash(&key);
+        if self.move_to_front {
+            return self.buckets[index].get_mut_promoted(&key);
+        }
+        self.buckets[index].get_mut(&key)
+    }
+
+    //  When enabled, a successful `get_promoted` or `get_mut` moves the pair to
+    //  the front of its bucket so hot keys are found first. Off by default to
+    //  keep insertion order. `get` and `get_ref` take `&self` and so leave the
+    //  order alone; promoting on them would need interior mutability around
+    //  every bucket, and the `&V` they hand out would no longer be stable
+    pub fn set_move_to_front(&mut self, enabled: bool) {
+        self.move_to_front = enabled;
+    }
+
+    //  When enabled, a chain that grows past TREEIFY_THRESHOLD pairs is sorted
+    //  by key and kept sorted, so lookups in it are a binary search rather
+    //  than a scan. Off by default, which is why only this method asks for
+    //  `K: Ord`. After disabling it, sorted buckets stay sorted until the next
+    //  rehash turns them back into chains
+    pub fn set_treeify(&mut self, enabled: bool)
+    where
+        K: Ord,
+    {
+        self.treeify = enabled.then_some(K::cmp as KeyOrder<K>);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets.iter().flat_map(|bucket| bucket.iter())
+    }
+
+    //  Every bucket in index order, empty ones included, with the pairs it
+    //  holds in chain order. Shows the layout the hash produced
+    pub fn iter_buckets(&self) -> impl Iterator<Item = (usize, impl Iterator<Item = (&K, &V)>)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(index, bucket)| (index, bucket.iter()))
+    }
+
+    //  Whether any key maps to `value`. A linear scan over every entry, unlike
+    //  the single lookup behind `contains_key`
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.iter().any(|(_, v)| v == value)
+    }
+
+    //  Every entry ordered by key, for deterministic output in tests and dumps
+    pub fn iter_sorted(&self) -> Vec<(&K, &V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    //  A digest of the entries that ignores their order, so maps that compare
+    //  equal always share a fingerprint whatever their capacity or seed
+    pub fn fingerprint(&self) -> u64
+    where
+        V: Hash,
+    {
+        crate::hasher::fingerprint(self.iter())
+    }
+
+    //  Copies the current contents into a read-only map that stays as it is
+    //  while this one changes
+    pub fn snapshot(&self) -> Arc<Snapshot<K, V, S>>
+    where
+        S: Clone,
+    {
+        Arc::new(Snapshot::from_entries(
+            self.hash_builder.clone(),
+            self.len(),
+            self.iter(),
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_ref(key).is_some()
+    }
+
     fn get_load_factor(&self) -> f64 {
         self.size as f64 / self.capacity as f64
     }
 
-    fn resize(&mut self) -> anyhow::Result<()> {
-        let new_capacity = self.capacity * 2;
-        let new_buckets: Vec<LinkedList<K, V>> = vec![LinkedList::new(); new_capacity];
-        let old_buckets = std::mem::replace(&mut self.buckets, new_buckets);
-        self.capacity = new_capacity;
-        self.size = 0;
+    //  The smallest capacity that holds `entries` under the load factor limit
+    fn min_capacity(&self, entries: usize) -> usize {
+        self.checked_min_capacity(entries)
+            .expect("capacity overflow")
+    }
+
+    fn checked_min_capacity(&self, entries: usize) -> Option<usize> {
+        crate::capacity::capacity_for(entries, self.load_factor_limit)
+    }
+
+    //  The number of non-empty buckets, the longest chain, and the mean chain
+    //  length over the non-empty buckets. A mean near 1 means keys are spread
+    //  well; with treeify enabled, a longest chain past TREEIFY_THRESHOLD
+    //  means a bucket was sorted
+    pub fn collision_stats(&self) -> (usize, usize, f64) {
+        let lengths = self.buckets.iter().map(Bucket::len).filter(|&len| len > 0);
+        let (non_empty, longest) = lengths.fold((0, 0), |(count, longest), len| {
+            (count + 1, longest.max(len))
+        });
+        let average = if non_empty == 0 {
+            0.0
+        } else {
+            self.size as f64 / non_empty as f64
+        };
+        (non_empty, longest, average)
+    }
+
+    //  Entry `i` is the number of buckets holding exactly `i` pairs
+    pub fn chain_length_histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for bucket in &self.buckets {
+            if histogram.len() <= bucket.len() {
+                histogram.resize(bucket.len() + 1, 0);
+            }
+            histogram[bucket.len()] += 1;
+        }
+        histogram
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.buckets.len() * core::mem::size_of::<Bucket<K, V>>()
+            + self
+                .buckets
+                .iter()
+                .map(|bucket| bucket.pairs().capacity() * core::mem::size_of::<(K, V)>())
+                .sum::<usize>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    //  Number of times the map has grown because it hit the load factor limit
+    pub fn resize_count(&self) -> usize {
+        self.resizes
+    }
+
+    //  Calls `f` with the old and new capacity every time the map grows because
+    //  it hit the load factor limit. Explicit `reserve` and `shrink_to_fit`
+    //  calls are not reported
+    pub fn on_resize(&mut self, f: impl FnMut(usize, usize) + Send + Sync + 'static) {
+        self.on_resize = Some(Box::new(f));
+    }
+
+    pub fn load_factor_limit(&self) -> f64 {
+        self.load_factor_limit
+    }
+
+    pub fn growth_factor(&self) -> f64 {
+        self.growth_factor
+    }
+
+    //  How much the table grows each time it hits the load factor limit. Below
+    //  the default of 2 the map resizes more often but overshoots its size by less
+    pub fn set_growth_factor(&mut self, factor: f64) {
+        assert!(factor > 1.0, "the growth factor must be greater than 1");
+        self.growth_factor = factor;
+    }
+
+    pub fn capacity_policy(&self) -> CapacityPolicy {
+        self.capacity_policy
+    }
+
+    //  Switches how the table is sized, rebuilding it straight away if the
+    //  current capacity does not fit the new policy
+    pub fn set_capacity_policy(&mut self, policy: CapacityPolicy) {
+        self.capacity_policy = policy;
+        let capacity = policy.fit(self.capacity);
+        if capacity != self.capacity {
+            self.rehash(capacity);
+        }
+    }
+
+    //  The capacity the next resize grows to, at least one slot more than now
+    fn grown_capacity(&self) -> usize {
+        let grown = ((self.capacity as f64 * self.growth_factor) as usize).max(self.capacity + 1);
+        self.capacity_policy.fit(grown)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let new_capacity = self
+            .capacity_policy
+            .fit(self.min_capacity(self.size + additional));
+        if new_capacity > self.capacity {
+            self.rehash(new_capacity);
+        }
+    }
+
+    //  Like `reserve`, but reports an error instead of panicking when the table
+    //  cannot be grown
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let new_capacity = self
+            .size
+            .checked_add(additional)
+            .and_then(|entries| self.checked_min_capacity(entries))
+            .map(|capacity| self.capacity_policy.fit(capacity))
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if new_capacity <= self.capacity {
+            return Ok(());
+        }
+        new_capacity
+            .checked_mul(core::mem::size_of::<Bucket<K, V>>())
+            .filter(|&bytes| bytes <= isize::MAX as usize)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let mut new_buckets = Vec::new();
+        new_buckets.try_reserve_exact(new_capacity)?;
+        new_buckets.resize(new_capacity, Bucket::new());
+        self.rehash_into(new_buckets);
+        Ok(())
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        let new_capacity = self.capacity_policy.fit(self.min_capacity(self.size));
+        if new_capacity < self.capacity {
+            self.rehash(new_capacity);
+        }
+    }
+
+    fn resize(&mut self) -> Result<(), HashMapError> {
+        self.resizes += 1;
+        let (old_capacity, new_capacity) = (self.capacity, self.grown_capacity());
+        self.rehash(new_capacity);
+        if let Some(on_resize) = self.on_resize.as_mut() {
+            on_resize(old_capacity, new_capacity);
+        }
+        Ok(())
+    }
+
+    fn rehash(&mut self, new_capacity: usize) {
+        self.rehash_into(vec![Bucket::new(); new_capacity]);
+    }
+
+    //  Moves every pair into the new buckets. Keys are already unique so they
+    //  can be pushed without a lookup or a clone
+    fn rehash_into(&mut self, new_buckets: Vec<Bucket<K, V>>) {
+        self.capacity = new_buckets.len();
+        let old_buckets = core::mem::replace(&mut self.buckets, new_buckets);
 
         for bucket in old_buckets {
-            for (key, value) in bucket.iter() {
-                self.insert(key, value)?;
+            for (key, value) in bucket.into_vec() {
+                let index = self.hash(&key);
+                self.buckets[index].push(key, value, self.treeify);
+            }
+        }
+    }
+
+    //  Walks every bucket checking the bookkeeping against the chains, for
+    //  tests and fuzzing. Each pair must hash to its bucket and be the one a
+    //  lookup finds, which rules out duplicates within a chain
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.buckets.len() != self.capacity {
+            return Err(format!(
+                "table has {} buckets but capacity is {}",
+                self.buckets.len(),
+                self.capacity
+            ));
+        }
+        let mut pairs = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            if let Bucket::Sorted(pairs, order) = bucket {
+                if !pairs
+                    .windows(2)
+                    .all(|w| order(&w[0].0, &w[1].0) == Ordering::Less)
+                {
+                    return Err(format!("sorted bucket {index} is out of order"));
+                }
             }
+            for (position, (key, _)) in bucket.pairs().iter().enumerate() {
+                if self.hash(key) != index {
+                    return Err(format!(
+                        "key {key:?} is in bucket {index} but hashes elsewhere"
+                    ));
+                }
+                if bucket.find(key) != Some(position) {
+                    return Err(format!(
+                        "key {key:?} appears more than once in bucket {index}"
+                    ));
+                }
+            }
+            pairs += bucket.len();
+        }
+        if pairs != self.size {
+            return Err(format!(
+                "size is {} but {pairs} pairs are stored",
+                self.size
+            ));
         }
+        Ok(())
+    }
 
-        anyhow::Ok(())
+    #[cfg(test)]
+    fn set_size_for_test(&mut self, size: usize) {
+        self.size = size;
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> anyhow::Result<()> {
-        if self.get_load_factor() >= LOAD_FACTOR_LIMIT {
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        if self.get_load_factor() >= self.load_factor_limit {
             self.resize()?;
         }
         let index = self.hash(&key);
         self.buckets
             .get_mut(index)
             .map(|bucket| {
-                let result = bucket.insert(key, value).unwrap();
+                let result = bucket.insert(key, value, self.treeify);
                 if result {
                     self.size += 1;
                 }
-                return anyhow::Ok(());
+                Ok(())
             })
             .transpose()
-            .and(anyhow::Ok(()))
+            .and(Ok(()))
+    }
+
+    //  Reserves room for the whole batch up front so the loop below never resizes
+    pub fn insert_many(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), HashMapError> {
+        let pairs = pairs.into_iter();
+        self.reserve(pairs.size_hint().0);
+        for (key, value) in pairs {
+            self.insert(key, value)?;
+        }
+        Ok(())
+    }
+
+    //  Hashes `key` once and searches its bucket once, growing the map first
+    //  if needed. An occupied entry remembers where the pair sits in the bucket
+    pub fn entry(&mut self, key: K) -> Result<Entry<'_, K, V, S>, HashMapError> {
+        if self.get_load_factor() >= self.load_factor_limit {
+            self.resize()?;
+        }
+        let index = self.hash(&key);
+        match self.buckets[index].find(&key) {
+            Some(position) => Ok(Entry::Occupied(OccupiedEntry {
+                map: self,
+                index,
+                position,
+            })),
+            None => Ok(Entry::Vacant(VacantEntry {
+                map: self,
+                index,
+                key,
+            })),
+        }
+    }
+
+    pub fn get_or_insert_with_key<F: FnOnce(&K) -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, HashMapError> {
+        Ok(self.entry(key)?.or_insert_with_key(f))
+    }
+
+    pub fn compute_if_absent<F: FnOnce(&K) -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, HashMapError> {
+        self.get_or_insert_with_key(key, f)
+    }
+
+    //  Replaces the value when `f` returns Some and removes the key when it returns None
+    pub fn compute_if_present<F: FnOnce(&K, V) -> Option<V>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Option<&mut V> {
+        let index = self.hash(&key);
+        let (key, value) = self.buckets[index].take(&key)?;
+        //  the pair is out of the table while `f` runs, so a panic in `f`
+        //  leaves the map one entry smaller rather than overcounting
+        self.size -= 1;
+        let value = f(&key, value)?;
+        self.size += 1;
+        Some(self.buckets[index].push(key, value, self.treeify))
+    }
+
+    //  Builds a map with the same keys and bucket layout but transformed values
+    pub fn map_values<W: Value, F: FnMut(&V) -> W>(&self, mut f: F) -> HashMap<K, W, S>
+    where
+        S: Clone,
+    {
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|bucket| {
+                let pairs = bucket.pairs().iter().map(|(k, v)| (k.clone(), f(v)));
+                match bucket {
+                    Bucket::Chain(_) => Bucket::Chain(pairs.collect()),
+                    Bucket::Sorted(_, order) => Bucket::Sorted(pairs.collect(), *order),
+                }
+            })
+            .collect();
+        HashMap {
+            buckets,
+            size: self.size,
+            capacity: self.capacity,
+            resizes: 0,
+            growth_factor: self.growth_factor,
+            load_factor_limit: self.load_factor_limit,
+            capacity_policy: self.capacity_policy,
+            on_resize: None,
+            move_to_front: self.move_to_front,
+            treeify: self.treeify,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+
+    //  Keeps the entries `f` maps to Some, in a map sized for just those entries
+    pub fn filter_map<W: Value, F: FnMut(&K, &V) -> Option<W>>(&self, mut f: F) -> HashMap<K, W, S>
+    where
+        S: Clone,
+    {
+        let kept: Vec<(K, W)> = self
+            .iter()
+            .filter_map(|(key, value)| f(key, value).map(|value| (key.clone(), value)))
+            .collect();
+        let mut map =
+            HashMap::with_hasher(self.min_capacity(kept.len()), self.hash_builder.clone());
+        map.treeify = self.treeify;
+        for (key, value) in kept {
+            map.insert(key, value)
+                .expect("map was sized to hold every kept entry");
+        }
+        map
+    }
+
+    //  Copies `pairs` into a new map hashing like this one, sized for `entries`
+    fn collect_pairs<'a>(&self, entries: usize, pairs: impl Iterator<Item = (&'a K, &'a V)>) -> Self
+    where
+        K: 'a,
+        V: 'a,
+        S: Clone,
+    {
+        let mut map = HashMap::with_hasher(self.min_capacity(entries), self.hash_builder.clone());
+        map.treeify = self.treeify;
+        for (key, value) in pairs {
+            map.insert(key.clone(), value.clone())
+                .expect("map was sized to hold every entry");
+        }
+        map
+    }
+
+    //  Every entry in either map, with this map's value for a key in both
+    pub fn union(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let only_other = other.iter().filter(|(key, _)| !self.contains_key(key));
+        self.collect_pairs(self.len() + other.len(), self.iter().chain(only_other))
+    }
+
+    //  The entries of this map whose keys are also in `other`. Walks whichever
+    //  map is smaller and looks each key up in the other one
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let entries = self.len().min(other.len());
+        if self.len() <= other.len() {
+            self.collect_pairs(
+                entries,
+                self.iter().filter(|(key, _)| other.contains_key(key)),
+            )
+        } else {
+            let shared = other.iter().filter_map(|(key, _)| self.get_key_value(key));
+            self.collect_pairs(entries, shared)
+        }
+    }
+
+    //  The entries of this map whose keys are not in `other`
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        self.collect_pairs(
+            self.len(),
+            self.iter().filter(|(key, _)| !other.contains_key(key)),
+        )
+    }
+
+    //  The entries whose keys are in exactly one of the two maps
+    pub fn symmetric_difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let only_self = self.iter().filter(|(key, _)| !other.contains_key(key));
+        let only_other = other.iter().filter(|(key, _)| !self.contains_key(key));
+        self.collect_pairs(self.len() + other.len(), only_self.chain(only_other))
+    }
+
+    //  Moves every entry out of `other`, which keeps its capacity for reuse
+    pub fn append(&mut self, other: &mut Self) -> Result<(), HashMapError> {
+        self.reserve(other.size);
+        for bucket in other.buckets.iter_mut() {
+            for (key, value) in core::mem::replace(bucket, Bucket::new()).into_vec() {
+                match self.entry(key)? {
+                    Entry::Occupied(entry) => *entry.into_mut() = value,
+                    Entry::Vacant(entry) => {
+                        entry.insert(value);
+                    }
+                }
+            }
+        }
+        other.size = 0;
+        Ok(())
+    }
+
+    //  Removes the entries matching `pred` and returns them as a new map
+    pub fn split_off<F: FnMut(&K, &V) -> bool>(&mut self, mut pred: F) -> Self
+    where
+        S: Clone,
+    {
+        let Self { buckets, size, .. } = self;
+        let mut split = Vec::new();
+        for bucket in buckets.iter_mut() {
+            //  each pair is counted out as it leaves, before `pred` runs again
+            for pair in bucket.extract_if(&mut pred) {
+                *size -= 1;
+                split.push(pair);
+            }
+        }
+        let mut map = Self::with_hasher(self.min_capacity(split.len()), self.hash_builder.clone());
+        map.treeify = self.treeify;
+        for (key, value) in split {
+            map.insert(key, value)
+                .expect("map was sized to hold every split entry");
+        }
+        map
+    }
+
+    //  Inserts only when `key` is absent, handing the rejected value back otherwise
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<K, V>> {
+        let entry = self
+            .entry(key.clone())
+            .expect("growing the map always leaves a free slot");
+        match entry {
+            Entry::Occupied(entry) => Err(OccupiedError {
+                key,
+                existing: entry.into_mut().clone(),
+                value,
+            }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+    //  Removes and returns the last pair of the first non-empty bucket, for
+    //  draining a map one entry at a time
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let bucket = self.buckets.iter_mut().find(|bucket| bucket.len() > 0)?;
+        let pair = bucket.pairs_mut().pop();
+        self.size -= 1;
+        pair
+    }
+
+    //  Removes every entry, keeping the table at its current capacity
+    pub fn clear(&mut self) {
+        for bucket in self.buckets.iter_mut() {
+            *bucket = Bucket::new();
+        }
+        self.size = 0;
+    }
+
+    //  Like `clear`, but also gives the table back and starts over at the
+    //  smallest capacity, so a long-lived map does not keep the peak size of
+    //  an earlier phase
+    pub fn reset(&mut self) {
+        let capacity = self.capacity_policy.fit(crate::capacity::MIN_CAPACITY);
+        self.buckets = vec![Bucket::new(); capacity];
+        self.capacity = capacity;
+        self.size = 0;
+    }
+
+    //  Keeps only the entries `f` returns true for
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, f: F) {
+        self.retain_count(f);
+    }
+
+    //  Like `retain`, but returns how many entries were removed
+    pub fn retain_count<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> usize {
+        let Self { buckets, size, .. } = self;
+        let mut removed = 0;
+        for bucket in buckets.iter_mut() {
+            for _ in bucket.extract_if(|k, v| !f(k, v)) {
+                *size -= 1;
+                removed += 1;
+            }
+        }
+        removed
     }
 
-    pub fn delete(&mut self, key: K) -> anyhow::Result<()> {
+    //  Deletes every key in `keys`, returning how many were present
+    pub fn delete_many<'a>(&mut self, keys: impl IntoIterator<Item = &'a K>) -> usize
+    where
+        K: 'a,
+    {
+        let mut removed = 0;
+        for key in keys {
+            let index = self.hash(key);
+            if self.buckets[index].take(key).is_some() {
+                removed += 1;
+            }
+        }
+        self.size -= removed;
+        removed
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
         let index = self.hash(&key);
         self.buckets
             .get_mut(index)
-            .map(|bucket| bucket.delete(key))
+            .map(|bucket| {
+                let result = bucket.delete(key);
+                if result {
+                    debug_assert!(self.size > 0, "deleting from a map whose size is 0");
+                    self.size -= 1;
+                }
+                Ok(())
+            })
             .transpose()
-            .and(anyhow::Ok(()))
+            .and(Ok(()))
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Key,
+    V: Value + AddAssign + From<u8>,
+    S: BuildHasher,
+{
+    //  Bumps the count for `key`, starting it at zero if absent
+    pub fn increment(&mut self, key: K) -> Result<(), HashMapError> {
+        *self.entry(key)?.or_insert(V::from(0)) += V::from(1);
+        Ok(())
+    }
+
+    //  The `n` keys with the highest counts, highest first
+    pub fn most_common(&self, n: usize) -> Vec<(K, V)>
+    where
+        V: Ord,
+    {
+        let mut counts: Vec<(K, V)> = self.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1));
+        counts.truncate(n);
+        counts
+    }
+}
+
+impl<K, V, S> FromBuilder<S> for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    fn from_builder(builder: HashMapBuilder<S>) -> Self {
+        let mut map = Self::with_hasher(builder.capacity, builder.hash_builder);
+        if let Some(limit) = builder.load_factor_limit {
+            map.load_factor_limit = limit;
+        }
+        if let Some(factor) = builder.growth_factor {
+            map.set_growth_factor(factor);
+        }
+        map.set_capacity_policy(builder.capacity_policy);
+        map
+    }
+}
+
+//  A clone starts without the original's on_resize callback
+impl<K, V, S> Clone for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            buckets: self.buckets.clone(),
+            size: self.size,
+            capacity: self.capacity,
+            resizes: self.resizes,
+            growth_factor: self.growth_factor,
+            load_factor_limit: self.load_factor_limit,
+            capacity_policy: self.capacity_policy,
+            on_resize: None,
+            move_to_front: self.move_to_front,
+            treeify: self.treeify,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+
+    //  Copies into the existing bucket array and each bucket's own storage,
+    //  so nothing is reallocated when `self` already has room for `source`.
+    //  Keeps the on_resize callback registered on `self`
+    fn clone_from(&mut self, source: &Self) {
+        self.buckets.clone_from(&source.buckets);
+        self.size = source.size;
+        self.capacity = source.capacity;
+        self.resizes = source.resizes;
+        self.growth_factor = source.growth_factor;
+        self.load_factor_limit = source.load_factor_limit;
+        self.capacity_policy = source.capacity_policy;
+        self.move_to_front = source.move_to_front;
+        self.treeify = source.treeify;
+        self.hash_builder.clone_from(&source.hash_builder);
+    }
+}
+
+impl<K, V, S> PartialEq for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value + PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get_ref(key) == Some(value))
+    }
+}
+
+impl<K, V, S> Eq for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value + Eq,
+    S: BuildHasher,
+{
+}
+
+//  Renders `{k1: v1, k2: v2}` in iteration order, for quick dumps
+impl<K, V, S> Display for HashMap<K, V, S>
+where
+    K: Key + Display,
+    V: Value + Display,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("{")?;
+        for (i, (key, value)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", key, value)?;
+        }
+        f.write_str("}")
+    }
+}
+
+//  Reserves room for the iterator's lower size hint up front, so a well
+//  hinted iterator grows the table at most once. A hint of zero reserves
+//  nothing and the map grows as pairs arrive
+impl<K, V, S> Extend<(K, V)> for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        self.reserve(lower);
+        for (key, value) in iter {
+            self.insert(key, value)
+                .expect("growing the map always leaves a free slot");
+        }
+    }
+}
+
+pub enum Entry<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+    position: usize,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+    key: K,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with_key(|_| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        self.or_insert_with_key(|_| f())
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    //  Applies `f` to the value if the key is present. The entry already holds
+    //  the slot, so a following `or_insert` does not probe again
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = f(&entry.key);
+                entry.insert(value)
+            }
+        }
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    //  The key stored in the map, not the one the entry was looked up with
+    pub fn key(&self) -> &K {
+        &self.map.buckets[self.index].pairs()[self.position].0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.buckets[self.index].pairs()[self.position].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.buckets[self.index].pairs_mut()[self.position].1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.buckets[self.index].pairs_mut()[self.position].1
+    }
+
+    //  Replaces the value, returning the old one
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        let (_, value) = self.map.buckets[self.index]
+            .pairs_mut()
+            .remove(self.position);
+        self.map.size -= 1;
+        value
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        let map = self.map;
+        map.size += 1;
+        let treeify = map.treeify;
+        map.buckets[self.index].push(self.key, value, treeify)
     }
 }
 
@@ -211,12 +1262,14 @@ where
 mod tests {
     use super::*;
 
+    map_tests!();
+
     #[test]
     fn test_hashmap() {
         let mut map: HashMap<String, String> = HashMap::new(16);
         map.insert("key".to_string(), "value".to_string()).unwrap();
         let value = map.get("key".to_string());
-        assert_eq!(value.unwrap().unwrap(), "value".to_string());
+        assert_eq!(value.unwrap(), "value".to_string());
     }
 
     #[test]
@@ -230,7 +1283,7 @@ mod tests {
         for i in 0..25 {
             let key = format!("key_{}", i);
             let value = format!("value_{}", i);
-            let result = map.get(key).unwrap();
+            let result = map.get(key);
             assert_eq!(result.unwrap(), value);
         }
     }
@@ -254,11 +1307,320 @@ mod tests {
         for i in 0..100 {
             if i % 5 == 0 {
                 let key = format!("Key{i}");
-                assert_eq!(map.get(key).unwrap(), None);
+                assert_eq!(map.get(key), None);
             } else {
                 let key = format!("Key{i}");
-                assert_eq!(map.get(key).unwrap(), Some(format!("Value{i}")));
+                assert_eq!(map.get(key), Some(format!("Value{i}")));
+            }
+        }
+    }
+
+    #[test]
+    fn test_treeify_colliding_bucket() {
+        let mut map: HashMap<u64, u64> = HashMap::new(1024);
+        let target = map.hash(&0);
+        let colliding: Vec<u64> = (0..)
+            .filter(|key| map.hash(key) == target)
+            .take(20)
+            .collect();
+        //  off by default, so a long chain stays in insertion order
+        for &key in &colliding[..10] {
+            map.insert(key, key * 2).unwrap();
+        }
+        assert!(!map.buckets[target].is_sorted());
+
+        map.set_treeify(true);
+        for &key in &colliding[10..] {
+            map.insert(key, key * 2).unwrap();
+        }
+        assert!(map.buckets[target].is_sorted());
+        map.check_invariants().unwrap();
+        assert_eq!(map.len(), 20);
+        for &key in &colliding {
+            assert_eq!(map.get(key), Some(key * 2));
+        }
+
+        map.delete(colliding[3]).unwrap();
+        assert_eq!(map.get(colliding[3]), None);
+        assert_eq!(map.len(), 19);
+
+        //  derived maps keep the setting and the sorted buckets
+        let doubled = map.map_values(|v| v * 2);
+        assert!(doubled.buckets[target].is_sorted());
+        let mut cloned = map.clone();
+        cloned.check_invariants().unwrap();
+        cloned.insert(colliding[3], 0).unwrap();
+        assert!(cloned.buckets[target].is_sorted());
+        cloned.check_invariants().unwrap();
+        map.rehash(map.capacity);
+        assert!(map.buckets[target].is_sorted());
+        map.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_vec_chains_match_std() {
+        let mut map: HashMap<u64, u64> = HashMap::new(16);
+        let mut expected = std::collections::HashMap::new();
+        for i in 0..2_000u64 {
+            let key = i * 7 % 500;
+            match i % 3 {
+                0 | 1 => {
+                    map.insert(key, i).unwrap();
+                    expected.insert(key, i);
+                }
+                _ => {
+                    map.delete(key).unwrap();
+                    expected.remove(&key);
+                }
             }
         }
+        assert_eq!(map.len(), expected.len());
+        for key in 0..500 {
+            assert_eq!(map.get(key), expected.get(&key).copied());
+        }
+    }
+
+    #[test]
+    fn test_move_to_front() {
+        let mut map: HashMap<u64, u64> = HashMap::new(1024);
+        let target = map.hash(&0);
+        let colliding: Vec<u64> = (0..)
+            .filter(|key| map.hash(key) == target)
+            .take(5)
+            .collect();
+        for &key in &colliding {
+            map.insert(key, key).unwrap();
+        }
+        let tail = colliding[4];
+
+        map.get_mut(tail).unwrap();
+        assert_eq!(map.buckets[target].pairs()[0].0, colliding[0]);
+
+        map.set_move_to_front(true);
+        //  shared reads do not promote
+        assert_eq!(map.get(tail), Some(tail));
+        assert_eq!(map.buckets[target].pairs()[0].0, colliding[0]);
+
+        assert_eq!(map.get_promoted(&colliding[3]), Some(&colliding[3]));
+        assert_eq!(map.buckets[target].pairs()[0].0, colliding[3]);
+        assert_eq!(map.get_promoted(&colliding[3]), Some(&colliding[3]));
+        assert_eq!(map.get_promoted(&u64::MAX), None);
+
+        *map.get_mut(tail).unwrap() += 1;
+        let keys: Vec<u64> = map.buckets[target]
+            .pairs()
+            .iter()
+            .map(|(k, _)| *k)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![tail, colliding[3], colliding[0], colliding[1], colliding[2]]
+        );
+        assert_eq!(map.get(tail), Some(tail + 1));
+
+        //  with promotion off the read path leaves the order alone
+        map.set_move_to_front(false);
+        assert_eq!(map.get_promoted(&colliding[2]), Some(&colliding[2]));
+        assert_eq!(map.buckets[target].pairs()[0].0, tail);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut map: HashMap<String, u32> = HashMap::new(16);
+        for i in 0..100 {
+            map.insert(format!("key{i}"), i).unwrap();
+        }
+        map.delete("key7".to_string()).unwrap();
+
+        let entries: Vec<(&String, &u32)> = map.iter().collect();
+        assert_eq!(entries.len(), map.len());
+        let total: u32 = entries.iter().map(|(_, v)| **v).sum();
+        assert_eq!(total, (0..100).sum::<u32>() - 7);
+    }
+
+    #[test]
+    fn test_resize_moves_entries() {
+        let mut map: HashMap<String, String> = HashMap::new(16);
+        for i in 0..10_000 {
+            map.insert(format!("key{i}"), format!("value{i}")).unwrap();
+        }
+        assert!(map.resize_count() >= 5);
+        assert_eq!(map.len(), 10_000);
+        assert_eq!(map.iter().count(), 10_000);
+        for i in 0..10_000 {
+            assert_eq!(map.get(format!("key{i}")), Some(format!("value{i}")));
+        }
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut map: HashMap<i32, i32> = HashMap::new(16);
+        for i in 0..40 {
+            map.insert(i, i).unwrap();
+            map.check_invariants().unwrap();
+        }
+        for i in 0..20 {
+            map.delete(i).unwrap();
+            map.check_invariants().unwrap();
+        }
+
+        map.set_size_for_test(3);
+        assert!(map.check_invariants().unwrap_err().contains("size"));
+        map.set_size_for_test(20);
+        map.check_invariants().unwrap();
+
+        //  a second copy of a key in its own bucket
+        let index = map.hash(&25);
+        map.buckets[index].pairs_mut().push((25, 0));
+        map.set_size_for_test(21);
+        assert!(map
+            .check_invariants()
+            .unwrap_err()
+            .contains("more than once"));
+    }
+
+    #[test]
+    fn test_with_seed_is_reproducible() {
+        let build = || {
+            let mut map = HashMap::with_seed(16, 42);
+            for i in 0..100 {
+                map.insert(i, i * 2).unwrap();
+            }
+            for i in (0..100).step_by(3) {
+                map.delete(i).unwrap();
+            }
+            map
+        };
+        let (first, second) = (build(), build());
+        let layout = |map: &HashMap<i32, i32, FixedSeedState>| -> Vec<(bool, Vec<(i32, i32)>)> {
+            map.buckets
+                .iter()
+                .map(|bucket| (bucket.is_sorted(), bucket.pairs().clone()))
+                .collect()
+        };
+        assert_eq!(layout(&first), layout(&second));
+    }
+
+    #[test]
+    fn test_clone_from_reuses_allocation() {
+        let mut source = HashMap::with_seed(16, 0);
+        for i in 0..500 {
+            source.insert(i, i.to_string()).unwrap();
+        }
+        let mut target = HashMap::with_seed(4096, 0);
+        for i in 0..2_000 {
+            target.insert(i * 7, "stale".to_string()).unwrap();
+        }
+        let buckets = target.buckets.as_ptr();
+
+        target.clone_from(&source);
+        assert!(target == source);
+        assert_eq!(target.capacity(), source.capacity());
+        assert_eq!(target.buckets.as_ptr(), buckets);
+        target.check_invariants().unwrap();
+
+        //  a clone is independent of the map it came from
+        let mut copy = source.clone();
+        copy.insert(0, "changed".to_string()).unwrap();
+        assert_eq!(source.get(0), Some("0".to_string()));
+    }
+
+    #[test]
+    fn test_collision_stats() {
+        let mut map: HashMap<u64, u64> = HashMap::new(1024);
+        assert_eq!(map.collision_stats(), (0, 0, 0.0));
+
+        let target = map.hash(&0);
+        let colliding: Vec<u64> = (0..)
+            .filter(|key| map.hash(key) == target)
+            .take(5)
+            .collect();
+        for &key in &colliding {
+            map.insert(key, key).unwrap();
+        }
+        let other = (0..).find(|key| map.hash(key) != target).unwrap();
+        map.insert(other, other).unwrap();
+
+        let (non_empty, longest, average) = map.collision_stats();
+        assert_eq!(non_empty, 2);
+        assert_eq!(longest, 5);
+        assert_eq!(average, 3.0);
+    }
+
+    #[test]
+    fn test_chain_length_histogram() {
+        let mut map = HashMap::new(16);
+        for i in 0..11 {
+            map.insert(i, i).unwrap();
+        }
+        let histogram = map.chain_length_histogram();
+        assert_eq!(histogram.iter().sum::<usize>(), map.capacity());
+        let weighted: usize = histogram.iter().enumerate().map(|(len, n)| len * n).sum();
+        assert_eq!(weighted, map.len());
+    }
+
+    #[test]
+    fn test_memory_upper_bound() {
+        let n = 3_000;
+        let mut map = HashMap::new(HashMap::<u64, u64>::capacity_for(n, LOAD_FACTOR_LIMIT));
+        for i in 0..n as u64 {
+            map.insert(i, i).unwrap();
+        }
+        assert_eq!(map.resize_count(), 0);
+        assert!(map.memory_usage() <= HashMap::<u64, u64>::memory_upper_bound(n));
+    }
+
+    #[test]
+    fn test_non_display_values() {
+        let mut map: HashMap<String, Vec<u8>> = HashMap::new(16);
+        for i in 0..40u8 {
+            map.insert(format!("key{i}"), vec![i; i as usize]).unwrap();
+        }
+        assert_eq!(map.get("key3".to_string()), Some(vec![3, 3, 3]));
+        map.get_mut("key0".to_string()).unwrap().push(9);
+        assert_eq!(map.get_ref(&"key0".to_string()), Some(&vec![9]));
+        assert_eq!(map.len(), 40);
+    }
+
+    #[test]
+    fn test_iter_buckets_covers_every_entry() {
+        let mut map = HashMap::with_seed(16, 0);
+        for i in 0..100 {
+            map.insert(i, i * 2).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let mut indices = Vec::new();
+        for (index, pairs) in map.iter_buckets() {
+            indices.push(index);
+            for (&key, &value) in pairs {
+                //  each pair sits in the bucket its key hashes to
+                assert_eq!(map.hash(&key), index);
+                seen.push((key, value));
+            }
+        }
+        assert_eq!(indices, (0..map.capacity()).collect::<Vec<_>>());
+        let mut all: Vec<(i32, i32)> = map.iter().map(|(&k, &v)| (k, v)).collect();
+        assert_eq!(seen, all);
+        all.sort();
+        assert_eq!(all, (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_delete_keeps_size_in_step() {
+        //  delete used to leave `size` alone, so churn at a steady number of
+        //  entries still crossed the load factor limit and grew the table
+        let mut map = HashMap::new(16);
+        for i in 0..8 {
+            map.insert(i, i).unwrap();
+        }
+        for round in 0..1_000 {
+            map.insert(100 + round, round).unwrap();
+            map.delete(100 + round).unwrap();
+            assert_eq!(map.len(), 8);
+        }
+        assert_eq!(map.resize_count(), 0);
+        assert_eq!(map.capacity(), 16);
+        map.check_invariants().unwrap();
     }
 }
@@ -0,0 +1,76 @@
+use core::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashMapError {
+    //  No free slot could be found for a new entry
+    Full,
+    //  The key to remove is not in the map
+    KeyNotFound,
+    //  A probe landed outside the table, which means the hash and capacity disagree
+    HashInvariantViolated,
+}
+
+impl Display for HashMapError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HashMapError::Full => write!(f, "hashmap is full"),
+            HashMapError::KeyNotFound => write!(f, "key not found"),
+            HashMapError::HashInvariantViolated => {
+                write!(
+                    f,
+                    "probe went out of bounds. seems like an issue with the hash function"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for HashMapError {}
+
+//  Returned by `try_insert` when the key is already present. Carries the value
+//  already in the map along with the one that was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OccupiedError<K, V> {
+    pub key: K,
+    pub existing: V,
+    pub value: V,
+}
+
+impl<K: core::fmt::Debug, V> Display for OccupiedError<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "key {:?} is already present", self.key)
+    }
+}
+
+impl<K: core::fmt::Debug, V: core::fmt::Debug> core::error::Error for OccupiedError<K, V> {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryReserveError {
+    //  The requested capacity does not fit in memory addressable by the table
+    CapacityOverflow,
+    //  The allocator could not hand out the new table
+    AllocError,
+}
+
+impl Display for TryReserveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => write!(f, "requested capacity overflowed"),
+            TryReserveError::AllocError => write!(f, "memory allocation failed"),
+        }
+    }
+}
+
+impl core::error::Error for TryReserveError {}
+
+impl From<allocator_api2::collections::TryReserveError> for TryReserveError {
+    fn from(_: allocator_api2::collections::TryReserveError) -> Self {
+        TryReserveError::AllocError
+    }
+}
+
+impl From<alloc::collections::TryReserveError> for TryReserveError {
+    fn from(_: alloc::collections::TryReserveError) -> Self {
+        TryReserveError::AllocError
+    }
+}
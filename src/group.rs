@@ -0,0 +1,139 @@
+//  Group-at-a-time scanning of control bytes for the compact map, a cut down
+//  version of the SwissTable layout. Each slot has one control byte: the 7-bit
+//  fingerprint of its key when occupied, or one of the tags below otherwise.
+//  The top bit is set only on the tags, so a fingerprint never matches one
+
+pub(crate) const GROUP_WIDTH: usize = 16;
+pub(crate) const EMPTY_TAG: u8 = 0xFF;
+pub(crate) const DELETED_TAG: u8 = 0x80;
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+pub(crate) use sse2::Group;
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2")))]
+pub(crate) use scalar::Group;
+
+//  One bit per slot in a group, lowest bit first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct BitMask(pub(crate) u16);
+
+impl BitMask {
+    pub(crate) fn any(self) -> bool {
+        self.0 != 0
+    }
+}
+
+impl Iterator for BitMask {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let bit = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(bit)
+    }
+}
+
+//  Only reached when the simd feature is off, or from the tests
+#[cfg_attr(
+    all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"),
+    allow(dead_code)
+)]
+pub(crate) mod scalar {
+    use super::{BitMask, EMPTY_TAG, GROUP_WIDTH};
+
+    pub(crate) struct Group([u8; GROUP_WIDTH]);
+
+    impl Group {
+        //  `bytes` must hold at least GROUP_WIDTH bytes
+        pub(crate) fn load(bytes: &[u8]) -> Self {
+            let mut group = [0; GROUP_WIDTH];
+            group.copy_from_slice(&bytes[..GROUP_WIDTH]);
+            Group(group)
+        }
+
+        pub(crate) fn match_byte(&self, byte: u8) -> BitMask {
+            let mut mask = 0;
+            for (i, &b) in self.0.iter().enumerate() {
+                if b == byte {
+                    mask |= 1 << i;
+                }
+            }
+            BitMask(mask)
+        }
+
+        pub(crate) fn match_empty(&self) -> BitMask {
+            self.match_byte(EMPTY_TAG)
+        }
+    }
+}
+
+//  Compiled on every x86_64 build so the tests can compare it with the scalar scan
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(feature = "simd"), allow(dead_code))]
+pub(crate) mod sse2 {
+    use super::{BitMask, EMPTY_TAG, GROUP_WIDTH};
+    use core::arch::x86_64::{
+        __m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_set1_epi8,
+    };
+
+    pub(crate) struct Group(__m128i);
+
+    impl Group {
+        //  `bytes` must hold at least GROUP_WIDTH bytes
+        pub(crate) fn load(bytes: &[u8]) -> Self {
+            assert!(bytes.len() >= GROUP_WIDTH);
+            // SAFETY: the assert above keeps the unaligned 16-byte load in
+            // bounds, and SSE2 is part of the x86_64 baseline
+            Group(unsafe { _mm_loadu_si128(bytes.as_ptr() as *const __m128i) })
+        }
+
+        pub(crate) fn match_byte(&self, byte: u8) -> BitMask {
+            // SAFETY: SSE2 is part of the x86_64 baseline
+            unsafe {
+                let cmp = _mm_cmpeq_epi8(self.0, _mm_set1_epi8(byte as i8));
+                BitMask(_mm_movemask_epi8(cmp) as u16)
+            }
+        }
+
+        pub(crate) fn match_empty(&self) -> BitMask {
+            self.match_byte(EMPTY_TAG)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_bitmask_iter() {
+        let bits: Vec<usize> = BitMask(0b1000_0000_0010_0101).collect();
+        assert_eq!(bits, vec![0, 2, 5, 15]);
+        assert!(!BitMask(0).any());
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_simd_and_scalar_agree() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..1_000 {
+            let bytes: Vec<u8> = (0..GROUP_WIDTH)
+                .map(|_| match rng.gen_range(0..4) {
+                    0 => EMPTY_TAG,
+                    1 => DELETED_TAG,
+                    _ => rng.gen_range(0..4),
+                })
+                .collect();
+            let simd = sse2::Group::load(&bytes);
+            let scalar = scalar::Group::load(&bytes);
+            assert_eq!(simd.match_empty(), scalar.match_empty());
+            for byte in [0, 1, 2, 3, DELETED_TAG] {
+                assert_eq!(simd.match_byte(byte), scalar.match_byte(byte));
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//  A set of keys, stored as an open addressing map with `()` for every
+//  value so it shares all of that map's probing and growth
+use core::hash::BuildHasher;
+
+use crate::open_addressing::{self, Key};
+use crate::{FixedSeedState, HashMapError, RandomState};
+
+pub struct HashSet<K, S = RandomState>
+where
+    K: Key,
+{
+    map: open_addressing::HashMap<K, (), S>,
+}
+
+impl<K> HashSet<K>
+where
+    K: Key,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K> HashSet<K, FixedSeedState>
+where
+    K: Key,
+{
+    pub fn with_seed(capacity: usize, seed: u64) -> Self {
+        Self::with_hasher(capacity, FixedSeedState::new(seed))
+    }
+}
+
+impl<K, S> HashSet<K, S>
+where
+    K: Key,
+    S: BuildHasher,
+{
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            map: open_addressing::HashMap::with_hasher(capacity, hash_builder),
+        }
+    }
+
+    //  Returns true if `key` was not in the set before
+    pub fn insert(&mut self, key: K) -> Result<bool, HashMapError> {
+        Ok(match self.map.entry(key)? {
+            open_addressing::Entry::Occupied(_) => false,
+            open_addressing::Entry::Vacant(entry) => {
+                entry.insert(());
+                true
+            }
+        })
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    //  Returns true if `key` was in the set
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.map.delete_many([key]) == 1
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.map.iter().map(|(key, _)| key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    //  Keys in either set
+    pub fn union(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        Self {
+            map: self.map.union(&other.map),
+        }
+    }
+
+    //  Keys in both sets
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        Self {
+            map: self.map.intersection(&other.map),
+        }
+    }
+
+    //  Keys in this set but not in `other`
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        Self {
+            map: self.map.difference(&other.map),
+        }
+    }
+
+    //  Keys in exactly one of the two sets
+    pub fn symmetric_difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        Self {
+            map: self.map.symmetric_difference(&other.map),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_of(keys: impl IntoIterator<Item = u32>) -> HashSet<u32, FixedSeedState> {
+        let mut set = HashSet::with_seed(16, 0);
+        for key in keys {
+            set.insert(key).unwrap();
+        }
+        set
+    }
+
+    fn sorted(set: &HashSet<u32, FixedSeedState>) -> Vec<u32> {
+        let mut keys: Vec<u32> = set.iter().copied().collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_set_semantics() {
+        let mut set = HashSet::new(16);
+        assert!(set.insert("a".to_string()).unwrap());
+        assert!(set.insert("b".to_string()).unwrap());
+        assert!(!set.insert("a".to_string()).unwrap());
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&"a".to_string()));
+
+        assert!(set.remove(&"a".to_string()));
+        assert!(!set.remove(&"a".to_string()));
+        assert!(!set.contains(&"a".to_string()));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let evens = set_of((0..20).step_by(2));
+        let threes = set_of((0..20).step_by(3));
+
+        assert_eq!(
+            sorted(&evens.union(&threes)),
+            vec![0, 2, 3, 4, 6, 8, 9, 10, 12, 14, 15, 16, 18]
+        );
+        assert_eq!(sorted(&evens.intersection(&threes)), vec![0, 6, 12, 18]);
+        assert_eq!(sorted(&threes.intersection(&evens)), vec![0, 6, 12, 18]);
+        assert_eq!(
+            sorted(&evens.difference(&threes)),
+            vec![2, 4, 8, 10, 14, 16]
+        );
+        assert_eq!(sorted(&threes.difference(&evens)), vec![3, 9, 15]);
+        assert!(evens.difference(&evens).is_empty());
+        assert_eq!(
+            sorted(&evens.symmetric_difference(&threes)),
+            vec![2, 3, 4, 8, 9, 10, 14, 15, 16]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//  SipHash-1-3, the algorithm behind std's `DefaultHasher`. Bundled so the
+//  maps can hash without std, and kept bit-for-bit identical to the std
+//  hasher for the same keys
+use core::hash::{BuildHasher, Hash, Hasher};
+
+#[derive(Clone, Debug)]
+pub struct SipHasher13 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    //  bytes of the current partial word, lowest first
+    tail: u64,
+    ntail: usize,
+    length: usize,
+}
+
+impl SipHasher13 {
+    //  Zero keys, which matches `std::hash::DefaultHasher::new()`
+    pub fn new() -> Self {
+        Self::new_with_keys(0, 0)
+    }
+
+    pub fn new_with_keys(k0: u64, k1: u64) -> Self {
+        Self {
+            v0: k0 ^ 0x736f6d6570736575,
+            v1: k1 ^ 0x646f72616e646f6d,
+            v2: k0 ^ 0x6c7967656e657261,
+            v3: k1 ^ 0x7465646279746573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, word: u64) {
+        self.v3 ^= word;
+        self.round();
+        self.v0 ^= word;
+    }
+}
+
+impl Default for SipHasher13 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.length += bytes.len();
+        for &byte in bytes {
+            self.tail |= (byte as u64) << (8 * self.ntail);
+            self.ntail += 1;
+            if self.ntail == 8 {
+                let word = self.tail;
+                self.compress(word);
+                self.tail = 0;
+                self.ntail = 0;
+            }
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+        let last = ((self.length as u64 & 0xff) << 56) | self.tail;
+        state.compress(last);
+        state.v2 ^= 0xff;
+        state.round();
+        state.round();
+        state.round();
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+//  The default hash builder for every map. Each instance draws fresh SipHash
+//  keys, so two maps holding the same keys lay them out differently and an
+//  attacker cannot precompute keys that all land on one probe sequence.
+//  The cost is that layouts, iteration order and probe lengths change from
+//  run to run; pass a fixed-key builder to `with_hasher` when those need to
+//  be reproducible. Without the `std` feature there is no entropy source, so
+//  every instance uses zero keys and offers no such protection
+#[derive(Clone, Debug)]
+pub struct RandomState {
+    k0: u64,
+    k1: u64,
+}
+
+impl RandomState {
+    #[cfg(feature = "std")]
+    pub fn new() -> Self {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        Self {
+            k0: rng.gen(),
+            k1: rng.gen(),
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn new() -> Self {
+        Self { k0: 0, k1: 0 }
+    }
+}
+
+impl Default for RandomState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for RandomState {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+//  A hash builder with caller-chosen keys, for when layouts must be the same
+//  on every run: benchmarks comparing probe lengths, golden tests, replaying
+//  a failing input. Seed 0 hashes exactly like std's `DefaultHasher::new()`.
+//  Anyone who can guess the seed can also craft colliding keys, so keep
+//  `RandomState` for maps that store untrusted input
+#[derive(Clone, Debug, Default)]
+pub struct FixedSeedState {
+    seed: u64,
+}
+
+impl FixedSeedState {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl BuildHasher for FixedSeedState {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.seed, self.seed)
+    }
+}
+
+//  XOR of a fixed-key hash of every (key, value) pair. XOR does not care
+//  about order, so the result depends only on the entries and not on slot
+//  layout, seed or insertion order
+pub(crate) fn fingerprint<'a, K, V>(entries: impl Iterator<Item = (&'a K, &'a V)>) -> u64
+where
+    K: Hash + 'a,
+    V: Hash + 'a,
+{
+    entries.fold(0, |acc, entry| {
+        let mut hasher = SipHasher13::new();
+        entry.hash(&mut hasher);
+        acc ^ hasher.finish()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_both<T: Hash>(value: T) -> (u64, u64) {
+        let mut ours = SipHasher13::new();
+        value.hash(&mut ours);
+        let mut theirs = std::hash::DefaultHasher::new();
+        value.hash(&mut theirs);
+        (ours.finish(), theirs.finish())
+    }
+
+    #[test]
+    fn test_matches_std_default_hasher() {
+        for i in 0..1_000u32 {
+            let (ours, theirs) = hash_both(i);
+            assert_eq!(ours, theirs);
+            let (ours, theirs) = hash_both(format!("key_{i}"));
+            assert_eq!(ours, theirs);
+        }
+        let (ours, theirs) = hash_both(("", 0u8, u64::MAX, [1u16; 13]));
+        assert_eq!(ours, theirs);
+    }
+}
@@ -0,0 +1,223 @@
+//  An open addressing map that normally grows without a stop-the-world
+//  rehash. When the table fills up, a new one is allocated and the old one
+//  is kept alongside it; every later `get`, `insert` and `delete` moves the entries
+//  from the next few old slots across, so no single operation pays for more
+//  than a bounded slice of the migration. Until the old table is drained,
+//  lookups check both, and a key lives in exactly one of them
+use core::hash::BuildHasher;
+
+use crate::open_addressing::{self, Key, Value};
+use crate::{FixedSeedState, HashMapError, RandomState};
+
+//  Old slots scanned per operation while a migration is running, so draining
+//  an old table of n slots adds at most n / 32 new entries on top of the
+//  migrated ones. The new table is either grown by the growth factor or, when
+//  the old one was mostly tombstones, the same size with far fewer live
+//  entries, which normally leaves room for both. It is not guaranteed: with a
+//  growth factor close to 1 the new table can still hit its load limit first
+const MIGRATION_SLOTS_PER_OP: usize = 32;
+
+pub struct IncrementalMap<K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    current: open_addressing::HashMap<K, V, S>,
+    //  the table being drained, and the next slot in it to move
+    old: Option<open_addressing::HashMap<K, V, S>>,
+    cursor: usize,
+}
+
+impl<K, V> IncrementalMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V> IncrementalMap<K, V, FixedSeedState>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn with_seed(capacity: usize, seed: u64) -> Self {
+        Self::with_hasher(capacity, FixedSeedState::new(seed))
+    }
+}
+
+impl<K, V, S> IncrementalMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher + Clone,
+{
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            current: open_addressing::HashMap::with_hasher(capacity, hash_builder),
+            old: None,
+            cursor: 0,
+        }
+    }
+
+    pub fn is_migrating(&self) -> bool {
+        self.old.is_some()
+    }
+
+    //  Moves the entries in the next MIGRATION_SLOTS_PER_OP old slots into
+    //  the current table, dropping the old table once it is drained. Entries
+    //  go in through `current.insert`, so if the current table reaches its
+    //  load limit mid-migration that insert rebuilds it in one stop-the-world
+    //  pass, like a plain open addressing map would
+    fn migrate_step(&mut self) {
+        let Some(old) = self.old.as_mut() else {
+            return;
+        };
+        let end = (self.cursor + MIGRATION_SLOTS_PER_OP).min(old.capacity());
+        for index in self.cursor..end {
+            if let Some((key, value)) = old.take_slot(index) {
+                self.current
+                    .insert(key, value)
+                    .expect("the new table has room for every migrated entry");
+            }
+        }
+        self.cursor = end;
+        if self.cursor == old.capacity() {
+            self.old = None;
+        }
+    }
+
+    //  Swaps in a larger table when the current one is about to rebuild
+    //  itself, finishing any migration still in flight first
+    fn start_migration_if_full(&mut self) {
+        if !self.current.at_load_limit() {
+            return;
+        }
+        while self.old.is_some() {
+            self.migrate_step();
+        }
+        let next = open_addressing::HashMap::with_hasher(
+            self.current.rebuild_capacity(),
+            self.current.hasher().clone(),
+        );
+        self.old = Some(core::mem::replace(&mut self.current, next));
+        self.cursor = 0;
+    }
+
+    pub fn get(&mut self, key: K) -> Option<V> {
+        self.migrate_step();
+        self.get_ref(&key).cloned()
+    }
+
+    //  Looks in both tables without moving any entries
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        self.current
+            .get_ref(key)
+            .or_else(|| self.old.as_ref()?.get_ref(key))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_ref(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        self.migrate_step();
+        self.start_migration_if_full();
+        if let Some(old) = self.old.as_mut() {
+            if old.contains_key(&key) {
+                old.delete(key.clone())?;
+            }
+        }
+        self.current.insert(key, value)
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        self.migrate_step();
+        if let Some(old) = self.old.as_mut() {
+            if old.contains_key(&key) {
+                return old.delete(key);
+            }
+        }
+        self.current.delete(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.current
+            .iter()
+            .chain(self.old.iter().flat_map(|old| old.iter()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.len() + self.old.as_ref().map_or(0, |old| old.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //  Capacity of the table new entries go into
+    pub fn capacity(&self) -> usize {
+        self.current.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_matches_std_through_migrations() {
+        let mut rng = rand::thread_rng();
+        let mut map = IncrementalMap::with_seed(16, 0);
+        let mut model = std::collections::HashMap::new();
+        let mut migrating_steps = 0;
+        for step in 0..20_000 {
+            let key = rng.gen_range(0..5_000);
+            match rng.gen_range(0..4) {
+                0 => {
+                    map.delete(key).unwrap();
+                    model.remove(&key);
+                }
+                1 => assert_eq!(map.get(key), model.get(&key).copied()),
+                _ => {
+                    map.insert(key, step).unwrap();
+                    model.insert(key, step);
+                }
+            }
+            if map.is_migrating() {
+                migrating_steps += 1;
+                //  every key is reachable while entries sit in either table
+                for (key, value) in &model {
+                    assert_eq!(map.get_ref(key), Some(value));
+                }
+            }
+            assert_eq!(map.len(), model.len());
+        }
+        assert!(migrating_steps > 0);
+    }
+
+    #[test]
+    fn test_migration_finishes() {
+        let mut map = IncrementalMap::with_seed(16, 0);
+        let mut i = 0;
+        while !map.is_migrating() {
+            map.insert(i, i).unwrap();
+            i += 1;
+        }
+        let old_capacity = map.old.as_ref().unwrap().capacity();
+        assert_eq!(map.capacity(), old_capacity * 2);
+        for _ in 0..old_capacity.div_ceil(MIGRATION_SLOTS_PER_OP) {
+            assert!(map.is_migrating());
+            map.get(0);
+        }
+        assert!(!map.is_migrating());
+        assert_eq!(map.len(), i as usize);
+        map.current.check_invariants().unwrap();
+        for key in 0..i {
+            assert_eq!(map.get(key), Some(key));
+        }
+    }
+}
@@ -1,4 +1,48 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+//  Declared before the maps so their test modules can expand the shared tests
+#[cfg(test)]
+#[macro_use]
+mod map_tests;
+
+pub mod bloom_map;
+pub mod bounded_map;
+pub mod builder;
+pub mod capacity;
 pub mod chaining;
+pub mod error;
+mod group;
+pub mod hash_set;
+pub mod hasher;
+pub mod incremental_map;
+pub mod lru_map;
+pub mod multi_map;
 pub mod open_addressing;
 pub mod open_addressing_compact;
+#[cfg(feature = "std")]
+pub mod sharded_map;
+pub mod small_map;
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod ttl_map;
+#[cfg(feature = "std")]
 pub mod workloads;
+
+pub use builder::HashMapBuilder;
+pub use capacity::CapacityPolicy;
+//  Each map has its own entry types, so the root names them after the map
+pub use chaining::{
+    Entry as ChainingEntry, OccupiedEntry as ChainingOccupiedEntry,
+    VacantEntry as ChainingVacantEntry,
+};
+pub use error::{HashMapError, OccupiedError, TryReserveError};
+pub use hasher::{FixedSeedState, RandomState};
+pub use open_addressing::{
+    Entry as OpenAddressingEntry, OccupiedEntry as OpenAddressingOccupiedEntry,
+    VacantEntry as OpenAddressingVacantEntry,
+};
+pub use open_addressing_compact::{
+    Entry as CompactEntry, OccupiedEntry as CompactOccupiedEntry, VacantEntry as CompactVacantEntry,
+};
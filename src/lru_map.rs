@@ -0,0 +1,207 @@
+//  A fixed-size cache that evicts the least recently used entry. The map
+//  holds each key's index into `nodes`, which form a doubly linked recency
+//  list through indices, most recent at the head. Both `get` and `insert`
+//  look the key up with a single probe
+use alloc::vec::Vec;
+
+use crate::open_addressing::{self, Entry, Key, Value};
+use crate::HashMapError;
+
+const NIL: usize = usize::MAX;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+pub struct LruMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    map: open_addressing::HashMap<K, usize>,
+    nodes: Vec<Node<K, V>>,
+    head: usize,
+    tail: usize,
+    max_entries: usize,
+}
+
+impl<K, V> LruMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new(max_entries: usize) -> Self {
+        assert!(max_entries > 0, "an LruMap must hold at least one entry");
+        Self {
+            map: open_addressing::HashMap::new(max_entries + 1),
+            nodes: Vec::with_capacity(max_entries + 1),
+            head: NIL,
+            tail: NIL,
+            max_entries,
+        }
+    }
+
+    //  Marks the entry as most recently used
+    pub fn get(&mut self, key: K) -> Option<V> {
+        let index = *self.map.get_ref(&key)?;
+        self.move_to_front(index);
+        Some(self.nodes[index].value.clone())
+    }
+
+    //  Inserts or updates `key` as the most recently used entry, evicting the
+    //  least recently used one if the map is over its limit
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        match self.map.entry(key.clone())? {
+            Entry::Occupied(entry) => {
+                let index = *entry.into_mut();
+                self.nodes[index].value = value;
+                self.move_to_front(index);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(self.nodes.len());
+                self.nodes.push(Node {
+                    key,
+                    value,
+                    prev: NIL,
+                    next: NIL,
+                });
+                self.push_front(self.nodes.len() - 1);
+                if self.nodes.len() > self.max_entries {
+                    self.evict()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    //  Drops the tail entry, filling its node slot with the last node so
+    //  `nodes` stays dense
+    fn evict(&mut self) -> Result<(), HashMapError> {
+        let index = self.tail;
+        self.unlink(index);
+        let last = self.nodes.len() - 1;
+        if index != last {
+            self.nodes.swap(index, last);
+            *self
+                .map
+                .get_mut(self.nodes[index].key.clone())
+                .expect("every node is in the map") = index;
+            self.relink(index);
+        }
+        let evicted = self.nodes.pop().expect("evict is only called when full");
+        self.map.delete(evicted.key)
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let Node { prev, next, .. } = self.nodes[index];
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+
+    //  Points the neighbours of a node that was moved to `index` back at it
+    fn relink(&mut self, index: usize) {
+        let Node { prev, next, .. } = self.nodes[index];
+        match prev {
+            NIL => self.head = index,
+            prev => self.nodes[prev].next = index,
+        }
+        match next {
+            NIL => self.tail = index,
+            next => self.nodes[next].prev = index,
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        self.nodes[index].prev = NIL;
+        self.nodes[index].next = self.head;
+        match self.head {
+            NIL => self.tail = index,
+            head => self.nodes[head].prev = index,
+        }
+        self.head = index;
+    }
+
+    fn move_to_front(&mut self, index: usize) {
+        if self.head != index {
+            self.unlink(index);
+            self.push_front(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_eviction_order() {
+        let mut cache: LruMap<i32, &str> = LruMap::new(3);
+        cache.insert(1, "one").unwrap();
+        cache.insert(2, "two").unwrap();
+        cache.insert(3, "three").unwrap();
+
+        //  1 becomes the most recent, so 2 is the oldest
+        assert_eq!(cache.get(1), Some("one"));
+        cache.insert(4, "four").unwrap();
+        assert_eq!(cache.get(2), None);
+
+        //  updating 3 refreshes it, leaving 1 as the oldest
+        cache.insert(3, "THREE").unwrap();
+        cache.insert(5, "five").unwrap();
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(3), Some("THREE"));
+        assert_eq!(cache.get(4), Some("four"));
+        assert_eq!(cache.get(5), Some("five"));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_len_never_exceeds_cap() {
+        let mut rng = rand::thread_rng();
+        let mut cache: LruMap<u32, u32> = LruMap::new(10);
+        //  keys in recency order, most recent last
+        let mut model: Vec<u32> = Vec::new();
+        for _ in 0..5_000 {
+            let key = rng.gen_range(0..40);
+            if rng.gen_bool(0.5) {
+                cache.insert(key, key * 2).unwrap();
+                model.retain(|&k| k != key);
+                model.push(key);
+                if model.len() > 10 {
+                    model.remove(0);
+                }
+            } else {
+                let expected = model.contains(&key).then_some(key * 2);
+                assert_eq!(cache.get(key), expected);
+                if expected.is_some() {
+                    model.retain(|&k| k != key);
+                    model.push(key);
+                }
+            }
+            assert!(cache.len() <= 10);
+            assert_eq!(cache.len(), model.len());
+        }
+    }
+}
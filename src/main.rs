@@ -1,23 +1,32 @@
 #![allow(unused_imports)]
+use anyhow::{bail, Context};
 use clap::Parser;
 
-mod workloads;
+use hashmap::{chaining, open_addressing, open_addressing_compact, FixedSeedState};
 
-use hashmap::{chaining, open_addressing, open_addressing_compact};
-
-use crate::workloads::generators::{
-    run_key_distribution_workload_integers, run_load_factor_workload,
-    run_load_factor_workload_integers, run_operation_mix_workload,
+use hashmap::workloads::generators::{
+    run_adversarial_workload, run_churn_workload, run_key_distribution_workload_integers,
+    run_load_factor_workload, run_load_factor_workload_with_latency, run_operation_mix_workload,
+    run_operation_mix_workload_with_latency,
+};
+use hashmap::workloads::{
+    adversarial_keys, ChurnWorkload, HashMapBehavior, KeyDistributionWorkload, KeyPattern,
+    LoadFactorWorkload, OperationMixWorkload, WorkloadStats,
 };
-use crate::workloads::{KeyDistributionWorkload, LoadFactorWorkload, OperationMixWorkload};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
 
 #[derive(Parser, Debug)]
 struct Args {
-    #[arg(short, long)]
-    workload: String,
+    #[arg(short, long, required_unless_present = "list")]
+    workload: Option<String>,
 
-    #[arg(short, long)]
-    implementation: String,
+    #[arg(short, long, required_unless_present = "list")]
+    implementation: Option<String>,
 
     #[arg(short, long)]
     #[arg(requires = "workload")]
@@ -28,131 +37,607 @@ struct Args {
     #[arg(requires = "workload")]
     #[arg(required_if_eq("workload", "operation_mix"))]
     op_mix: Option<String>,
+
+    // Number of entries to load (initial entries for operation_mix and churn)
+    #[arg(short, long, value_parser = parse_positive)]
+    size: Option<usize>,
+
+    // Hash key_distribution keys with the fixed seed 0, which matches std's
+    // DefaultHasher::new(), instead of a random seed. The adversarial keys
+    // only collide with this set
+    #[arg(long)]
+    fixed_seed: bool,
+
+    // Keys operation_mix draws from, never fewer than --size. Keys past --size
+    // start out absent, so reads miss and writes grow the map
+    #[arg(long, value_parser = parse_positive)]
+    key_space: Option<usize>,
+
+    // Number of operations for operation_mix, or rounds for churn
+    #[arg(short = 'n', long, value_parser = parse_positive)]
+    operations: Option<usize>,
+
+    #[arg(short, long, value_parser = parse_positive, default_value_t = 100)]
+    value_size: usize,
+
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    // Time every operation and report the p50, p99 and p99.9 latency. Only
+    // load_factor and operation_mix support it
+    #[arg(long)]
+    latency: bool,
+
+    // Print the supported workloads, implementations and patterns, then exit
+    #[arg(short, long)]
+    list: bool,
 }
 
-fn main() {
-    let args = Args::parse();
+fn parse_positive(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("must be greater than 0".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
-    match args.workload.as_str() {
-        "load_factor" => {
-            match args.implementation.as_str() {
-                "chaining" => run_load_factor_workload::<chaining::HashMap<String, String>>(
-                    &LoadFactorWorkload {
-                        size: 10_000_000,
-                        value_size: 100,
-                    },
-                ),
-                "open_addressing" => run_load_factor_workload::<
-                    open_addressing::HashMap<String, String>,
-                >(&LoadFactorWorkload {
-                    size: 10_000_000,
-                    value_size: 100,
-                }),
-                "open_addressing_compact" => run_load_factor_workload::<
-                    open_addressing_compact::HashMap<String, String>,
-                >(&LoadFactorWorkload {
-                    size: 10_000_000,
-                    value_size: 100,
-                }),
-                _ => panic!("invalid implementation called for workload of load_factor"),
-            }
+//  A command line option with a fixed set of named values
+trait NamedOption: Sized + Copy + 'static {
+    const KIND: &'static str;
+    const ALL: &'static [Self];
+
+    fn name(&self) -> &'static str;
+
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        if let Some(option) = Self::ALL.iter().find(|option| option.name() == s) {
+            return Ok(*option);
         }
-        // "load_factor" => match args.implementation.as_str() {
-        //     "chaining" => run_load_factor_workload_integers::<chaining::HashMap<u64, u64>>(
-        //         &LoadFactorWorkload {
-        //             size: 10_000_000,
-        //             value_size: 100,
-        //         },
-        //     ),
-        //     "open_addressing" => run_load_factor_workload_integers::<
-        //         open_addressing::HashMap<u64, u64>,
-        //     >(&LoadFactorWorkload {
-        //         size: 10_000_000,
-        //         value_size: 100,
-        //     }),
-        //     "open_addressing_compact" => run_load_factor_workload_integers::<
-        //         open_addressing_compact::HashMap<u64, u64>,
-        //     >(&LoadFactorWorkload {
-        //         size: 10_000_000,
-        //         value_size: 100,
-        //     }),
-        //     _ => panic!("invalid implementation called for workload of load_factor"),
-        // },
-        "key_distribution" => {
-            let pattern = match args.key_dist.as_deref() {
-                Some("uniform") => workloads::KeyPattern::Uniform,
-                Some("clustered") => workloads::KeyPattern::Clustered,
-                Some("sequential") => workloads::KeyPattern::Sequential,
-                _ => {
-                    panic!("Invalid key distribution pattern");
-                }
+        let valid: Vec<&str> = Self::ALL.iter().map(|option| option.name()).collect();
+        bail!(
+            "unknown {} `{}`, valid options are: {}",
+            Self::KIND,
+            s,
+            valid.join(", ")
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Workload {
+    LoadFactor,
+    KeyDistribution,
+    OperationMix,
+    Churn,
+}
+
+impl NamedOption for Workload {
+    const KIND: &'static str = "workload";
+    const ALL: &'static [Self] = &[
+        Self::LoadFactor,
+        Self::KeyDistribution,
+        Self::OperationMix,
+        Self::Churn,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::LoadFactor => "load_factor",
+            Self::KeyDistribution => "key_distribution",
+            Self::OperationMix => "operation_mix",
+            Self::Churn => "churn",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Implementation {
+    Chaining,
+    OpenAddressing,
+    OpenAddressingCompact,
+}
+
+impl NamedOption for Implementation {
+    const KIND: &'static str = "implementation";
+    const ALL: &'static [Self] = &[
+        Self::Chaining,
+        Self::OpenAddressing,
+        Self::OpenAddressingCompact,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Chaining => "chaining",
+            Self::OpenAddressing => "open_addressing",
+            Self::OpenAddressingCompact => "open_addressing_compact",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum KeyDistribution {
+    Uniform,
+    Clustered,
+    Sequential,
+    Zipfian,
+    Gaussian,
+    //  Keys crafted to collide under the fixed seed, see `adversarial_keys`
+    Adversarial,
+}
+
+impl NamedOption for KeyDistribution {
+    const KIND: &'static str = "key distribution";
+    const ALL: &'static [Self] = &[
+        Self::Uniform,
+        Self::Clustered,
+        Self::Sequential,
+        Self::Zipfian,
+        Self::Gaussian,
+        Self::Adversarial,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Uniform => "uniform",
+            Self::Clustered => "clustered",
+            Self::Sequential => "sequential",
+            Self::Zipfian => "zipfian",
+            Self::Gaussian => "gaussian",
+            Self::Adversarial => "adversarial",
+        }
+    }
+}
+
+impl KeyDistribution {
+    //  None for adversarial keys, which are built up front rather than
+    //  generated while the clock runs
+    fn pattern(&self, size: usize) -> Option<KeyPattern> {
+        Some(match self {
+            Self::Uniform => KeyPattern::Uniform,
+            Self::Clustered => KeyPattern::Clustered,
+            Self::Sequential => KeyPattern::Sequential,
+            Self::Zipfian => KeyPattern::Zipfian { s: 1.0 },
+            Self::Gaussian => KeyPattern::Gaussian {
+                mean: size as f64 / 2.0,
+                stddev: size as f64 / 20.0,
+            },
+            Self::Adversarial => return None,
+        })
+    }
+}
+
+//  Zero bits every adversarial key's hash ends in. Each key then shares its
+//  slot with about 256 times as many others as it should
+const ADVERSARIAL_ZERO_BITS: u32 = 8;
+
+fn measure_key_distribution<M: HashMapBehavior<u64, u64>>(
+    key_dist: KeyDistribution,
+    size: usize,
+) -> WorkloadStats {
+    match key_dist.pattern(size) {
+        Some(pattern) => {
+            let workload = KeyDistributionWorkload { size, pattern };
+            WorkloadStats::measure(size, || {
+                run_key_distribution_workload_integers::<M>(&workload)
+            })
+        }
+        None => {
+            let keys = adversarial_keys(size, ADVERSARIAL_ZERO_BITS, |i| i);
+            //  every key is inserted and then looked up
+            WorkloadStats::measure(2 * size, || run_adversarial_workload::<M>(&keys))
+        }
+    }
+}
+
+fn measure_load_factor<M: HashMapBehavior<String, String>>(
+    workload: &LoadFactorWorkload,
+    latency: bool,
+) -> WorkloadStats {
+    if latency {
+        return run_load_factor_workload_with_latency::<M>(workload);
+    }
+    WorkloadStats::measure(workload.size, || run_load_factor_workload::<M>(workload))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OperationMix {
+    ReadHeavy,
+    WriteHeavy,
+    Balanced,
+    TypicalWeb,
+    UpdateHeavy,
+}
+
+impl NamedOption for OperationMix {
+    const KIND: &'static str = "operation mix";
+    const ALL: &'static [Self] = &[
+        Self::ReadHeavy,
+        Self::WriteHeavy,
+        Self::Balanced,
+        Self::TypicalWeb,
+        Self::UpdateHeavy,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::ReadHeavy => "read_heavy",
+            Self::WriteHeavy => "write_heavy",
+            Self::Balanced => "balanced",
+            Self::TypicalWeb => "typical_web",
+            Self::UpdateHeavy => "update_heavy",
+        }
+    }
+}
+
+impl OperationMix {
+    //  (read_pct, write_pct, update_pct)
+    fn percentages(&self) -> (u8, u8, u8) {
+        match self {
+            Self::ReadHeavy => (90, 5, 0),
+            Self::WriteHeavy => (5, 90, 0),
+            Self::Balanced => (33, 33, 0),
+            Self::TypicalWeb => (80, 15, 0),
+            Self::UpdateHeavy => (20, 10, 65),
+        }
+    }
+}
+
+fn measure_operation_mix<M: HashMapBehavior<String, String>>(
+    workload: &OperationMixWorkload,
+    latency: bool,
+) -> WorkloadStats {
+    if latency {
+        return run_operation_mix_workload_with_latency::<M>(workload);
+    }
+    let operations = workload.initial_size + workload.operations;
+    WorkloadStats::measure(operations, || run_operation_mix_workload::<M>(workload))
+}
+
+fn option_line<T: NamedOption>() -> String {
+    let names: Vec<&str> = T::ALL.iter().map(|option| option.name()).collect();
+    format!("{}: {}", T::KIND, names.join(", "))
+}
+
+fn listing() -> String {
+    [
+        option_line::<Workload>(),
+        option_line::<Implementation>(),
+        option_line::<KeyDistribution>(),
+        option_line::<OperationMix>(),
+    ]
+    .join("\n")
+}
+
+fn run(args: &Args) -> anyhow::Result<WorkloadStats> {
+    let implementation = args
+        .implementation
+        .as_deref()
+        .context("--implementation is required")?;
+    let implementation = Implementation::parse(implementation)?;
+    let workload = args.workload.as_deref().context("--workload is required")?;
+    let workload = Workload::parse(workload)?;
+    if args.latency && !matches!(workload, Workload::LoadFactor | Workload::OperationMix) {
+        bail!("--latency is only supported by the load_factor and operation_mix workloads");
+    }
+
+    let stats = match workload {
+        Workload::LoadFactor => {
+            let workload = LoadFactorWorkload {
+                size: args.size.unwrap_or(10_000_000),
+                value_size: args.value_size,
+                preallocate: false,
             };
 
-            match args.implementation.as_str() {
-                "chaining" => {
-                    run_key_distribution_workload_integers::<chaining::HashMap<u64, u64>>(
-                        &KeyDistributionWorkload {
-                            size: 10_000_000,
-                            pattern,
-                        },
+            match implementation {
+                Implementation::Chaining => {
+                    measure_load_factor::<chaining::HashMap<String, String>>(
+                        &workload,
+                        args.latency,
                     )
                 }
+                Implementation::OpenAddressing => measure_load_factor::<
+                    open_addressing::HashMap<String, String>,
+                >(&workload, args.latency),
+                Implementation::OpenAddressingCompact => measure_load_factor::<
+                    open_addressing_compact::HashMap<String, String>,
+                >(&workload, args.latency),
+            }
+        }
+        Workload::KeyDistribution => {
+            let size = args.size.unwrap_or(10_000_000);
+            let key_dist = args
+                .key_dist
+                .as_deref()
+                .context("key_distribution requires --key-dist")?;
+            let key_dist = KeyDistribution::parse(key_dist)?;
 
-                "open_addressing" => run_key_distribution_workload_integers::<
-                    open_addressing::HashMap<u64, u64>,
-                >(&KeyDistributionWorkload {
-                    size: 10_000_000,
-                    pattern,
-                }),
-                "open_addressing_compact" => run_key_distribution_workload_integers::<
+            match (implementation, args.fixed_seed) {
+                (Implementation::Chaining, false) => {
+                    measure_key_distribution::<chaining::HashMap<u64, u64>>(key_dist, size)
+                }
+                (Implementation::Chaining, true) => measure_key_distribution::<
+                    chaining::HashMap<u64, u64, FixedSeedState>,
+                >(key_dist, size),
+                (Implementation::OpenAddressing, false) => {
+                    measure_key_distribution::<open_addressing::HashMap<u64, u64>>(key_dist, size)
+                }
+                (Implementation::OpenAddressing, true) => measure_key_distribution::<
+                    open_addressing::HashMap<u64, u64, FixedSeedState>,
+                >(key_dist, size),
+                (Implementation::OpenAddressingCompact, false) => measure_key_distribution::<
                     open_addressing_compact::HashMap<u64, u64>,
-                >(&KeyDistributionWorkload {
-                    size: 10_000_000,
-                    pattern,
-                }),
-
-                _ => panic!("invalid implementation"),
+                >(key_dist, size),
+                (Implementation::OpenAddressingCompact, true) => measure_key_distribution::<
+                    open_addressing_compact::HashMap<u64, u64, FixedSeedState>,
+                >(key_dist, size),
             }
         }
-        "operation_mix" => {
-            let (read_pct, write_pct) = match args.op_mix.as_deref() {
-                Some("read_heavy") => (90, 5),
-                Some("write_heavy") => (5, 90),
-                Some("balanced") => (33, 33),
-                Some("typica_web") => (80, 15),
-                _ => {
-                    panic!("Invalid operation mix pattern")
-                }
+        Workload::OperationMix => {
+            let op_mix = args
+                .op_mix
+                .as_deref()
+                .context("operation_mix requires --op-mix")?;
+            let (read_pct, write_pct, update_pct) = OperationMix::parse(op_mix)?.percentages();
+
+            let initial_size = args.size.unwrap_or(1000);
+            let workload = OperationMixWorkload {
+                initial_size,
+                key_space: args.key_space.unwrap_or(initial_size).max(initial_size),
+                operations: args.operations.unwrap_or(1000),
+                read_pct,
+                write_pct,
+                update_pct,
+                value_size: args.value_size,
             };
 
-            match args.implementation.as_str() {
-                "chaining" => run_operation_mix_workload::<chaining::HashMap<String, String>>(
-                    &OperationMixWorkload {
-                        initial_size: 1000,
-                        operations: 1000,
-                        read_pct,
-                        write_pct,
-                    },
-                ),
-                "open_addressing" => run_operation_mix_workload::<
+            match implementation {
+                Implementation::Chaining => measure_operation_mix::<
+                    chaining::HashMap<String, String>,
+                >(&workload, args.latency),
+                Implementation::OpenAddressing => measure_operation_mix::<
                     open_addressing::HashMap<String, String>,
-                >(&OperationMixWorkload {
-                    initial_size: 1000,
-                    operations: 1000,
-                    read_pct,
-                    write_pct,
-                }),
-                "open_addressing_compact" => run_operation_mix_workload::<
+                >(&workload, args.latency),
+                Implementation::OpenAddressingCompact => measure_operation_mix::<
                     open_addressing_compact::HashMap<String, String>,
-                >(&OperationMixWorkload {
-                    initial_size: 1000,
-                    operations: 1000,
-                    read_pct,
-                    write_pct,
+                >(&workload, args.latency),
+            }
+        }
+        Workload::Churn => {
+            let workload = ChurnWorkload {
+                initial_size: args.size.unwrap_or(100_000),
+                rounds: args.operations.unwrap_or(100),
+            };
+
+            // Each round deletes and re-inserts the whole key space
+            let operations = workload.initial_size * (1 + 2 * workload.rounds);
+            match implementation {
+                Implementation::Chaining => WorkloadStats::measure(operations, || {
+                    run_churn_workload::<chaining::HashMap<String, String>>(&workload)
+                }),
+                Implementation::OpenAddressing => WorkloadStats::measure(operations, || {
+                    run_churn_workload::<open_addressing::HashMap<String, String>>(&workload)
+                }),
+                Implementation::OpenAddressingCompact => WorkloadStats::measure(operations, || {
+                    run_churn_workload::<open_addressing_compact::HashMap<String, String>>(
+                        &workload,
+                    )
                 }),
-                _ => panic!("invalid implementation"),
             }
         }
-        _ => panic!("Invalid workload"),
     };
+    Ok(stats)
+}
+
+fn render(stats: &WorkloadStats, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => stats.to_string(),
+        OutputFormat::Json => stats.to_json(),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    if args.list {
+        println!("{}", listing());
+        return Ok(());
+    }
+    let stats = run(&args)?;
+    println!("{}", render(&stats, args.format));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_args_parse() {
+        let args = Args::try_parse_from([
+            "hashmap",
+            "-w",
+            "operation_mix",
+            "-i",
+            "chaining",
+            "-o",
+            "balanced",
+            "--size",
+            "50",
+            "--operations",
+            "200",
+            "--value-size",
+            "8",
+        ])
+        .unwrap();
+        assert_eq!(args.size, Some(50));
+        assert_eq!(args.operations, Some(200));
+        assert_eq!(args.value_size, 8);
+
+        let args =
+            Args::try_parse_from(["hashmap", "-w", "load_factor", "-i", "chaining"]).unwrap();
+        assert_eq!(args.size, None);
+        assert_eq!(args.value_size, 100);
+    }
+
+    #[test]
+    fn test_size_args_reject_zero() {
+        for flag in ["--size", "--operations", "--value-size"] {
+            let result =
+                Args::try_parse_from(["hashmap", "-w", "load_factor", "-i", "chaining", flag, "0"]);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_json_output() {
+        let args = Args::try_parse_from([
+            "hashmap",
+            "-w",
+            "load_factor",
+            "-i",
+            "chaining",
+            "-s",
+            "100",
+            "-f",
+            "json",
+        ])
+        .unwrap();
+        let output = render(&run(&args).unwrap(), args.format);
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        for key in [
+            "elapsed_secs",
+            "operations",
+            "throughput",
+            "final_size",
+            "final_capacity",
+            "resizes",
+        ] {
+            assert!(json.get(key).is_some(), "missing key {key}");
+        }
+        assert_eq!(json["final_size"], 100);
+        assert_eq!(json["resizes"], 4);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(Workload::parse("churn").unwrap(), Workload::Churn);
+        assert_eq!(
+            Implementation::parse("open_addressing").unwrap(),
+            Implementation::OpenAddressing
+        );
+        assert_eq!(
+            KeyDistribution::parse("zipfian").unwrap(),
+            KeyDistribution::Zipfian
+        );
+        assert_eq!(
+            OperationMix::parse("typical_web").unwrap(),
+            OperationMix::TypicalWeb
+        );
+    }
+
+    #[test]
+    fn test_parse_options_rejects_unknown_values() {
+        let err = Workload::parse("load").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "unknown workload `load`, valid options are: load_factor, key_distribution, operation_mix, churn"
+        );
+        let err = Implementation::parse("robin_hood").unwrap_err().to_string();
+        assert!(err.contains("unknown implementation `robin_hood`"));
+        assert!(err.contains("open_addressing_compact"));
+        let err = KeyDistribution::parse("bimodal").unwrap_err().to_string();
+        assert!(err.contains("uniform, clustered, sequential, zipfian, gaussian"));
+        let err = OperationMix::parse("typica_web").unwrap_err().to_string();
+        assert!(err.contains("typical_web"));
+    }
+
+    #[test]
+    fn test_key_distribution_small_size() {
+        for key_dist in KeyDistribution::ALL {
+            for implementation in Implementation::ALL {
+                let args = Args::try_parse_from([
+                    "hashmap",
+                    "-w",
+                    "key_distribution",
+                    "-i",
+                    implementation.name(),
+                    "-k",
+                    key_dist.name(),
+                    "-s",
+                    "5",
+                ])
+                .unwrap();
+                //  skewed patterns draw repeats, so fewer than 5 keys may land
+                let stats = run(&args).unwrap();
+                assert!((1..=5).contains(&stats.final_size), "{}", key_dist.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_listing() {
+        let args = Args::try_parse_from(["hashmap", "--list"]).unwrap();
+        assert!(args.list);
+
+        let listing = listing();
+        for name in Workload::ALL.iter().map(|option| option.name()) {
+            assert!(listing.contains(name));
+        }
+        for name in Implementation::ALL.iter().map(|option| option.name()) {
+            assert!(listing.contains(name));
+        }
+        for name in KeyDistribution::ALL.iter().map(|option| option.name()) {
+            assert!(listing.contains(name));
+        }
+        for name in OperationMix::ALL.iter().map(|option| option.name()) {
+            assert!(listing.contains(name));
+        }
+    }
+
+    #[test]
+    fn test_latency_flag() {
+        let args = Args::try_parse_from([
+            "hashmap",
+            "-w",
+            "operation_mix",
+            "-i",
+            "open_addressing",
+            "-o",
+            "balanced",
+            "-s",
+            "100",
+            "-n",
+            "1000",
+            "--latency",
+        ])
+        .unwrap();
+        let stats = run(&args).unwrap();
+        assert!(stats.latency.is_some());
+        let output = render(&stats, OutputFormat::Text);
+        assert!(output.contains("p50") && output.contains("p99") && output.contains("p99.9"));
+
+        let args = Args::try_parse_from([
+            "hashmap",
+            "-w",
+            "load_factor",
+            "-i",
+            "chaining",
+            "-s",
+            "100",
+            "--latency",
+        ])
+        .unwrap();
+        assert!(run(&args).unwrap().latency.is_some());
+
+        let args = Args::try_parse_from([
+            "hashmap",
+            "-w",
+            "churn",
+            "-i",
+            "chaining",
+            "-s",
+            "10",
+            "--latency",
+        ])
+        .unwrap();
+        assert!(run(&args).is_err());
+    }
 }
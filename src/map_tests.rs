@@ -0,0 +1,956 @@
+//  Tests that every map has to pass, written once and expanded inside each
+//  map's test module so `HashMap`, `Entry` and the other names resolve to
+//  that map's own types
+
+macro_rules! map_tests {
+    () => {
+        #[test]
+        fn test_get_mut() {
+            let mut map: HashMap<String, String> = HashMap::new(16);
+            map.insert("key".to_string(), "value".to_string()).unwrap();
+            map.get_mut("key".to_string()).unwrap().push_str("_updated");
+            assert_eq!(
+                map.get("key".to_string()),
+                Some("value_updated".to_string())
+            );
+            assert!(map.get_mut("missing".to_string()).is_none());
+        }
+
+        #[test]
+        fn test_memory_usage() {
+            let mut map: HashMap<u64, u64> = HashMap::new(16);
+            let empty_usage = map.memory_usage();
+            for i in 0..100 {
+                map.insert(i, i).unwrap();
+            }
+            let grown_usage = map.memory_usage();
+            assert!(map.capacity > 16);
+            assert!(grown_usage > empty_usage);
+
+            for i in 0..95 {
+                map.delete(i).unwrap();
+            }
+            map.shrink_to_fit();
+            assert_eq!(map.capacity, 16);
+            assert!(map.memory_usage() < grown_usage);
+            for i in 95..100 {
+                assert_eq!(map.get(i), Some(i));
+            }
+        }
+
+        #[test]
+        fn test_contains_key() {
+            let mut map: HashMap<u32, u32> = HashMap::new(16);
+            assert!(!map.contains_key(&1));
+            assert_eq!(map.get(1), None);
+            map.insert(1, 10).unwrap();
+            assert!(map.contains_key(&1));
+            assert_eq!(map.get(1), Some(10));
+            map.delete(1).unwrap();
+            assert!(!map.contains_key(&1));
+            assert_eq!(map.get_mut(1), None);
+        }
+
+        #[test]
+        fn test_get_many() {
+            let mut map: HashMap<u32, String> = HashMap::new(16);
+            for i in 0..10 {
+                map.insert(i, format!("value{i}")).unwrap();
+            }
+            let keys = [3, 42, 0, 9, 100];
+            let values = map.get_many(&keys);
+            assert_eq!(
+                values,
+                vec![
+                    Some(&"value3".to_string()),
+                    None,
+                    Some(&"value0".to_string()),
+                    Some(&"value9".to_string()),
+                    None,
+                ]
+            );
+        }
+
+        #[test]
+        fn test_insert_many() {
+            let pairs: Vec<(u32, u32)> = (0..10_000).map(|i| (i, i * 2)).collect();
+            let mut reserved: HashMap<u32, u32> = HashMap::new(16);
+            reserved.reserve(pairs.len());
+
+            //  the up-front reserve is the only growth, so the loop never
+            //  hits the load factor limit
+            let mut bulk: HashMap<u32, u32> = HashMap::new(16);
+            bulk.insert_many(pairs.clone()).unwrap();
+            assert_eq!(bulk.resize_count(), 0);
+            assert_eq!(bulk.capacity(), reserved.capacity());
+
+            let mut single: HashMap<u32, u32> = HashMap::new(16);
+            for (key, value) in pairs {
+                single.insert(key, value).unwrap();
+            }
+            assert_eq!(bulk.len(), single.len());
+            for i in 0..10_000 {
+                assert_eq!(bulk.get(i), single.get(i));
+            }
+        }
+
+        #[test]
+        fn test_get_or_insert_with_key() {
+            let mut map: HashMap<String, usize> = HashMap::new(16);
+            let value = map
+                .get_or_insert_with_key("hello".to_string(), |key| key.len())
+                .unwrap();
+            assert_eq!(*value, 5);
+            *value += 1;
+
+            let value = map
+                .get_or_insert_with_key("hello".to_string(), |_| unreachable!())
+                .unwrap();
+            assert_eq!(*value, 6);
+            assert_eq!(map.len(), 1);
+        }
+
+        #[test]
+        fn test_compute_if_present_and_absent() {
+            let mut map: HashMap<u32, u32> = HashMap::new(16);
+            map.insert(1, 10).unwrap();
+            map.insert(2, 20).unwrap();
+
+            //  present -> replace
+            assert_eq!(map.compute_if_present(1, |_, v| Some(v + 1)), Some(&mut 11));
+            assert_eq!(map.get(1), Some(11));
+
+            //  present -> remove
+            assert_eq!(map.compute_if_present(2, |_, _| None), None);
+            assert!(!map.contains_key(&2));
+            assert_eq!(map.len(), 1);
+
+            //  absent -> insert
+            assert_eq!(*map.compute_if_absent(3, |k| k * 100).unwrap(), 300);
+            assert_eq!(*map.compute_if_absent(3, |_| unreachable!()).unwrap(), 300);
+
+            //  absent -> no-op
+            assert_eq!(map.compute_if_present(4, |_, _| unreachable!()), None);
+            assert_eq!(map.len(), 2);
+        }
+
+        #[test]
+        fn test_increment_and_most_common() {
+            let text = "the cat and the dog and the bird saw a cat";
+            let mut counts: HashMap<&str, u32> = HashMap::new(16);
+            for word in text.split_whitespace() {
+                counts.increment(word).unwrap();
+            }
+            assert_eq!(counts.get("the"), Some(3));
+            assert_eq!(counts.get("bird"), Some(1));
+            let top = counts.most_common(3);
+            assert_eq!(top[0], ("the", 3));
+            let mut rest = vec![top[1], top[2]];
+            rest.sort();
+            assert_eq!(rest, vec![("and", 2), ("cat", 2)]);
+        }
+
+        #[test]
+        fn test_map_values() {
+            let mut map: HashMap<u32, u32> = HashMap::new(64);
+            for i in 0..20 {
+                map.insert(i, i * 10).unwrap();
+            }
+            map.delete(5).unwrap();
+
+            let mapped: HashMap<u32, String> = map.map_values(|v| format!("#{v}"));
+            assert_eq!(mapped.len(), map.len());
+            assert_eq!(mapped.capacity(), map.capacity());
+            for i in 0..20 {
+                assert_eq!(mapped.get(i), map.get(i).map(|v| format!("#{v}")));
+            }
+        }
+
+        #[test]
+        fn test_filter_map() {
+            let mut map: HashMap<u32, u32> = HashMap::new(16);
+            for i in 0..100 {
+                map.insert(i, i).unwrap();
+            }
+
+            let big: HashMap<u32, String> =
+                map.filter_map(|_, &v| (v > 89).then(|| format!("big{v}")));
+            assert_eq!(big.len(), 10);
+            for i in 0..100 {
+                let expected = (i > 89).then(|| format!("big{i}"));
+                assert_eq!(big.get(i), expected);
+            }
+        }
+
+        #[test]
+        fn test_append() {
+            let mut map: HashMap<u32, u32> = HashMap::new(16);
+            let mut other: HashMap<u32, u32> = HashMap::new(16);
+            for i in 0..50 {
+                map.insert(i, i).unwrap();
+            }
+            for i in 25..100 {
+                other.insert(i, i * 2).unwrap();
+            }
+            let other_capacity = other.capacity();
+
+            map.append(&mut other).unwrap();
+            assert!(other.is_empty());
+            assert_eq!(other.capacity(), other_capacity);
+            assert_eq!(other.get(30), None);
+
+            assert_eq!(map.len(), 100);
+            for i in 0..100 {
+                let expected = if i < 25 { i } else { i * 2 };
+                assert_eq!(map.get(i), Some(expected));
+            }
+        }
+
+        #[test]
+        fn test_split_off() {
+            let mut map: HashMap<u32, u32> = HashMap::new(16);
+            for i in 0..100 {
+                map.insert(i, i).unwrap();
+            }
+
+            let even = map.split_off(|k, _| k % 2 == 0);
+            assert_eq!(map.len(), 50);
+            assert_eq!(even.len(), 50);
+            for i in 0..100 {
+                assert_ne!(map.contains_key(&i), even.contains_key(&i));
+                assert_eq!(even.contains_key(&i), i % 2 == 0);
+            }
+        }
+
+        #[test]
+        fn test_try_insert() {
+            let mut map: HashMap<String, u32> = HashMap::new(16);
+            let value = map.try_insert("a".to_string(), 1).unwrap();
+            *value += 1;
+            assert_eq!(map.get("a".to_string()), Some(2));
+
+            let err = map.try_insert("a".to_string(), 10).unwrap_err();
+            assert_eq!(err.key, "a");
+            assert_eq!(err.existing, 2);
+            assert_eq!(err.value, 10);
+            assert_eq!(map.get("a".to_string()), Some(2));
+            assert_eq!(map.len(), 1);
+        }
+
+        #[test]
+        fn test_try_reserve() {
+            let mut map: HashMap<u32, u32> = HashMap::new(16);
+            map.insert(1, 1).unwrap();
+            map.try_reserve(100).unwrap();
+            assert!(map.capacity() >= 128);
+            assert_eq!(map.get(1), Some(1));
+
+            assert_eq!(
+                map.try_reserve(usize::MAX),
+                Err(TryReserveError::CapacityOverflow)
+            );
+            assert_eq!(
+                map.try_reserve(1 << 60),
+                Err(TryReserveError::CapacityOverflow)
+            );
+            assert_eq!(map.len(), 1);
+        }
+
+        #[cfg(feature = "std")]
+        #[test]
+        fn test_random_seed_per_map() {
+            //  each map draws its own keys, so the same insertions land in different slots
+            let mut first = HashMap::new(128);
+            let mut second = HashMap::new(128);
+            for i in 0..64 {
+                first.insert(i, i).unwrap();
+                second.insert(i, i).unwrap();
+            }
+            let first_order: Vec<i32> = first.iter().map(|(k, _)| *k).collect();
+            let second_order: Vec<i32> = second.iter().map(|(k, _)| *k).collect();
+            assert_ne!(first_order, second_order);
+            for i in 0..64 {
+                assert_eq!(first.get(i), second.get(i));
+            }
+        }
+
+        #[test]
+        fn test_growth_factor() {
+            let grow = |factor: f64| {
+                let mut map = HashMap::with_seed(16, 0);
+                map.set_growth_factor(factor);
+                for i in 0..10_000 {
+                    map.insert(i, i).unwrap();
+                }
+                map.check_invariants().unwrap();
+                for i in 0..10_000 {
+                    assert_eq!(map.get(i), Some(i));
+                }
+                map
+            };
+            let (doubling, gradual) = (grow(2.0), grow(1.5));
+            //  smaller steps mean more resizes but less headroom at the end
+            assert!(gradual.resize_count() > doubling.resize_count());
+            assert!(gradual.capacity() < doubling.capacity());
+        }
+
+        #[test]
+        fn test_prime_capacity_policy() {
+            let mut map = HashMap::with_seed(16, 0);
+            map.set_capacity_policy(CapacityPolicy::Prime);
+            assert_eq!(map.capacity(), 17);
+            for i in 0..10_000 {
+                map.insert(i, i).unwrap();
+                assert!(CapacityPolicy::is_listed_prime(map.capacity()));
+            }
+            for i in (0..10_000).step_by(3) {
+                map.delete(i).unwrap();
+            }
+            map.shrink_to_fit();
+            assert!(CapacityPolicy::is_listed_prime(map.capacity()));
+            map.check_invariants().unwrap();
+            for i in 0..10_000 {
+                assert_eq!(map.get(i), (i % 3 != 0).then_some(i));
+            }
+        }
+
+        #[test]
+        fn test_edge_case_capacities() {
+            for (requested, expected) in [(0, 16), (1, 16), (16, 16), (17, 32), (usize::MAX, 16)] {
+                let mut map = HashMap::new(requested);
+                assert_eq!(map.capacity(), expected, "new({requested})");
+                map.insert(1, 1).unwrap();
+                assert_eq!(map.get(1), Some(1));
+            }
+            //  rounds to a power of two that would not fit in isize::MAX bytes
+            let map: HashMap<u64, u64> = HashMap::new(usize::MAX / 4);
+            assert_eq!(map.capacity(), 16);
+        }
+
+        #[test]
+        fn test_on_resize() {
+            use std::sync::{Arc, Mutex};
+
+            let resizes = Arc::new(Mutex::new(Vec::new()));
+            let log = Arc::clone(&resizes);
+            let mut map = HashMap::new(16);
+            map.on_resize(move |old, new| log.lock().unwrap().push((old, new)));
+            //  16 * 0.7 = 11.2, so the table grows on the 13th insert and again on the 24th
+            for i in 0..24 {
+                map.insert(i, i).unwrap();
+            }
+            assert_eq!(*resizes.lock().unwrap(), vec![(16, 32), (32, 64)]);
+            assert_eq!(map.resize_count(), 2);
+        }
+
+        #[test]
+        fn test_fingerprint() {
+            let mut forward = HashMap::new(16);
+            for i in 0..100 {
+                forward.insert(i, i * 3).unwrap();
+            }
+            //  different seed, capacity and insertion order, same entries
+            let mut backward = HashMap::new(256);
+            for i in (0..100).rev() {
+                backward.insert(i, i * 3).unwrap();
+            }
+            assert!(forward == backward);
+            assert_eq!(forward.fingerprint(), backward.fingerprint());
+
+            *backward.get_mut(42).unwrap() += 1;
+            assert!(forward != backward);
+            assert_ne!(forward.fingerprint(), backward.fingerprint());
+            assert_eq!(HashMap::<u32, u32>::new(16).fingerprint(), 0);
+        }
+
+        #[test]
+        fn test_pop_drains_every_entry() {
+            let mut map = HashMap::new(16);
+            for i in 0..10 {
+                map.insert(i, i * 10).unwrap();
+            }
+            let mut popped = Vec::new();
+            while let Some(pair) = map.pop() {
+                popped.push(pair);
+            }
+            popped.sort();
+            assert_eq!(popped, (0..10).map(|i| (i, i * 10)).collect::<Vec<_>>());
+            assert!(map.is_empty());
+            assert_eq!(map.pop(), None);
+            map.check_invariants().unwrap();
+        }
+
+        #[test]
+        fn test_iter_sorted() {
+            let mut map = HashMap::new(16);
+            for key in ["pear", "apple", "fig", "banana"] {
+                map.insert(key.to_string(), key.len()).unwrap();
+            }
+            let sorted: Vec<(&str, usize)> = map
+                .iter_sorted()
+                .into_iter()
+                .map(|(k, v)| (k.as_str(), *v))
+                .collect();
+            assert_eq!(
+                sorted,
+                vec![("apple", 5), ("banana", 6), ("fig", 3), ("pear", 4)]
+            );
+
+            for i in 0..200 {
+                map.insert(format!("key_{i}"), i).unwrap();
+            }
+            let mut reference: Vec<(&String, &usize)> = map.iter().collect();
+            reference.sort();
+            assert_eq!(map.iter_sorted(), reference);
+        }
+
+        #[test]
+        fn test_retain_count() {
+            let mut map = HashMap::new(16);
+            for i in 0..100 {
+                map.insert(i, i).unwrap();
+            }
+            for i in 0..10 {
+                map.delete(i).unwrap();
+            }
+            assert_eq!(map.retain_count(|k, _| k % 2 == 0), 45);
+            assert_eq!(map.len(), 45);
+            assert_eq!(map.retain_count(|k, _| k % 2 == 0), 0);
+            map.retain(|_, v| *v < 50);
+            assert_eq!(map.len(), 20);
+            for i in 0..100 {
+                let kept = (10..50).contains(&i) && i % 2 == 0;
+                assert_eq!(map.get(i), kept.then_some(i));
+            }
+            map.check_invariants().unwrap();
+        }
+
+        #[test]
+        fn test_delete_many() {
+            let mut map = HashMap::new(16);
+            for i in 0..100 {
+                map.insert(i, i).unwrap();
+            }
+            let keys: Vec<i32> = (50..150).collect();
+            assert_eq!(map.delete_many(&keys), 50);
+            assert_eq!(map.len(), 50);
+            assert_eq!(map.delete_many(&[0, 0, 200]), 1);
+            assert_eq!(map.len(), 49);
+            assert_eq!(map.get(0), None);
+            assert_eq!(map.get(1), Some(1));
+            map.check_invariants().unwrap();
+        }
+
+        #[test]
+        fn test_contains_value() {
+            let mut map = HashMap::new(16);
+            for i in 0..20 {
+                map.insert(i, format!("value{i}")).unwrap();
+            }
+            assert!(map.contains_value(&"value7".to_string()));
+            assert!(!map.contains_value(&"value20".to_string()));
+            map.delete(7).unwrap();
+            assert!(!map.contains_value(&"value7".to_string()));
+            map.insert(3, "value7".to_string()).unwrap();
+            assert!(map.contains_value(&"value7".to_string()));
+            assert!(!map.contains_value(&"value3".to_string()));
+        }
+
+        #[test]
+        fn test_entry_methods() {
+            let mut map: HashMap<i32, String> = HashMap::new(16);
+            map.insert(1, "one".to_string()).unwrap();
+
+            let Entry::Occupied(mut entry) = map.entry(1).unwrap() else {
+                panic!("key 1 is in the map");
+            };
+            assert_eq!(*entry.key(), 1);
+            assert_eq!(entry.get(), "one");
+            entry.get_mut().push('!');
+            assert_eq!(entry.insert("uno".to_string()), "one!");
+            assert_eq!(entry.into_mut(), "uno");
+
+            let Entry::Vacant(entry) = map.entry(2).unwrap() else {
+                panic!("key 2 is not in the map");
+            };
+            assert_eq!(*entry.key(), 2);
+            entry.insert("two".to_string()).push('!');
+            assert_eq!(map.get_ref(&2).map(String::as_str), Some("two!"));
+            assert_eq!(map.len(), 2);
+
+            //  removing through an entry matches `delete`
+            let Entry::Occupied(entry) = map.entry(1).unwrap() else {
+                panic!("key 1 is in the map");
+            };
+            assert_eq!(entry.remove(), "uno");
+            assert!(!map.contains_key(&1));
+            assert_eq!(map.len(), 1);
+            map.check_invariants().unwrap();
+        }
+
+        #[test]
+        fn test_snapshot_keeps_old_contents() {
+            let mut map: HashMap<i32, i32> = HashMap::new(16);
+            for i in 0..10 {
+                map.insert(i, i).unwrap();
+            }
+            let snapshot = map.snapshot();
+            let shared = Arc::clone(&snapshot);
+
+            for i in 0..5 {
+                map.delete(i).unwrap();
+            }
+            for i in 5..100 {
+                map.insert(i, -i).unwrap();
+            }
+
+            //  the live map moved on, through a resize, but the snapshot did not
+            assert_eq!(map.len(), 95);
+            assert_eq!(shared.len(), 10);
+            for i in 0..10 {
+                assert_eq!(shared.get_ref(&i), Some(&i));
+            }
+            assert!(!shared.contains_key(&50));
+        }
+
+        #[test]
+        fn test_entry_or_default_groups_values() {
+            let mut groups: HashMap<u32, Vec<u32>> = HashMap::new(16);
+            for n in 0..20 {
+                groups.entry(n % 3).unwrap().or_default().push(n);
+            }
+            assert_eq!(groups.len(), 3);
+            assert_eq!(groups.get_ref(&0), Some(&vec![0, 3, 6, 9, 12, 15, 18]));
+            assert_eq!(groups.get_ref(&2).map(Vec::len), Some(6));
+        }
+
+        #[test]
+        fn test_entry_and_modify_counts() {
+            let mut counts: HashMap<char, u32> = HashMap::new(16);
+            for c in "mississippi".chars() {
+                counts
+                    .entry(c)
+                    .unwrap()
+                    .and_modify(|n| *n += 1)
+                    .or_insert(1);
+            }
+            assert_eq!(counts.get_ref(&'m'), Some(&1));
+            assert_eq!(counts.get_ref(&'i'), Some(&4));
+            assert_eq!(counts.get_ref(&'s'), Some(&4));
+            assert_eq!(counts.get_ref(&'p'), Some(&2));
+            assert_eq!(counts.len(), 4);
+        }
+
+        #[test]
+        fn test_intern_returns_the_stored_key() {
+            let mut strings: HashMap<Arc<str>, ()> = HashMap::new(16);
+            let first = strings.intern(Arc::from("hash")).unwrap();
+            let second = strings.intern(Arc::from("hash")).unwrap();
+            let other = strings.intern(Arc::from("map")).unwrap();
+
+            assert_eq!(first, second);
+            //  the second lookup handed back the first allocation, not its own
+            assert!(Arc::ptr_eq(&first, &second));
+            assert!(!Arc::ptr_eq(&first, &other));
+            assert_eq!(strings.len(), 2);
+            let (stored, _) = strings.get_key_value(&Arc::from("hash")).unwrap();
+            assert!(Arc::ptr_eq(stored, &first));
+        }
+
+        #[test]
+        fn test_occupied_entry_key_is_the_stored_key() {
+            let mut strings: HashMap<Arc<str>, u32> = HashMap::new(16);
+            let stored: Arc<str> = Arc::from("hash");
+            strings.insert(stored.clone(), 1).unwrap();
+
+            let lookup: Arc<str> = Arc::from("hash");
+            let Entry::Occupied(entry) = strings.entry(lookup.clone()).unwrap() else {
+                panic!("the key is in the map");
+            };
+            assert!(Arc::ptr_eq(entry.key(), &stored));
+            assert!(!Arc::ptr_eq(entry.key(), &lookup));
+            assert_eq!(entry.remove(), 1);
+            assert!(strings.is_empty());
+        }
+
+        #[test]
+        fn test_union_intersection_difference() {
+            let mut left = HashMap::new(16);
+            let mut right = HashMap::new(16);
+            for i in 0..10 {
+                left.insert(i, "left").unwrap();
+            }
+            for i in 5..20 {
+                right.insert(i, "right").unwrap();
+            }
+            let sorted = |map: &HashMap<i32, &'static str>| {
+                let mut entries: Vec<(i32, &str)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+                entries.sort();
+                entries
+            };
+
+            //  the left value wins for the keys both maps hold
+            let union = left.union(&right);
+            assert_eq!(union.len(), 20);
+            assert_eq!(union.get_ref(&7), Some(&"left"));
+            assert_eq!(union.get_ref(&15), Some(&"right"));
+
+            let expected: Vec<(i32, &str)> = (5..10).map(|i| (i, "left")).collect();
+            assert_eq!(sorted(&left.intersection(&right)), expected);
+            let expected: Vec<(i32, &str)> = (5..10).map(|i| (i, "right")).collect();
+            assert_eq!(sorted(&right.intersection(&left)), expected);
+
+            let expected: Vec<(i32, &str)> = (0..5).map(|i| (i, "left")).collect();
+            assert_eq!(sorted(&left.difference(&right)), expected);
+            assert_eq!(right.difference(&left).len(), 10);
+        }
+
+        #[test]
+        fn test_symmetric_difference() {
+            let mut before = HashMap::new(16);
+            let mut after = HashMap::new(16);
+            for i in 0..10 {
+                before.insert(i, i).unwrap();
+            }
+            for i in 5..15 {
+                after.insert(i, i * 10).unwrap();
+            }
+
+            let changed = before.symmetric_difference(&after);
+            let mut entries: Vec<(i32, i32)> = changed.iter().map(|(k, v)| (*k, *v)).collect();
+            entries.sort();
+            //  the shared keys 5..10 drop out, whatever their values
+            let expected: Vec<(i32, i32)> = (0..5)
+                .map(|i| (i, i))
+                .chain((10..15).map(|i| (i, i * 10)))
+                .collect();
+            assert_eq!(entries, expected);
+        }
+
+        #[test]
+        fn test_display() {
+            let mut map = HashMap::new(16);
+            assert_eq!(format!("{}", map), "{}");
+            map.insert(1, "one").unwrap();
+            map.insert(2, "two").unwrap();
+            let rendered = format!("{}", map);
+            assert!(
+                rendered == "{1: one, 2: two}" || rendered == "{2: two, 1: one}",
+                "{rendered}"
+            );
+        }
+
+        #[test]
+        fn test_into_mut_outlives_the_entry() {
+            let mut map: HashMap<u32, Vec<u32>> = HashMap::new(16);
+            let values = map.entry(1).unwrap().or_insert_with(|| vec![0]);
+            values.push(1);
+            values.push(2);
+
+            let Entry::Occupied(entry) = map.entry(1).unwrap() else {
+                panic!("key 1 was just inserted");
+            };
+            //  the entry is gone but the reference keeps the map borrowed
+            let values = entry.into_mut();
+            values.push(3);
+            values.retain(|&v| v != 2);
+            values.push(4);
+            assert_eq!(map.get(1), Some(vec![0, 1, 3, 4]));
+        }
+
+        #[test]
+        fn test_extend_reserves_once() {
+            let mut map = HashMap::new(16);
+            map.extend((0..10_000).map(|i| (i, i * 2)));
+            assert!(map.resize_count() <= 1);
+            assert_eq!(map.len(), 10_000);
+            assert_eq!(map.get(9_999), Some(19_998));
+
+            //  a filter hides the length, so the map grows as it goes
+            let mut unhinted = HashMap::new(16);
+            unhinted.extend((0..10_000).filter(|_| true).map(|i| (i, i)));
+            assert!(unhinted.resize_count() > 1);
+            assert_eq!(unhinted.len(), 10_000);
+        }
+
+        #[test]
+        fn test_reset_shrinks_to_base_capacity() {
+            let mut map = HashMap::new(16);
+            for i in 0..5_000 {
+                map.insert(i, i.to_string()).unwrap();
+            }
+            assert!(map.capacity() > 16);
+            map.reset();
+            assert_eq!(map.capacity(), 16);
+            assert_eq!(map.len(), 0);
+            assert_eq!(map.get(10), None);
+
+            map.insert(10, "ten".to_string()).unwrap();
+            assert_eq!(map.get(10), Some("ten".to_string()));
+            assert_eq!(map.len(), 1);
+        }
+
+        #[test]
+        fn test_delete_twice_and_from_empty() {
+            let mut map = HashMap::new(16);
+            map.delete(1).unwrap();
+            assert_eq!(map.len(), 0);
+
+            map.insert(1, 10).unwrap();
+            map.insert(2, 20).unwrap();
+            map.delete(1).unwrap();
+            map.delete(1).unwrap();
+            assert_eq!(map.len(), 1);
+            assert_eq!(map.get(2), Some(20));
+
+            map.delete(2).unwrap();
+            map.delete(2).unwrap();
+            map.delete(3).unwrap();
+            assert_eq!(map.len(), 0);
+            assert!(map.is_empty());
+        }
+
+        #[test]
+        fn test_compute_if_present_survives_a_panic() {
+            let mut map = HashMap::with_seed(16, 0);
+            for i in 0..200 {
+                map.insert(i, i).unwrap();
+            }
+            for i in (0..200).step_by(7) {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    map.compute_if_present(i, |_, _| panic!("compute failed"));
+                }));
+                assert!(result.is_err());
+            }
+            //  the panicking entries are gone and everything else is still reachable
+            map.check_invariants().unwrap();
+            assert_eq!(map.len(), 200 - 29);
+            for i in 0..200 {
+                assert_eq!(map.get(i), (i % 7 != 0).then_some(i));
+            }
+            assert_eq!(map.compute_if_present(1, |_, v| Some(v + 1)), Some(&mut 2));
+            map.check_invariants().unwrap();
+        }
+
+        #[test]
+        fn test_split_off_survives_a_panicking_predicate() {
+            let mut map = HashMap::with_seed(16, 0);
+            for i in 0..200 {
+                map.insert(i, i).unwrap();
+            }
+            let mut calls = 0;
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                map.split_off(|_, _| {
+                    calls += 1;
+                    assert!(calls < 100, "predicate gave up");
+                    true
+                })
+            }));
+            assert!(result.is_err());
+            //  the 99 entries split before the panic are gone, the rest still reachable
+            map.check_invariants().unwrap();
+            assert_eq!(map.len(), 101);
+            assert_eq!((0..200).filter(|&i| map.get(i) == Some(i)).count(), 101);
+        }
+
+        #[test]
+        fn test_retain_survives_a_panicking_predicate() {
+            let mut map = HashMap::with_seed(16, 0);
+            for i in 0..200 {
+                map.insert(i, i).unwrap();
+            }
+            let mut calls = 0;
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                map.retain(|_, _| {
+                    calls += 1;
+                    assert!(calls < 100, "predicate gave up");
+                    false
+                })
+            }));
+            assert!(result.is_err());
+            //  the 99 entries dropped before the panic are gone, the rest still reachable
+            map.check_invariants().unwrap();
+            assert_eq!(map.len(), 101);
+            assert_eq!((0..200).filter(|&i| map.get(i) == Some(i)).count(), 101);
+        }
+
+        #[test]
+        fn test_capacity_for() {
+            for (n, load_factor) in [(0, 0.7), (11, 0.7), (12, 0.7), (1_000, 0.5), (5_000, 0.9)] {
+                let capacity = HashMap::<u64, u64>::capacity_for(n, load_factor);
+                assert!(capacity.is_power_of_two() && capacity >= 16);
+                assert!((n as f64) / (capacity as f64) < load_factor);
+                assert!(capacity == 16 || (n as f64) / (capacity as f64 / 2.0) >= load_factor);
+            }
+
+            let n = 3_000;
+            let mut map = HashMap::new(HashMap::<u64, u64>::capacity_for(n, LOAD_FACTOR_LIMIT));
+            for i in 0..n as u64 {
+                map.insert(i, i).unwrap();
+            }
+            assert_eq!(map.resize_count(), 0);
+        }
+
+        #[test]
+        fn test_debug_only_keys() {
+            //  keys only need Debug, which is what error messages print
+            #[derive(Hash, Clone, PartialEq, Debug)]
+            struct Point {
+                x: i32,
+                y: i32,
+            }
+
+            let mut map = HashMap::new(16);
+            for x in 0..50 {
+                map.insert(Point { x, y: -x }, x).unwrap();
+            }
+            assert_eq!(map.get(Point { x: 7, y: -7 }), Some(7));
+            assert_eq!(map.get(Point { x: 7, y: 7 }), None);
+            let err = map.try_insert(Point { x: 1, y: -1 }, 0).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "key Point { x: 1, y: -1 } is already present"
+            );
+        }
+    };
+}
+
+//  Tests for behaviour the two open addressing maps share but chaining does
+//  not, such as probe distances and tombstones
+macro_rules! open_map_tests {
+    () => {
+        #[test]
+        fn test_churn_does_not_grow_capacity() {
+            let mut map: HashMap<u64, u64> = HashMap::new(16);
+            for round in 0..1000 {
+                for i in 0..8 {
+                    map.insert(round * 8 + i, i).unwrap();
+                }
+                for i in 0..8 {
+                    map.delete(round * 8 + i).unwrap();
+                }
+                assert_eq!(map.capacity, 16);
+            }
+            assert_eq!(map.capacity, 16);
+            assert_eq!(map.size, 0);
+        }
+
+        #[test]
+        fn test_resize_does_not_clone_values() {
+            let mut map: HashMap<u64, CountedClone> = HashMap::new(16);
+            for i in 0..1_000 {
+                map.insert(i, CountedClone(i)).unwrap();
+            }
+            map.reserve(10_000);
+            assert!(map.resize_count() >= 5);
+            assert_eq!(VALUE_CLONES.with(|clones| clones.get()), 0);
+            assert_eq!(map.get_ref(&500), Some(&CountedClone(500)));
+        }
+
+        #[test]
+        fn test_raw_entry_reuses_a_hash() {
+            let mut map: HashMap<String, usize> = HashMap::new(16);
+            for i in 0..100 {
+                let key = format!("key{i}");
+                let hash = map.hasher().hash_one(&key);
+                match map.raw_entry_mut().from_hash(hash, |k| *k == key).unwrap() {
+                    RawEntryMut::Vacant(entry) => {
+                        entry.insert(key, i);
+                    }
+                    RawEntryMut::Occupied(_) => panic!("key{i} was not inserted yet"),
+                }
+            }
+            assert_eq!(map.len(), 100);
+
+            for i in 0..100 {
+                let key = format!("key{i}");
+                let RawEntryMut::Occupied(mut entry) = map.raw_entry_mut().from_key(&key).unwrap()
+                else {
+                    panic!("{key} should be in the map");
+                };
+                assert_eq!(*entry.get(), i);
+                *entry.get_mut() += 1;
+            }
+            //  the same hash finds the entry again without the key
+            let hash = map.hasher().hash_one("key7".to_string());
+            match map
+                .raw_entry_mut()
+                .from_hash(hash, |k| k == "key7")
+                .unwrap()
+            {
+                RawEntryMut::Occupied(entry) => assert_eq!(entry.remove(), 8),
+                RawEntryMut::Vacant(_) => panic!("key7 should be in the map"),
+            }
+            assert!(matches!(
+                map.raw_entry_mut().from_key(&"key7".to_string()).unwrap(),
+                RawEntryMut::Vacant(_)
+            ));
+            assert_eq!(map.get_ref(&"key8".to_string()), Some(&9));
+            map.check_invariants().unwrap();
+        }
+
+        #[test]
+        fn test_find_slot_reports_probe_distance() {
+            let mut map = HashMap::with_seed(64, 3);
+            let home = map.hash(&0);
+            let collider = (1..)
+                .find(|key| map.hash(key) == home)
+                .expect("some key shares slot {home}");
+            map.insert(0, 0).unwrap();
+            map.insert(collider, 1).unwrap();
+
+            assert_eq!(map.find_slot(&0), (home, home));
+            let (ideal, actual) = map.find_slot(&collider);
+            assert_eq!(ideal, home);
+            //  the second key probes past the first
+            let distance = (actual + map.capacity() - ideal) % map.capacity();
+            assert!(distance > 0);
+        }
+
+        #[test]
+        fn test_entry_ref_only_builds_vacant_keys() {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            static BUILT: AtomicUsize = AtomicUsize::new(0);
+
+            //  counts every owned copy made, whether cloned or built from a &str
+            #[derive(Hash, PartialEq, Debug)]
+            struct Counted(String);
+            impl Clone for Counted {
+                fn clone(&self) -> Self {
+                    BUILT.fetch_add(1, Ordering::Relaxed);
+                    Counted(self.0.clone())
+                }
+            }
+            impl From<&str> for Counted {
+                fn from(s: &str) -> Self {
+                    BUILT.fetch_add(1, Ordering::Relaxed);
+                    Counted(s.to_string())
+                }
+            }
+            impl Borrow<str> for Counted {
+                fn borrow(&self) -> &str {
+                    &self.0
+                }
+            }
+            let mut map: HashMap<Counted, u32> = HashMap::new(16);
+            *map.entry_ref("hits").unwrap().or_insert(0) += 1;
+            assert_eq!(BUILT.load(Ordering::Relaxed), 1);
+            for _ in 0..10 {
+                *map.entry_ref("hits").unwrap().or_insert(0) += 1;
+            }
+            assert_eq!(BUILT.load(Ordering::Relaxed), 1);
+            assert_eq!(map.len(), 1);
+            assert_eq!(map.iter().next().map(|(_, v)| *v), Some(11));
+        }
+
+        #[test]
+        fn test_estimate_memory() {
+            let n = 3_000;
+            let mut map = HashMap::new(HashMap::<u64, u64>::capacity_for(n, LOAD_FACTOR_LIMIT));
+            for i in 0..n as u64 {
+                map.insert(i, i).unwrap();
+            }
+            assert_eq!(map.memory_usage(), HashMap::<u64, u64>::estimate_memory(n));
+        }
+    };
+}
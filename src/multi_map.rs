@@ -0,0 +1,171 @@
+//  A map from each key to a list of values, such as an inverted index from a
+//  term to the documents holding it. Values under a key keep the order they
+//  were inserted in, and a key is dropped once its last value is removed
+use alloc::vec::Vec;
+
+use crate::chaining::{self, Key, Value};
+use crate::HashMapError;
+
+pub struct MultiMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    map: chaining::HashMap<K, Vec<V>>,
+    //  total number of values across all keys
+    len: usize,
+}
+
+impl<K, V> MultiMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            map: chaining::HashMap::new(capacity),
+            len: 0,
+        }
+    }
+
+    //  Adds `value` after any values already under `key`
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        self.map.entry(key)?.or_insert_with(Vec::new).push(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn get_all(&self, key: &K) -> &[V] {
+        self.map.get_ref(key).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    //  Removes the first occurrence of `value` under `key`, returning whether
+    //  one was found
+    pub fn remove_value(&mut self, key: &K, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        let Some(values) = self.map.get_mut(key.clone()) else {
+            return false;
+        };
+        let Some(i) = values.iter().position(|v| v == value) else {
+            return false;
+        };
+        values.remove(i);
+        if values.is_empty() {
+            self.map
+                .delete(key.clone())
+                .expect("the key was just found in the map");
+        }
+        self.len -= 1;
+        true
+    }
+
+    //  Removes `key` and every value under it
+    pub fn remove_all(&mut self, key: &K) -> Vec<V> {
+        let Some(values) = self.map.get_mut(key.clone()).map(core::mem::take) else {
+            return Vec::new();
+        };
+        self.map
+            .delete(key.clone())
+            .expect("the key was just found in the map");
+        self.len -= values.len();
+        values
+    }
+
+    //  Every (key, value) pair, with a key repeated once per value
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map
+            .iter()
+            .flat_map(|(key, values)| values.iter().map(move |value| (key, value)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.map.len()
+    }
+}
+
+//  Buckets `items` by the key `key_of` gives each one, keeping the order they
+//  arrived in within each bucket
+pub fn group_by<I, K, V, F>(items: I, mut key_of: F) -> chaining::HashMap<K, Vec<V>>
+where
+    I: IntoIterator<Item = V>,
+    K: Key,
+    V: Value,
+    F: FnMut(&V) -> K,
+{
+    let mut groups = MultiMap::new(16);
+    for item in items {
+        groups
+            .insert(key_of(&item), item)
+            .expect("growing a chaining map cannot fail");
+    }
+    groups.map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_all_keeps_insertion_order() {
+        let mut index: MultiMap<String, u32> = MultiMap::new(16);
+        for doc in [3, 1, 4, 1, 5] {
+            index.insert("rust".to_string(), doc).unwrap();
+        }
+        index.insert("hash".to_string(), 9).unwrap();
+
+        assert_eq!(index.get_all(&"rust".to_string()), &[3, 1, 4, 1, 5]);
+        assert_eq!(index.get_all(&"hash".to_string()), &[9]);
+        assert_eq!(index.get_all(&"missing".to_string()), &[] as &[u32]);
+        assert_eq!(index.len(), 6);
+        assert_eq!(index.key_count(), 2);
+
+        let mut pairs: Vec<(String, u32)> = index.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        pairs.sort();
+        assert_eq!(pairs.len(), 6);
+        assert_eq!(pairs[0], ("hash".to_string(), 9));
+    }
+
+    #[test]
+    fn test_remove_value() {
+        let mut index: MultiMap<i32, i32> = MultiMap::new(16);
+        for value in [1, 2, 1] {
+            index.insert(7, value).unwrap();
+        }
+        assert!(index.remove_value(&7, &1));
+        assert_eq!(index.get_all(&7), &[2, 1]);
+        assert!(!index.remove_value(&7, &5));
+        assert!(!index.remove_value(&8, &1));
+
+        assert!(index.remove_value(&7, &2));
+        assert!(index.remove_value(&7, &1));
+        assert!(!index.contains_key(&7));
+        assert!(index.is_empty());
+
+        index.insert(1, 1).unwrap();
+        index.insert(1, 2).unwrap();
+        assert_eq!(index.remove_all(&1), vec![1, 2]);
+        assert_eq!(index.len(), 0);
+    }
+
+    #[test]
+    fn test_group_by_parity() {
+        let groups = group_by(1..=7, |n| if n % 2 == 0 { "even" } else { "odd" });
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups.get_ref(&"even"), Some(&vec![2, 4, 6]));
+        assert_eq!(groups.get_ref(&"odd"), Some(&vec![1, 3, 5, 7]));
+    }
+}
@@ -1,187 +1,1618 @@
 #![allow(dead_code)]
-use std::{
-    fmt::Display,
-    hash::{DefaultHasher, Hash, Hasher},
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use core::{
+    borrow::Borrow,
+    fmt::{Debug, Display},
+    hash::{BuildHasher, Hash},
+    ops::AddAssign,
 };
 
-use anyhow::{anyhow, bail};
+use allocator_api2::alloc::{Allocator, Global};
 
-pub trait Key: Hash + Clone + PartialEq + Display {}
-impl<T> Key for T where T: Hash + Clone + PartialEq + Display {}
+use crate::builder::{FromBuilder, HashMapBuilder};
+use crate::snapshot::Snapshot;
+use crate::{
+    CapacityPolicy, FixedSeedState, HashMapError, OccupiedError, RandomState, TryReserveError,
+};
+
+pub trait Key: Hash + Clone + PartialEq + Debug {}
+impl<T> Key for T where T: Hash + Clone + PartialEq + Debug {}
+
+pub trait Value: Clone {}
+impl<T> Value for T where T: Clone {}
+
+const LOAD_FACTOR_LIMIT: f64 = 0.7;
+const DEFAULT_GROWTH_FACTOR: f64 = 2.0;
+const TOMBSTONE_RATIO_LIMIT: f64 = 0.3;
+
+#[derive(Clone)]
+enum Slot<K, V> {
+    Empty,
+    Deleted(K),
+    Occupied(K, V),
+}
+
+//  The slot table lives in the map's allocator
+type Slots<K, V, A> = allocator_api2::vec::Vec<Slot<K, V>, A>;
+
+//  Called with the key of an entry that was stored or removed
+type Callback<K> = Box<dyn FnMut(&K) + Send + Sync>;
+
+//  Called with the old and new capacity each time the table grows
+type ResizeCallback = Box<dyn FnMut(usize, usize) + Send + Sync>;
+
+pub struct HashMap<K, V, S = RandomState, A = Global>
+where
+    K: Key,
+    V: Value,
+    A: Allocator,
+{
+    data: Slots<K, V, A>,
+    capacity: usize,
+    size: usize,
+    tombstones: usize,
+    resizes: usize,
+    growth_factor: f64,
+    load_factor_limit: f64,
+    capacity_policy: CapacityPolicy,
+    on_resize: Option<ResizeCallback>,
+    hash_builder: S,
+    on_insert: Option<Callback<K>>,
+    on_delete: Option<Callback<K>>,
+}
+
+impl<K, V> HashMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    //  `capacity` is rounded up to a power of two of at least 16. Anything too
+    //  large to ever allocate, up to usize::MAX, starts the map at 16 instead
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher_in(capacity, RandomState::new(), Global)
+    }
+
+    //  The power-of-two capacity that keeps `entries` pairs below `load_factor`,
+    //  for sizing a map up front with `new`
+    pub fn capacity_for(entries: usize, load_factor: f64) -> usize {
+        crate::capacity::capacity_for_entries(entries, load_factor)
+    }
+
+    //  Bytes `memory_usage` reports for a map made with `new(capacity_for(entries,
+    //  ..))` at the default load factor limit once it holds `entries` pairs
+    pub fn estimate_memory(entries: usize) -> usize {
+        Self::capacity_for(entries, LOAD_FACTOR_LIMIT) * core::mem::size_of::<Slot<K, V>>()
+    }
+}
+
+impl<K, V> HashMap<K, V, FixedSeedState>
+where
+    K: Key,
+    V: Value,
+{
+    //  Same seed and same operations give the same layout on every run
+    pub fn with_seed(capacity: usize, seed: u64) -> Self {
+        Self::with_hasher(capacity, FixedSeedState::new(seed))
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    //  Like `new`, but keys are hashed with `hash_builder`
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_hasher_in(capacity, hash_builder, Global)
+    }
+}
+
+impl<K, V, A> HashMap<K, V, RandomState, A>
+where
+    K: Key,
+    V: Value,
+    A: Allocator + Clone,
+{
+    //  Like `new`, but every table the map allocates comes from `alloc`
+    pub fn new_in(capacity: usize, alloc: A) -> Self {
+        Self::with_hasher_in(capacity, RandomState::new(), alloc)
+    }
+}
+
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    pub fn with_hasher_in(capacity: usize, hash_builder: S, alloc: A) -> Self {
+        let initial_capacity =
+            crate::capacity::initial_capacity(capacity, core::mem::size_of::<Slot<K, V>>());
+        let data = Self::empty_slots(initial_capacity, alloc);
+        Self {
+            data,
+            capacity: initial_capacity,
+            size: 0,
+            tombstones: 0,
+            resizes: 0,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            load_factor_limit: LOAD_FACTOR_LIMIT,
+            capacity_policy: CapacityPolicy::default(),
+            on_resize: None,
+            hash_builder,
+            on_insert: None,
+            on_delete: None,
+        }
+    }
+
+    //  Built with resize_with so no slot is ever produced by cloning, which
+    //  keeps growth from touching K::clone or V::clone
+    fn empty_slots(capacity: usize, alloc: A) -> Slots<K, V, A> {
+        let mut slots = Slots::with_capacity_in(capacity, alloc);
+        slots.resize_with(capacity, || Slot::Empty);
+        slots
+    }
+
+    pub fn allocator(&self) -> &A {
+        self.data.allocator()
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    //  Calls `f` with the key every time `insert`, `append` or a vacant entry
+    //  stores a value, whether the key is new or overwritten. Changes made
+    //  through a `&mut V` are not reported. Maps derived from this one, such
+    //  as those from `map_values` or `split_off`, start without callbacks
+    pub fn on_insert(&mut self, f: impl FnMut(&K) + Send + Sync + 'static) {
+        self.on_insert = Some(Box::new(f));
+    }
+
+    //  Calls `f` with the key of every entry removed from the map by `delete`,
+    //  `clear`, `compute_if_present`, `split_off`, or as the `other` side of
+    //  `append`
+    pub fn on_delete(&mut self, f: impl FnMut(&K) + Send + Sync + 'static) {
+        self.on_delete = Some(Box::new(f));
+    }
+
+    fn notify_delete(&mut self, key: &K) {
+        if let Some(on_delete) = self.on_delete.as_mut() {
+            on_delete(key);
+        }
+    }
+
+    fn hash(&self, key: &K) -> usize {
+        (self.hash_builder.hash_one(key) as usize) % self.capacity
+    }
+
+    //  Returns the slot index holding the key, if it is present. Every probe
+    //  loop visits each slot at most once, so it ends even on a table with
+    //  no empty slot left, and only after checking every slot
+    fn find_index(&self, key: &K) -> Option<usize> {
+        let index = self.hash(key);
+        for step in 0..self.capacity {
+            let current_index = (index + step) % self.capacity;
+            match self.data.get(current_index) {
+                Some(Slot::Empty) => return None,
+                Some(Slot::Occupied(k, _)) if k == key => return Some(current_index),
+                Some(_) => {}
+                None => {
+                    debug_assert!(
+                        false,
+                        "entry at {index} cannot be found. seems like an issue with the hash function"
+                    );
+                    return None;
+                }
+            };
+        }
+        None
+    }
+
+    pub fn get(&self, key: K) -> Option<V> {
+        self.get_ref(&key).cloned()
+    }
+
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        let index = self.find_index(key)?;
+        match &self.data[index] {
+            Slot::Occupied(_, v) => Some(v),
+            _ => None,
+        }
+    }
+
+    //  Like `get_ref`, but also hands back the key as stored in the map
+    pub fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
+        let index = self.find_index(key)?;
+        match &self.data[index] {
+            Slot::Occupied(k, v) => Some((k, v)),
+            _ => None,
+        }
+    }
+
+    //  Returns the copy of `key` already stored, or stores `key` under a
+    //  default value and returns a copy of it. With a key type that shares its
+    //  contents on clone, such as Arc<str>, every equal key handed in comes
+    //  back as the same allocation
+    pub fn intern(&mut self, key: K) -> Result<K, HashMapError>
+    where
+        V: Default,
+    {
+        Ok(match self.entry(key)? {
+            Entry::Occupied(entry) => entry.key().clone(),
+            Entry::Vacant(entry) => {
+                let key = entry.key().clone();
+                entry.insert(V::default());
+                key
+            }
+        })
+    }
+
+    pub fn get_many<'a>(&self, keys: impl IntoIterator<Item = &'a K>) -> Vec<Option<&V>>
+    where
+        K: 'a,
+    {
+        keys.into_iter().map(|key| self.get_ref(key)).collect()
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        let index = self.find_index(&key)?;
+        match &mut self.data[index] {
+            Slot::Occupied(_, v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.data.iter().filter_map(|slot| match slot {
+            Slot::Occupied(k, v) => Some((k, v)),
+            _ => None,
+        })
+    }
+
+    //  Whether any key maps to `value`. A linear scan over every entry, unlike
+    //  the single lookup behind `contains_key`
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.iter().any(|(_, v)| v == value)
+    }
+
+    //  Every entry ordered by key, for deterministic output in tests and dumps
+    pub fn iter_sorted(&self) -> Vec<(&K, &V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    //  A digest of the entries that ignores their order, so maps that compare
+    //  equal always share a fingerprint whatever their capacity or seed
+    pub fn fingerprint(&self) -> u64
+    where
+        V: Hash,
+    {
+        crate::hasher::fingerprint(self.iter())
+    }
+
+    //  Copies the current contents into a read-only map that stays as it is
+    //  while this one changes
+    pub fn snapshot(&self) -> Arc<Snapshot<K, V, S>>
+    where
+        S: Clone,
+    {
+        Arc::new(Snapshot::from_entries(
+            self.hash_builder.clone(),
+            self.len(),
+            self.iter(),
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find_index(key).is_some()
+    }
+
+    fn get_load_factor(&self) -> f64 {
+        (self.size + self.tombstones) as f64 / self.capacity as f64
+    }
+
+    pub fn tombstone_ratio(&self) -> f64 {
+        self.tombstones as f64 / self.capacity as f64
+    }
+
+    //  The smallest capacity that holds `entries` under the load factor limit
+    fn min_capacity(&self, entries: usize) -> usize {
+        self.checked_min_capacity(entries)
+            .expect("capacity overflow")
+    }
+
+    fn checked_min_capacity(&self, entries: usize) -> Option<usize> {
+        crate::capacity::capacity_for(entries, self.load_factor_limit)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.capacity * core::mem::size_of::<Slot<K, V>>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    //  Number of times the map has grown because it hit the load factor limit
+    pub fn resize_count(&self) -> usize {
+        self.resizes
+    }
+
+    //  Calls `f` with the old and new capacity every time the map grows because
+    //  it hit the load factor limit. Explicit `reserve` and `shrink_to_fit`
+    //  calls are not reported
+    pub fn on_resize(&mut self, f: impl FnMut(usize, usize) + Send + Sync + 'static) {
+        self.on_resize = Some(Box::new(f));
+    }
+
+    pub fn load_factor_limit(&self) -> f64 {
+        self.load_factor_limit
+    }
+
+    pub fn growth_factor(&self) -> f64 {
+        self.growth_factor
+    }
+
+    //  How much the table grows each time it hits the load factor limit. Below
+    //  the default of 2 the map resizes more often but overshoots its size by less
+    pub fn set_growth_factor(&mut self, factor: f64) {
+        assert!(factor > 1.0, "the growth factor must be greater than 1");
+        self.growth_factor = factor;
+    }
+
+    pub fn capacity_policy(&self) -> CapacityPolicy {
+        self.capacity_policy
+    }
+
+    //  Switches how the table is sized, rebuilding it straight away if the
+    //  current capacity does not fit the new policy
+    pub fn set_capacity_policy(&mut self, policy: CapacityPolicy) {
+        self.capacity_policy = policy;
+        let capacity = policy.fit(self.capacity);
+        if capacity != self.capacity {
+            self.rehash(capacity);
+        }
+    }
+
+    //  The capacity the next resize grows to, at least one slot more than now
+    fn grown_capacity(&self) -> usize {
+        let grown = ((self.capacity as f64 * self.growth_factor) as usize).max(self.capacity + 1);
+        self.capacity_policy.fit(grown)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let new_capacity = self
+            .capacity_policy
+            .fit(self.min_capacity(self.size + additional));
+        if new_capacity > self.capacity {
+            self.rehash(new_capacity);
+        }
+    }
+
+    //  Like `reserve`, but reports an error instead of panicking when the table
+    //  cannot be grown
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let new_capacity = self
+            .size
+            .checked_add(additional)
+            .and_then(|entries| self.checked_min_capacity(entries))
+            .map(|capacity| self.capacity_policy.fit(capacity))
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if new_capacity <= self.capacity {
+            return Ok(());
+        }
+        new_capacity
+            .checked_mul(core::mem::size_of::<Slot<K, V>>())
+            .filter(|&bytes| bytes <= isize::MAX as usize)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let mut new_data = Slots::new_in(self.allocator().clone());
+        new_data.try_reserve_exact(new_capacity)?;
+        new_data.resize_with(new_capacity, || Slot::Empty);
+        self.rehash_into(new_data);
+        Ok(())
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        let new_capacity = self.capacity_policy.fit(self.min_capacity(self.size));
+        if new_capacity < self.capacity {
+            self.rehash(new_capacity);
+        }
+    }
+
+    //  Whether the next insert would rebuild the table. Besides the load
+    //  factor check, the table is rebuilt before an insert could take its
+    //  last empty slot, which a limit close to 1 on a small table would
+    //  otherwise allow, so misses always stop at an empty slot
+    pub(crate) fn at_load_limit(&self) -> bool {
+        self.get_load_factor() >= self.load_factor_limit
+            || self.size + self.tombstones + 1 >= self.capacity
+    }
+
+    //  The capacity the next rebuild would pick: the same one when it only
+    //  needs to clear tombstones, grown by the growth factor otherwise
+    pub(crate) fn rebuild_capacity(&self) -> usize {
+        if self.tombstone_ratio() > TOMBSTONE_RATIO_LIMIT {
+            self.capacity
+        } else {
+            self.grown_capacity()
+        }
+    }
+
+    pub fn count_tombstones(&self) -> usize {
+        self.data
+            .iter()
+            .filter(|entry| matches!(entry, Slot::Deleted(_)))
+            .count()
+    }
+
+    //  Walks the whole table checking the bookkeeping against the slots, for
+    //  tests and fuzzing. Every occupied key must be found by a lookup at its
+    //  own slot, which also rules out duplicates since only the first copy on
+    //  a probe sequence is reachable
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.data.len() != self.capacity {
+            return Err(format!(
+                "table has {} slots but capacity is {}",
+                self.data.len(),
+                self.capacity
+            ));
+        }
+        let mut occupied = 0;
+        let mut deleted = 0;
+        for (index, slot) in self.data.iter().enumerate() {
+            match slot {
+                Slot::Empty => {}
+                Slot::Deleted(_) => deleted += 1,
+                Slot::Occupied(key, _) => {
+                    occupied += 1;
+                    match self.find_index(key) {
+                        Some(found) if found == index => {}
+                        Some(found) => {
+                            return Err(format!(
+                            "key {key:?} at slot {index} is shadowed by a duplicate at slot {found}"
+                        ))
+                        }
+                        None => return Err(format!("key {key:?} at slot {index} is not findable")),
+                    }
+                }
+            }
+        }
+        if occupied != self.size {
+            return Err(format!(
+                "size is {} but {occupied} slots are occupied",
+                self.size
+            ));
+        }
+        if deleted != self.tombstones {
+            return Err(format!(
+                "tombstone count is {} but {deleted} slots are deleted",
+                self.tombstones
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn set_size_for_test(&mut self, size: usize) {
+        self.size = size;
+    }
+
+    //  The slot `key` hashes to and the slot it actually sits in, for looking
+    //  at how far collisions push keys. Panics if `key` is not in the map
+    #[cfg(test)]
+    pub(crate) fn find_slot(&self, key: &K) -> (usize, usize) {
+        let actual = self
+            .find_index(key)
+            .expect("find_slot is only asked about keys in the map");
+        (self.hash(key), actual)
+    }
+
+    //  Goes through `entry` so an existing key is always updated in place, even
+    //  when a tombstone earlier on its probe sequence would take a new key
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        match self.entry(key)? {
+            Entry::Occupied(entry) => {
+                let index = entry.index;
+                *entry.into_mut() = value;
+                self.notify_insert(index);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+        Ok(())
+    }
+
+    //  Reserves room for the whole batch up front so the loop below never resizes
+    pub fn insert_many(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), HashMapError> {
+        let pairs = pairs.into_iter();
+        self.reserve(pairs.size_hint().0);
+        for (key, value) in pairs {
+            self.insert(key, value)?;
+        }
+        Ok(())
+    }
+
+    //  Finds the slot for `key` in a single probe, growing the table first if needed
+    pub fn entry(&mut self, key: K) -> Result<Entry<'_, K, V, S, A>, HashMapError> {
+        let hash = self.hash_builder.hash_one(&key);
+        Ok(match self.raw_entry_mut().from_hash(hash, |k| *k == key)? {
+            RawEntryMut::Occupied(entry) => Entry::Occupied(entry),
+            RawEntryMut::Vacant(entry) => Entry::Vacant(VacantEntry {
+                map: entry.map,
+                key,
+                index: entry.index,
+            }),
+        })
+    }
+
+    //  Like `entry`, but looks the key up by reference and only builds an
+    //  owned key when the entry is vacant, so updating a key that is already
+    //  there costs no clone
+    pub fn entry_ref<'q, Q>(&mut self, key: &'q Q) -> Result<Entry<'_, K, V, S, A>, HashMapError>
+    where
+        K: Borrow<Q> + From<&'q Q>,
+        Q: Hash + PartialEq + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        Ok(
+            match self
+                .raw_entry_mut()
+                .from_hash(hash, |k| k.borrow() == key)?
+            {
+                RawEntryMut::Occupied(entry) => Entry::Occupied(entry),
+                RawEntryMut::Vacant(entry) => Entry::Vacant(VacantEntry {
+                    map: entry.map,
+                    key: K::from(key),
+                    index: entry.index,
+                }),
+            },
+        )
+    }
+
+    //  Like `entry`, but the slot can be found from a hash the caller already
+    //  has, so a key hashed once can be looked up and inserted without
+    //  hashing it again
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S, A> {
+        RawEntryBuilderMut { map: self }
+    }
+
+    pub fn get_or_insert_with_key<F: FnOnce(&K) -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, HashMapError> {
+        Ok(self.entry(key)?.or_insert_with_key(f))
+    }
+
+    pub fn compute_if_absent<F: FnOnce(&K) -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, HashMapError> {
+        self.get_or_insert_with_key(key, f)
+    }
+
+    //  Replaces the value when `f` returns Some and removes the key when it returns None
+    pub fn compute_if_present<F: FnOnce(&K, V) -> Option<V>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Option<&mut V> {
+        let index = self.find_index(&key)?;
+        //  the entry counts as removed while `f` runs, so a panic in `f`
+        //  drops it without leaving a hole in the probe run
+        let value = self.tombstone_slot(index);
+        let Slot::Deleted(key) = &self.data[index] else {
+            unreachable!();
+        };
+        match f(key, value) {
+            Some(value) => Some(self.refill_tombstone(index, value)),
+            None => {
+                self.notify_tombstone(index);
+                None
+            }
+        }
+    }
+
+    //  Builds a map with the same keys and slot layout but transformed values
+    pub fn map_values<W: Value, F: FnMut(&V) -> W>(&self, mut f: F) -> HashMap<K, W, S, A>
+    where
+        S: Clone,
+    {
+        let mut data = Slots::with_capacity_in(self.capacity, self.allocator().clone());
+        data.extend(self.data.iter().map(|slot| match slot {
+            Slot::Empty => Slot::Empty,
+            Slot::Deleted(k) => Slot::Deleted(k.clone()),
+            Slot::Occupied(k, v) => Slot::Occupied(k.clone(), f(v)),
+        }));
+        HashMap {
+            data,
+            capacity: self.capacity,
+            size: self.size,
+            tombstones: self.tombstones,
+            resizes: 0,
+            growth_factor: self.growth_factor,
+            load_factor_limit: self.load_factor_limit,
+            capacity_policy: self.capacity_policy,
+            on_resize: None,
+            hash_builder: self.hash_builder.clone(),
+            on_insert: None,
+            on_delete: None,
+        }
+    }
+
+    //  Keeps the entries `f` maps to Some, in a map sized for just those entries
+    pub fn filter_map<W: Value, F: FnMut(&K, &V) -> Option<W>>(
+        &self,
+        mut f: F,
+    ) -> HashMap<K, W, S, A>
+    where
+        S: Clone,
+    {
+        let kept: Vec<(K, W)> = self
+            .data
+            .iter()
+            .filter_map(|slot| match slot {
+                Slot::Occupied(key, value) => f(key, value).map(|value| (key.clone(), value)),
+                _ => None,
+            })
+            .collect();
+        let mut map = HashMap::with_hasher_in(
+            self.min_capacity(kept.len()),
+            self.hash_builder.clone(),
+            self.allocator().clone(),
+        );
+        for (key, value) in kept {
+            map.insert(key, value)
+                .expect("map was sized to hold every kept entry");
+        }
+        map
+    }
+
+    //  Copies `pairs` into a new map hashing like this one, sized for `entries`
+    fn collect_pairs<'a>(&self, entries: usize, pairs: impl Iterator<Item = (&'a K, &'a V)>) -> Self
+    where
+        K: 'a,
+        V: 'a,
+        S: Clone,
+    {
+        let mut map = HashMap::with_hasher_in(
+            self.min_capacity(entries),
+            self.hash_builder.clone(),
+            self.allocator().clone(),
+        );
+        for (key, value) in pairs {
+            map.insert(key.clone(), value.clone())
+                .expect("map was sized to hold every entry");
+        }
+        map
+    }
+
+    //  Every entry in either map, with this map's value for a key in both
+    pub fn union(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let only_other = other.iter().filter(|(key, _)| !self.contains_key(key));
+        self.collect_pairs(self.len() + other.len(), self.iter().chain(only_other))
+    }
+
+    //  The entries of this map whose keys are also in `other`. Walks whichever
+    //  map is smaller and looks each key up in the other one
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let entries = self.len().min(other.len());
+        if self.len() <= other.len() {
+            self.collect_pairs(
+                entries,
+                self.iter().filter(|(key, _)| other.contains_key(key)),
+            )
+        } else {
+            let shared = other.iter().filter_map(|(key, _)| self.get_key_value(key));
+            self.collect_pairs(entries, shared)
+        }
+    }
+
+    //  The entries of this map whose keys are not in `other`
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        self.collect_pairs(
+            self.len(),
+            self.iter().filter(|(key, _)| !other.contains_key(key)),
+        )
+    }
+
+    //  The entries whose keys are in exactly one of the two maps
+    pub fn symmetric_difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let only_self = self.iter().filter(|(key, _)| !other.contains_key(key));
+        let only_other = other.iter().filter(|(key, _)| !self.contains_key(key));
+        self.collect_pairs(self.len() + other.len(), only_self.chain(only_other))
+    }
+
+    //  Moves every entry out of `other`, which keeps its capacity for reuse
+    pub fn append(&mut self, other: &mut Self) -> Result<(), HashMapError> {
+        self.reserve(other.size);
+        let empty = Self::empty_slots(other.capacity, other.allocator().clone());
+        let data = core::mem::replace(&mut other.data, empty);
+        other.size = 0;
+        other.tombstones = 0;
+        for slot in data {
+            if let Slot::Occupied(key, value) = slot {
+                other.notify_delete(&key);
+                self.insert(key, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    //  Removes the entries matching `pred` and returns them as a new map
+    pub fn split_off<F: FnMut(&K, &V) -> bool>(&mut self, mut pred: F) -> Self
+    where
+        S: Clone,
+    {
+        let mut split = Vec::new();
+        for index in 0..self.capacity {
+            if matches!(&self.data[index], Slot::Occupied(k, v) if pred(k, v)) {
+                //  each entry leaves the table before any more user code runs
+                let value = self.tombstone_slot(index);
+                let Slot::Deleted(key) = &self.data[index] else {
+                    unreachable!();
+                };
+                split.push((key.clone(), value));
+                self.notify_tombstone(index);
+            }
+        }
+        let mut map = Self::with_hasher_in(
+            self.min_capacity(split.len()),
+            self.hash_builder.clone(),
+            self.allocator().clone(),
+        );
+        for (key, value) in split {
+            map.insert(key, value)
+                .expect("map was sized to hold every split entry");
+        }
+        map
+    }
+
+    //  Inserts only when `key` is absent, handing the rejected value back otherwise
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<K, V>> {
+        let entry = self
+            .entry(key.clone())
+            .expect("growing the map always leaves a free slot");
+        match entry {
+            Entry::Occupied(entry) => Err(OccupiedError {
+                key,
+                existing: entry.into_mut().clone(),
+                value,
+            }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+    fn resize(&mut self) {
+        self.resizes += 1;
+        let (old_capacity, new_capacity) = (self.capacity, self.grown_capacity());
+        self.rehash(new_capacity);
+        if let Some(on_resize) = self.on_resize.as_mut() {
+            on_resize(old_capacity, new_capacity);
+        }
+    }
+
+    //  Rebuilds the table at its current capacity, dropping all tombstones
+    fn rehash_in_place(&mut self) {
+        self.rehash(self.capacity);
+    }
+
+    fn rehash(&mut self, new_capacity: usize) {
+        self.rehash_into(Self::empty_slots(new_capacity, self.allocator().clone()));
+    }
+
+    fn rehash_into(&mut self, new_data: Slots<K, V, A>) {
+        self.capacity = new_data.len();
+        let old_data = core::mem::replace(&mut self.data, new_data);
+        for entry in old_data {
+            if let Slot::Occupied(k, v) = entry {
+                let mut index = self.hash(&k);
+                while let Some(Slot::Occupied(_, _)) = self.data.get(index) {
+                    index = (index + 1) % self.capacity;
+                }
+                self.data[index] = Slot::Occupied(k, v);
+            }
+        }
+        self.tombstones = 0;
+    }
+
+    //  Removes and returns the entry in the first occupied slot, leaving a
+    //  tombstone, for draining a map one entry at a time
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let index = self
+            .data
+            .iter()
+            .position(|slot| matches!(slot, Slot::Occupied(_, _)))?;
+        let (key, value) = self.take_slot(index)?;
+        self.notify_delete(&key);
+        Some((key, value))
+    }
+
+    //  Removes every entry, keeping the table at its current capacity
+    pub fn clear(&mut self) {
+        for index in 0..self.capacity {
+            if let Slot::Occupied(key, _) = core::mem::replace(&mut self.data[index], Slot::Empty) {
+                self.notify_delete(&key);
+            }
+        }
+        self.size = 0;
+        self.tombstones = 0;
+    }
+
+    //  Like `clear`, but also gives the table back and starts over at the
+    //  smallest capacity, so a long-lived map does not keep the peak size of
+    //  an earlier phase. Every removed key is reported to `on_delete`
+    pub fn reset(&mut self) {
+        self.clear();
+        let capacity = self.capacity_policy.fit(crate::capacity::MIN_CAPACITY);
+        self.data = Self::empty_slots(capacity, self.allocator().clone());
+        self.capacity = capacity;
+    }
+
+    //  Keeps only the entries `f` returns true for
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, f: F) {
+        self.retain_count(f);
+    }
+
+    //  Like `retain`, but returns how many entries were removed. Only live
+    //  entries are offered to `f`, so the count is exactly the number of
+    //  slots that turned into tombstones
+    pub fn retain_count<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> usize {
+        let mut removed = 0;
+        for index in 0..self.capacity {
+            if matches!(&self.data[index], Slot::Occupied(k, v) if !f(k, v)) {
+                self.tombstone_slot(index);
+                self.notify_tombstone(index);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    //  Deletes every key in `keys`, returning how many were present. Tombstones
+    //  are only cleaned up once at the end, rather than as each delete adds one
+    pub fn delete_many<'a>(&mut self, keys: impl IntoIterator<Item = &'a K>) -> usize
+    where
+        K: 'a,
+    {
+        let mut removed = 0;
+        for key in keys {
+            if let Some(index) = self.find_index(key) {
+                self.tombstone_slot(index);
+                self.notify_tombstone(index);
+                removed += 1;
+            }
+        }
+        if self.tombstone_ratio() > TOMBSTONE_RATIO_LIMIT {
+            self.rehash_in_place();
+        }
+        removed
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        let index = self.hash(&key);
+        for step in 0..self.capacity {
+            let current_index = (index + step) % self.capacity;
+            match self.data.get_mut(current_index) {
+                Some(Slot::Empty) => return Ok(()),
+                Some(Slot::Deleted(k)) if *k == key => return Ok(()),
+                Some(Slot::Occupied(k, _v)) if *k == key => {
+                    self.notify_delete(&key);
+                    self.data[current_index] = Slot::Deleted(key);
+                    self.size -= 1;
+                    self.tombstones += 1;
+                    return Ok(());
+                }
+                Some(_) => {}
+                None => return Err(HashMapError::HashInvariantViolated),
+            };
+        }
+        Err(HashMapError::KeyNotFound)
+    }
+}
+
+//  Slot helpers for code that runs user callbacks while storing or removing
+//  an entry. The slot is written and the counters are settled before the
+//  callback runs, so a panic in it leaves every probe run intact
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    A: Allocator,
+{
+    fn notify_insert(&mut self, index: usize) {
+        if let (Some(on_insert), Slot::Occupied(key, _)) =
+            (self.on_insert.as_mut(), &self.data[index])
+        {
+            on_insert(key);
+        }
+    }
+
+    //  Turns the occupied slot at `index` into a tombstone that keeps its key,
+    //  handing back the value
+    fn tombstone_slot(&mut self, index: usize) -> V {
+        let Slot::Occupied(key, value) = core::mem::replace(&mut self.data[index], Slot::Empty)
+        else {
+            unreachable!("only occupied slots are turned into tombstones");
+        };
+        self.data[index] = Slot::Deleted(key);
+        self.size -= 1;
+        self.tombstones += 1;
+        value
+    }
+
+    //  Moves the entry out of slot `index` if it is occupied, leaving a
+    //  tombstone so lookups for keys further along the probe run still reach
+    //  them. The tombstone keeps the key, so the caller gets a clone of it
+    pub(crate) fn take_slot(&mut self, index: usize) -> Option<(K, V)> {
+        if !matches!(self.data.get(index), Some(Slot::Occupied(_, _))) {
+            return None;
+        }
+        let value = self.tombstone_slot(index);
+        let Slot::Deleted(key) = &self.data[index] else {
+            unreachable!("the slot was just turned into a tombstone");
+        };
+        Some((key.clone(), value))
+    }
+
+    //  Undoes `tombstone_slot`, putting `value` back under the kept key
+    fn refill_tombstone(&mut self, index: usize, value: V) -> &mut V {
+        let Slot::Deleted(key) = core::mem::replace(&mut self.data[index], Slot::Empty) else {
+            unreachable!("only tombstones are refilled");
+        };
+        self.data[index] = Slot::Occupied(key, value);
+        self.size += 1;
+        self.tombstones -= 1;
+        match &mut self.data[index] {
+            Slot::Occupied(_, value) => value,
+            _ => unreachable!(),
+        }
+    }
+
+    //  Reports the key kept in the tombstone at `index` to `on_delete`
+    fn notify_tombstone(&mut self, index: usize) {
+        if let (Some(on_delete), Slot::Deleted(key)) = (self.on_delete.as_mut(), &self.data[index])
+        {
+            on_delete(key);
+        }
+    }
+}
+
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Key,
+    V: Value + AddAssign + From<u8>,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    //  Bumps the count for `key`, starting it at zero if absent
+    pub fn increment(&mut self, key: K) -> Result<(), HashMapError> {
+        *self.entry(key)?.or_insert(V::from(0)) += V::from(1);
+        Ok(())
+    }
+
+    //  The `n` keys with the highest counts, highest first
+    pub fn most_common(&self, n: usize) -> Vec<(K, V)>
+    where
+        V: Ord,
+    {
+        let mut counts: Vec<(K, V)> = self
+            .data
+            .iter()
+            .filter_map(|slot| match slot {
+                Slot::Occupied(k, v) => Some((k.clone(), v.clone())),
+                _ => None,
+            })
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1));
+        counts.truncate(n);
+        counts
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Key + Send + Sync,
+    V: Value + Send + Sync,
+    S: BuildHasher + Sync,
+    A: Allocator + Clone,
+{
+    //  Like `reserve`, but rehashes on the rayon thread pool. Worth it ahead of
+    //  a bulk load into a map with millions of entries, where a serial rehash
+    //  is a long pause
+    pub fn par_reserve(&mut self, additional: usize) {
+        let new_capacity = self
+            .capacity_policy
+            .fit(self.min_capacity(self.size + additional));
+        if new_capacity > self.capacity {
+            self.par_rehash(new_capacity);
+        }
+    }
+
+    //  Inserts every pair, as `insert` would one at a time, with a later pair
+    //  for a key winning over an earlier one. The table is grown once up
+    //  front, clearing any tombstones, then the keys are hashed and placed on
+    //  the thread pool one region of the table at a time, the same split
+    //  `par_rehash` uses. A pair whose probe runs past the end of its region
+    //  is inserted serially afterwards. `on_insert` fires once per pair, but
+    //  in table order rather than the order of `pairs`
+    pub fn par_extend(
+        &mut self,
+        pairs: impl rayon::iter::IntoParallelIterator<Item = (K, V)>,
+    ) -> Result<(), HashMapError> {
+        use rayon::prelude::*;
+
+        let pairs: Vec<(K, V)> = pairs.into_par_iter().collect();
+        let new_capacity = self
+            .capacity_policy
+            .fit(self.min_capacity(self.size + pairs.len()))
+            .max(self.capacity);
+        //  without tombstones every miss inside a region ends at an empty slot
+        if new_capacity > self.capacity || self.tombstones > 0 {
+            self.par_rehash(new_capacity);
+        }
+
+        let hash_builder = &self.hash_builder;
+        let capacity = self.capacity;
+        let pairs: Vec<(usize, (u64, K, V))> = pairs
+            .into_par_iter()
+            .map(|(k, v)| {
+                let hash = hash_builder.hash_one(&k);
+                ((hash as usize) % capacity, (hash, k, v))
+            })
+            .collect();
+
+        let placed = self.par_regions(pairs, |slots, start, pairs| {
+            let mut spilled = Vec::new();
+            let mut written = Vec::new();
+            let mut inserted = 0;
+            for (home, (hash, k, v)) in pairs {
+                let mut index = home - start;
+                loop {
+                    match slots.get_mut(index) {
+                        Some(Slot::Occupied(key, value)) if *key == k => {
+                            *value = v;
+                            written.push(start + index);
+                            break;
+                        }
+                        Some(slot @ Slot::Empty) => {
+                            *slot = Slot::Occupied(k, v);
+                            written.push(start + index);
+                            inserted += 1;
+                            break;
+                        }
+                        Some(_) => index += 1,
+                        None => {
+                            spilled.push((hash, k, v));
+                            break;
+                        }
+                    }
+                }
+            }
+            (spilled, written, inserted)
+        });
+
+        let mut spilled = Vec::new();
+        for (region_spilled, written, inserted) in placed {
+            self.size += inserted;
+            for index in written {
+                self.notify_insert(index);
+            }
+            spilled.extend(region_spilled);
+        }
+        for (hash, key, value) in spilled {
+            match self.raw_entry_mut().from_hash(hash, |k| *k == key)? {
+                RawEntryMut::Occupied(mut entry) => {
+                    entry.insert(value);
+                }
+                RawEntryMut::Vacant(entry) => {
+                    entry.insert(key, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //  Hashes every entry in parallel, then places each one inside the region
+    //  of the new table holding its home slot. An entry that probes past the
+    //  end of its region is held back and placed serially afterwards, once
+    //  every region is settled
+    fn par_rehash(&mut self, new_capacity: usize) {
+        use rayon::prelude::*;
+
+        let new_data = Self::empty_slots(new_capacity, self.allocator().clone());
+        let old_data = core::mem::replace(&mut self.data, new_data);
+        self.capacity = new_capacity;
+        self.tombstones = 0;
+
+        let entries: Vec<(K, V)> = old_data
+            .into_iter()
+            .filter_map(|slot| match slot {
+                Slot::Occupied(k, v) => Some((k, v)),
+                _ => None,
+            })
+            .collect();
+        let hash_builder = &self.hash_builder;
+        let entries: Vec<(usize, (K, V))> = entries
+            .into_par_iter()
+            .map(|(k, v)| ((hash_builder.hash_one(&k) as usize) % new_capacity, (k, v)))
+            .collect();
+
+        let spilled = self.par_regions(entries, |slots, start, entries| {
+            let mut spilled = Vec::new();
+            for (home, (k, v)) in entries {
+                let mut index = home - start;
+                while let Some(Slot::Occupied(_, _)) = slots.get(index) {
+                    index += 1;
+                }
+                match slots.get_mut(index) {
+                    Some(slot) => *slot = Slot::Occupied(k, v),
+                    None => spilled.push((home, k, v)),
+                }
+            }
+            spilled
+        });
+
+        //  The whole run from a spilled entry's home to its region's end is
+        //  occupied, so carrying on from there keeps the probe sequence intact
+        for (home, k, v) in spilled.into_iter().flatten() {
+            let mut index = home;
+            while let Some(Slot::Occupied(_, _)) = self.data.get(index) {
+                index = (index + 1) % self.capacity;
+            }
+            self.data[index] = Slot::Occupied(k, v);
+        }
+    }
+
+    //  Splits the table into one region per chunk of slots and runs `place`
+    //  on the regions concurrently. Each call gets the region's slots, the
+    //  index of its first slot and the items whose home slot falls inside it,
+    //  in their original order, so no two threads ever write the same slot
+    fn par_regions<T, R, F>(&mut self, items: Vec<(usize, T)>, place: F) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        F: Fn(&mut [Slot<K, V>], usize, Vec<(usize, T)>) -> R + Sync,
+    {
+        use rayon::prelude::*;
+
+        let region_len = self.capacity.div_ceil(rayon::current_num_threads() * 4);
+        let mut regions: Vec<Vec<(usize, T)>> = (0..self.capacity.div_ceil(region_len))
+            .map(|_| Vec::new())
+            .collect();
+        for (home, item) in items {
+            regions[home / region_len].push((home, item));
+        }
+
+        self.data
+            .par_chunks_mut(region_len)
+            .zip(regions)
+            .enumerate()
+            .map(|(region, (slots, items))| place(slots, region * region_len, items))
+            .collect()
+    }
+}
+
+impl<K, V, S> FromBuilder<S> for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    fn from_builder(builder: HashMapBuilder<S>) -> Self {
+        let mut map = Self::with_hasher(builder.capacity, builder.hash_builder);
+        if let Some(limit) = builder.load_factor_limit {
+            map.load_factor_limit = limit;
+        }
+        if let Some(factor) = builder.growth_factor {
+            map.set_growth_factor(factor);
+        }
+        map.set_capacity_policy(builder.capacity_policy);
+        map
+    }
+}
+
+//  A clone starts without the original's callbacks
+impl<K, V, S, A> Clone for HashMap<K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: Clone,
+    A: Allocator + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            capacity: self.capacity,
+            size: self.size,
+            tombstones: self.tombstones,
+            resizes: self.resizes,
+            growth_factor: self.growth_factor,
+            load_factor_limit: self.load_factor_limit,
+            capacity_policy: self.capacity_policy,
+            on_resize: None,
+            hash_builder: self.hash_builder.clone(),
+            on_insert: None,
+            on_delete: None,
+        }
+    }
+
+    //  Copies into the existing slot table, which is only reallocated when it
+    //  is smaller than `source`'s. Keeps the callbacks registered on `self`
+    fn clone_from(&mut self, source: &Self) {
+        self.data.clone_from(&source.data);
+        self.capacity = source.capacity;
+        self.size = source.size;
+        self.tombstones = source.tombstones;
+        self.resizes = source.resizes;
+        self.growth_factor = source.growth_factor;
+        self.load_factor_limit = source.load_factor_limit;
+        self.capacity_policy = source.capacity_policy;
+        self.hash_builder.clone_from(&source.hash_builder);
+    }
+}
+
+impl<K, V, S, A> PartialEq for HashMap<K, V, S, A>
+where
+    K: Key,
+    V: Value + PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get_ref(key) == Some(value))
+    }
+}
 
-pub trait Value: Clone {}
-impl<T> Value for T where T: Clone {}
+impl<K, V, S, A> Eq for HashMap<K, V, S, A>
+where
+    K: Key,
+    V: Value + Eq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+}
 
-const LOAD_FACTOR_LIMIT: f64 = 0.7;
+//  Renders `{k1: v1, k2: v2}` in iteration order, for quick dumps
+impl<K, V, S, A> Display for HashMap<K, V, S, A>
+where
+    K: Key + Display,
+    V: Value + Display,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("{")?;
+        for (i, (key, value)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", key, value)?;
+        }
+        f.write_str("}")
+    }
+}
 
-#[derive(Clone)]
-enum Entry<K, V> {
-    Empty,
-    Deleted(K),
-    Occupied(K, V),
+//  Reserves room for the iterator's lower size hint up front, so a well
+//  hinted iterator grows the table at most once. A hint of zero reserves
+//  nothing and the map grows as pairs arrive
+impl<K, V, S, A> Extend<(K, V)> for HashMap<K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        self.reserve(lower);
+        for (key, value) in iter {
+            self.insert(key, value)
+                .expect("growing the map always leaves a free slot");
+        }
+    }
 }
 
-pub struct HashMap<K, V>
+pub enum Entry<'a, K, V, S = RandomState, A = Global>
 where
     K: Key,
     V: Value,
+    A: Allocator,
 {
-    data: Vec<Entry<K, V>>,
-    capacity: usize,
-    size: usize,
+    Occupied(OccupiedEntry<'a, K, V, S, A>),
+    Vacant(VacantEntry<'a, K, V, S, A>),
 }
 
-impl<K, V> HashMap<K, V>
+pub struct OccupiedEntry<'a, K, V, S = RandomState, A = Global>
 where
     K: Key,
     V: Value,
+    A: Allocator,
 {
-    pub fn new(capacity: usize) -> Self {
-        let initial_capacity = 16.max(capacity.next_power_of_two());
-        let data = vec![Entry::Empty; initial_capacity];
-        Self {
-            data,
-            capacity: initial_capacity,
-            size: 0,
-        }
+    map: &'a mut HashMap<K, V, S, A>,
+    index: usize,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState, A = Global>
+where
+    K: Key,
+    V: Value,
+    A: Allocator,
+{
+    map: &'a mut HashMap<K, V, S, A>,
+    key: K,
+    index: usize,
+}
+
+impl<'a, K, V, S, A> Entry<'a, K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+    A: Allocator,
+{
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with_key(|_| default)
     }
 
-    fn hash(&self, key: &K) -> usize {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        (hasher.finish() as usize) % self.capacity
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        self.or_insert_with_key(|_| f())
     }
 
-    pub fn get(&self, key: K) -> anyhow::Result<Option<V>>
+    pub fn or_default(self) -> &'a mut V
     where
-        V: Value,
+        V: Default,
     {
-        let index = self.hash(&key);
-        let mut current_index = index;
-        loop {
-            match self.data.get(current_index) {
-                Some(Entry::Empty) => return anyhow::Ok(None),
-                Some(Entry::Occupied(k, v)) => {
-                    if *k == key {
-                        return anyhow::Ok(Some(v.clone()));
-                    }
-                    current_index = (current_index + 1) % self.capacity;
-                }
-                Some(Entry::Deleted(_)) => {
-                    current_index = (current_index + 1) % self.capacity;
-                }
-                None => {
-                    bail!("entry at {index} cannot be found. seems like an issue with the hash function")
-                }
-            };
-            if current_index == index {
-                return anyhow::Ok(None);
+        self.or_insert_with(V::default)
+    }
+
+    //  Applies `f` to the value if the key is present. The entry already holds
+    //  the slot, so a following `or_insert` does not probe again
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = f(&entry.key);
+                entry.insert(value)
             }
         }
     }
+}
 
-    fn get_load_factor(&self) -> f64 {
-        self.size as f64 / self.capacity as f64
+impl<'a, K, V, S, A> OccupiedEntry<'a, K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+    A: Allocator,
+{
+    pub fn key(&self) -> &K {
+        match &self.map.data[self.index] {
+            Slot::Occupied(k, _) => k,
+            _ => unreachable!("occupied entry points at a free slot"),
+        }
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> anyhow::Result<()> {
-        if self.get_load_factor() >= LOAD_FACTOR_LIMIT {
-            self.resize();
+    pub fn get(&self) -> &V {
+        match &self.map.data[self.index] {
+            Slot::Occupied(_, v) => v,
+            _ => unreachable!("occupied entry points at a free slot"),
         }
+    }
 
-        let index = self.hash(&key);
-        let mut current_index = index;
-        loop {
-            match self.data.get(current_index) {
-                Some(Entry::Empty) => {
-                    self.data[current_index] = Entry::Occupied(key, value);
-                    self.size += 1;
-                    return Ok(());
-                }
-                Some(Entry::Deleted(_)) => {
-                    self.data[current_index] = Entry::Occupied(key, value);
-                    self.size += 1;
-                    return Ok(());
-                }
-                Some(Entry::Occupied(_, _)) => {
-                    current_index = (current_index + 1) % self.capacity;
-                }
-                None => {
-                    bail!("entry at {index} cannot be found. seems like an issue with the hash function");
-                }
-            };
-            if current_index == index {
-                bail!("entry for key {key} cannot be inserted. seems like an issue with the hash function");
-            }
+    pub fn get_mut(&mut self) -> &mut V {
+        match &mut self.map.data[self.index] {
+            Slot::Occupied(_, v) => v,
+            _ => unreachable!("occupied entry points at a free slot"),
         }
     }
 
-    fn resize(&mut self) {
-        let old_capacity = self.capacity;
-        let new_capacity = old_capacity << 1;
-
-        // Calculate sizes
-        // let entry_size = std::mem::size_of::<Entry<K, V>>();
-        // let vec_size = new_capacity * entry_size;
-        // println!("Resize Stats:");
-        // println!(
-        //     "  Old capacity: {}, New capacity: {}",
-        //     old_capacity, new_capacity
-        // );
-        // println!("  Entry size: {} bytes", entry_size);
-        // println!("  New vec size: {} bytes", vec_size);
-        // println!("  Current size (items): {}", self.size);
-        // println!(
-        //     "  Actual old vec size: {} bytes",
-        //     self.data.len() * entry_size
-        // );
-        // let old_entries: Vec<Entry<K, V>> = self.data.drain(..).collect();
-
-        let new_data: Vec<Entry<K, V>> = vec![Entry::Empty; new_capacity];
-        let old_data = std::mem::replace(&mut self.data, new_data);
-        self.capacity = new_capacity;
-        for entry in old_data {
-            if let Entry::Occupied(k, v) = entry {
-                let mut index = self.hash(&k);
-                while let Some(Entry::Occupied(_, _)) = self.data.get(index) {
-                    index = (index + 1) % self.capacity;
-                }
-                self.data[index] = Entry::Occupied(k, v);
-            }
+    pub fn into_mut(self) -> &'a mut V {
+        match &mut self.map.data[self.index] {
+            Slot::Occupied(_, v) => v,
+            _ => unreachable!("occupied entry points at a free slot"),
         }
-        // println!("Done resizing!!!");
     }
 
-    pub fn delete(&mut self, key: K) -> anyhow::Result<()> {
-        let index = self.hash(&key);
-        let mut current_index = index;
-        loop {
-            match self.data.get_mut(current_index) {
-                Some(Entry::Empty) => return anyhow::Ok(()),
-                Some(Entry::Deleted(k)) => {
-                    if *k == key {
-                        return anyhow::Ok(());
-                    }
-                    current_index = (current_index + 1) % self.capacity;
+    //  Replaces the value, returning the old one. Counts as an insert for
+    //  `on_insert`, the same as overwriting a key through `insert`
+    pub fn insert(&mut self, value: V) -> V {
+        let map = &mut *self.map;
+        let Slot::Occupied(key, v) = &mut map.data[self.index] else {
+            unreachable!("occupied entry points at a free slot");
+        };
+        if let Some(on_insert) = map.on_insert.as_mut() {
+            on_insert(key);
+        }
+        core::mem::replace(v, value)
+    }
+
+    //  Leaves a tombstone, like `delete`
+    pub fn remove(self) -> V {
+        let value = self.map.tombstone_slot(self.index);
+        self.map.notify_tombstone(self.index);
+        value
+    }
+}
+
+impl<'a, K, V, S, A> VacantEntry<'a, K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+    A: Allocator,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        let map = self.map;
+        //  the entry is in place and counted before the callback runs
+        let old = core::mem::replace(&mut map.data[self.index], Slot::Occupied(self.key, value));
+        if let Slot::Deleted(_) = old {
+            map.tombstones -= 1;
+        }
+        map.size += 1;
+        map.notify_insert(self.index);
+        match &mut map.data[self.index] {
+            Slot::Occupied(_, v) => v,
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub struct RawEntryBuilderMut<'a, K, V, S = RandomState, A = Global>
+where
+    K: Key,
+    V: Value,
+    A: Allocator,
+{
+    map: &'a mut HashMap<K, V, S, A>,
+}
+
+pub enum RawEntryMut<'a, K, V, S = RandomState, A = Global>
+where
+    K: Key,
+    V: Value,
+    A: Allocator,
+{
+    Occupied(OccupiedEntry<'a, K, V, S, A>),
+    Vacant(RawVacantEntryMut<'a, K, V, S, A>),
+}
+
+pub struct RawVacantEntryMut<'a, K, V, S = RandomState, A = Global>
+where
+    K: Key,
+    V: Value,
+    A: Allocator,
+{
+    map: &'a mut HashMap<K, V, S, A>,
+    index: usize,
+}
+
+impl<'a, K, V, S, A> RawEntryBuilderMut<'a, K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    pub fn from_key(self, key: &K) -> Result<RawEntryMut<'a, K, V, S, A>, HashMapError> {
+        let hash = self.map.hash_builder.hash_one(key);
+        self.from_hash(hash, |k| k == key)
+    }
+
+    //  Probes from the slot `hash` picks, returning the first entry
+    //  `is_match` accepts. `hash` must be what `hasher()` gives the key being
+    //  looked for, or the probe starts in the wrong place. Grows the table
+    //  first if needed, so a vacant entry always has room
+    pub fn from_hash<F: FnMut(&K) -> bool>(
+        self,
+        hash: u64,
+        mut is_match: F,
+    ) -> Result<RawEntryMut<'a, K, V, S, A>, HashMapError> {
+        let map = self.map;
+        if map.at_load_limit() {
+            if map.tombstone_ratio() > TOMBSTONE_RATIO_LIMIT {
+                map.rehash_in_place();
+            } else {
+                map.resize();
+            }
+        }
+
+        let index = (hash as usize) % map.capacity;
+        let mut first_deleted = None;
+        for step in 0..map.capacity {
+            let current_index = (index + step) % map.capacity;
+            match map.data.get(current_index) {
+                Some(Slot::Empty) => {
+                    let index = first_deleted.unwrap_or(current_index);
+                    return Ok(RawEntryMut::Vacant(RawVacantEntryMut { map, index }));
                 }
-                Some(Entry::Occupied(k, _v)) => {
-                    if *k == key {
-                        self.data[current_index] = Entry::Deleted(key);
-                        self.size -= 1;
-                        return anyhow::Ok(());
-                    }
-                    current_index = (current_index + 1) % self.capacity;
+                Some(Slot::Deleted(_)) => {
+                    first_deleted.get_or_insert(current_index);
                 }
-                None => {
-                    bail!("entry at {index} cannot be found. seems like an issue with the hash function")
+                Some(Slot::Occupied(k, _)) => {
+                    if is_match(k) {
+                        return Ok(RawEntryMut::Occupied(OccupiedEntry {
+                            map,
+                            index: current_index,
+                        }));
+                    }
                 }
+                None => return Err(HashMapError::HashInvariantViolated),
             };
-            if current_index == index {
-                return Err(anyhow!(
-                    "entry for key {key} cannot be found, so it was not deleted"
-                ));
-            }
         }
+        match first_deleted {
+            Some(index) => Ok(RawEntryMut::Vacant(RawVacantEntryMut { map, index })),
+            None => Err(HashMapError::Full),
+        }
+    }
+}
+
+impl<'a, K, V, S, A> RawVacantEntryMut<'a, K, V, S, A>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+    A: Allocator,
+{
+    //  `key` must hash to the value the entry was found with
+    pub fn insert(self, key: K, value: V) -> &'a mut V {
+        VacantEntry {
+            map: self.map,
+            key,
+            index: self.index,
+        }
+        .insert(value)
     }
 }
 
@@ -189,14 +1620,14 @@ where
 mod tests {
     use super::*;
 
+    map_tests!();
+    open_map_tests!();
+
     #[test]
     fn test_hashmap() {
         let mut map: HashMap<String, String> = HashMap::new(10);
         map.insert("key".to_string(), "value".to_string()).unwrap();
-        assert_eq!(
-            map.get("key".to_string()).unwrap(),
-            Some("value".to_string())
-        );
+        assert_eq!(map.get("key".to_string()), Some("value".to_string()));
     }
 
     #[test]
@@ -210,7 +1641,7 @@ mod tests {
 
         for i in 0..100 {
             let key = format!("Key{i}");
-            let value = map.get(key).unwrap();
+            let value = map.get(key);
             assert_eq!(value, Some(format!("Value{i}")));
         }
     }
@@ -234,14 +1665,57 @@ mod tests {
         for i in 0..100 {
             if i % 5 == 0 {
                 let key = format!("Key{i}");
-                assert_eq!(map.get(key).unwrap(), None);
+                assert_eq!(map.get(key), None);
             } else {
                 let key = format!("Key{i}");
-                assert_eq!(map.get(key).unwrap(), Some(format!("Value{i}")));
+                assert_eq!(map.get(key), Some(format!("Value{i}")));
             }
         }
     }
 
+    #[test]
+    fn test_count_tombstones() {
+        let mut map: HashMap<u64, u64> = HashMap::new(64);
+        for i in 0..40 {
+            map.insert(i, i).unwrap();
+        }
+        for i in (0..40).step_by(4) {
+            map.delete(i).unwrap();
+        }
+        assert_eq!(map.count_tombstones(), 10);
+        assert_eq!(map.count_tombstones(), map.tombstones);
+
+        map.rehash_in_place();
+        assert_eq!(map.count_tombstones(), 0);
+        for i in 0..40 {
+            let expected = if i % 4 == 0 { None } else { Some(i) };
+            assert_eq!(map.get(i), expected);
+        }
+    }
+
+    #[test]
+    fn test_error_variants() {
+        let mut map: HashMap<u64, u64> = HashMap::new(16);
+        //  fill every slot behind the map's back so no resize is triggered
+        for (i, entry) in map.data.iter_mut().enumerate() {
+            *entry = Slot::Occupied(i as u64, i as u64);
+        }
+        match map.insert(100, 100) {
+            Err(HashMapError::Full) => {}
+            other => panic!("expected Full, got {:?}", other),
+        }
+        match map.delete(100) {
+            Err(HashMapError::KeyNotFound) => {}
+            other => panic!("expected KeyNotFound, got {:?}", other),
+        }
+
+        map.data.clear();
+        match map.delete(0) {
+            Err(HashMapError::HashInvariantViolated) => {}
+            other => panic!("expected HashInvariantViolated, got {:?}", other),
+        }
+    }
+
     #[test]
     fn profile_memory_patterns() {
         let mut map: HashMap<String, String> = HashMap::new(16);
@@ -268,4 +1742,350 @@ mod tests {
             }
         }
     }
+
+    thread_local! {
+        static VALUE_CLONES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    #[derive(Default, Debug, PartialEq)]
+    struct CountedClone(u64);
+
+    impl Clone for CountedClone {
+        fn clone(&self) -> Self {
+            VALUE_CLONES.with(|clones| clones.set(clones.get() + 1));
+            CountedClone(self.0)
+        }
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut map: HashMap<i32, i32> = HashMap::new(16);
+        for i in 0..40 {
+            map.insert(i, i).unwrap();
+            map.check_invariants().unwrap();
+        }
+        for i in 0..20 {
+            map.delete(i).unwrap();
+            map.check_invariants().unwrap();
+        }
+
+        map.set_size_for_test(3);
+        assert!(map.check_invariants().unwrap_err().contains("size"));
+        map.set_size_for_test(20);
+        map.check_invariants().unwrap();
+
+        //  plant a second copy of a key further along its probe sequence
+        let index = map.find_index(&25).unwrap();
+        let mut dup = (index + 1) % map.capacity;
+        while matches!(map.data[dup], Slot::Occupied(_, _)) {
+            dup = (dup + 1) % map.capacity;
+        }
+        if matches!(map.data[dup], Slot::Deleted(_)) {
+            map.tombstones -= 1;
+        }
+        map.data[dup] = Slot::Occupied(25, 0);
+        map.set_size_for_test(21);
+        assert!(map.check_invariants().unwrap_err().contains("duplicate"));
+    }
+
+    //  Hands every request to Global and counts the allocations
+    #[derive(Clone, Default)]
+    struct CountingAlloc(std::rc::Rc<std::cell::Cell<usize>>);
+
+    unsafe impl Allocator for CountingAlloc {
+        fn allocate(
+            &self,
+            layout: allocator_api2::alloc::Layout,
+        ) -> Result<std::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(
+            &self,
+            ptr: std::ptr::NonNull<u8>,
+            layout: allocator_api2::alloc::Layout,
+        ) {
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_new_in_uses_allocator() {
+        let alloc = CountingAlloc::default();
+        let mut map = HashMap::new_in(16, alloc.clone());
+        assert_eq!(alloc.0.get(), 1);
+        for i in 0..100 {
+            map.insert(i, i).unwrap();
+        }
+        //  one table per resize on top of the initial one
+        assert!(map.resize_count() > 0);
+        assert_eq!(alloc.0.get(), 1 + map.resize_count());
+
+        let doubled = map.map_values(|v| v * 2);
+        assert_eq!(alloc.0.get(), 2 + map.resize_count());
+        assert_eq!(doubled.get(7), Some(14));
+        map.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_with_seed_is_reproducible() {
+        let build = || {
+            let mut map = HashMap::with_seed(16, 42);
+            for i in 0..100 {
+                map.insert(i, i * 2).unwrap();
+            }
+            for i in (0..100).step_by(3) {
+                map.delete(i).unwrap();
+            }
+            map
+        };
+        let (first, second) = (build(), build());
+        let layout = |map: &HashMap<i32, i32, FixedSeedState>| -> Vec<Option<(i32, Option<i32>)>> {
+            map.data
+                .iter()
+                .map(|slot| match slot {
+                    Slot::Empty => None,
+                    Slot::Deleted(k) => Some((*k, None)),
+                    Slot::Occupied(k, v) => Some((*k, Some(*v))),
+                })
+                .collect()
+        };
+        assert_eq!(layout(&first), layout(&second));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_par_reserve_matches_reserve() {
+        let build = || {
+            let mut map = HashMap::with_seed(16, 7);
+            for i in 0..20_000 {
+                map.insert(i, i.to_string()).unwrap();
+            }
+            for i in (0..20_000).step_by(5) {
+                map.delete(i).unwrap();
+            }
+            map
+        };
+        let (mut serial, mut parallel) = (build(), build());
+        serial.reserve(100_000);
+        parallel.par_reserve(100_000);
+
+        assert_eq!(parallel.capacity(), serial.capacity());
+        assert_eq!(parallel.len(), serial.len());
+        assert_eq!(parallel.count_tombstones(), 0);
+        parallel.check_invariants().unwrap();
+        for (key, value) in serial.iter() {
+            assert_eq!(parallel.get_ref(key), Some(value));
+        }
+        for i in 0..100_000 {
+            parallel.insert(20_000 + i, String::new()).unwrap();
+        }
+        parallel.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_insert_and_delete_callbacks() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut map: HashMap<u32, u32> = HashMap::new(16);
+        let log = Arc::clone(&events);
+        map.on_insert(move |key| log.lock().unwrap().push(("insert", *key)));
+        let log = Arc::clone(&events);
+        map.on_delete(move |key| log.lock().unwrap().push(("delete", *key)));
+
+        map.insert(1, 10).unwrap();
+        map.insert(2, 20).unwrap();
+        map.insert(1, 11).unwrap();
+        map.entry(3).unwrap().or_insert(30);
+        //  neither changes the map, so neither is reported
+        assert!(map.try_insert(2, 21).is_err());
+        map.delete(4).unwrap();
+        map.delete(2).unwrap();
+        map.compute_if_present(3, |_, _| None);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ("insert", 1),
+                ("insert", 2),
+                ("insert", 1),
+                ("insert", 3),
+                ("delete", 2),
+                ("delete", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut original = HashMap::with_seed(16, 0);
+        for i in 0..500 {
+            original.insert(i, i.to_string()).unwrap();
+        }
+        original.delete(7).unwrap();
+
+        let mut copy = original.clone();
+        assert!(copy == original);
+        copy.check_invariants().unwrap();
+        copy.insert(0, "changed".to_string()).unwrap();
+        copy.delete(1).unwrap();
+        copy.insert(7, "back".to_string()).unwrap();
+        assert_eq!(original.get(0), Some("0".to_string()));
+        assert_eq!(original.get(1), Some("1".to_string()));
+        assert_eq!(original.get(7), None);
+        assert_eq!(original.len(), 499);
+
+        //  clone_from refills a larger map without reallocating its table
+        let mut target = HashMap::with_seed(4096, 0);
+        for i in 0..2_000 {
+            target.insert(i * 7, "stale".to_string()).unwrap();
+        }
+        let table = target.data.as_ptr();
+        target.clone_from(&original);
+        assert!(target == original);
+        assert_eq!(target.data.as_ptr(), table);
+        target.check_invariants().unwrap();
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_par_extend_matches_serial_inserts() {
+        //  every key shows up twice, and the second value has to win
+        let pairs: Vec<(u64, u64)> = (0..50_000u64)
+            .chain(0..50_000)
+            .enumerate()
+            .map(|(i, key)| (key, i as u64))
+            .collect();
+
+        let mut serial = HashMap::with_seed(16, 1);
+        serial.insert(7, 0).unwrap();
+        for &(key, value) in &pairs {
+            serial.insert(key, value).unwrap();
+        }
+        let mut parallel = HashMap::with_seed(16, 1);
+        parallel.insert(7, 0).unwrap();
+        parallel.par_extend(pairs).unwrap();
+
+        assert!(parallel == serial);
+        assert_eq!(parallel.get_ref(&7), Some(&50_007));
+        parallel.check_invariants().unwrap();
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_par_extend_into_a_map_with_tombstones() {
+        let mut map = HashMap::with_seed(16, 2);
+        for i in 0..20_000u64 {
+            map.insert(i, i).unwrap();
+        }
+        for i in (0..20_000u64).step_by(2) {
+            map.delete(i).unwrap();
+        }
+        assert!(map.count_tombstones() > 0);
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let seen = calls.clone();
+        map.on_insert(move |_| {
+            seen.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        });
+
+        //  half the keys overwrite surviving entries, half are new
+        let pairs: Vec<(u64, u64)> = (10_000..30_000u64).map(|i| (i, i + 1)).collect();
+        map.par_extend(pairs).unwrap();
+
+        map.check_invariants().unwrap();
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 20_000);
+        assert_eq!(map.len(), 5_000 + 20_000);
+        for i in 0..30_000u64 {
+            let expected = match i {
+                0..=9_999 => (i % 2 == 1).then_some(i),
+                _ => Some(i + 1),
+            };
+            assert_eq!(map.get(i), expected);
+        }
+    }
+
+    #[test]
+    fn test_fill_to_load_limit_boundary() {
+        for limit in [0.5, 0.7, 0.9, 0.99] {
+            let mut map: HashMap<u64, u64, FixedSeedState> = HashMapBuilder::new()
+                .capacity(16)
+                .load_factor_limit(limit)
+                .hasher(FixedSeedState::new(3))
+                .build();
+            for i in 0..2_000 {
+                map.insert(i, i * 3).unwrap();
+                //  the insert just made never takes the last empty slot
+                assert!(map.data.iter().any(|slot| matches!(slot, Slot::Empty)));
+                if i % 7 == 0 {
+                    map.delete(i / 2).unwrap();
+                    map.insert(i / 2, i / 2 * 3).unwrap();
+                }
+            }
+            for i in 0..2_000 {
+                assert_eq!(map.get(i), Some(i * 3), "limit {limit}");
+            }
+            assert_eq!(map.get(2_000), None);
+            assert_eq!(map.len(), 2_000);
+            map.check_invariants().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_entry_remove_survives_a_panicking_callback() {
+        let mut map = HashMap::with_seed(16, 0);
+        for i in 0..100 {
+            map.insert(i, i).unwrap();
+        }
+        map.on_delete(|key| assert_ne!(*key, 42, "refusing to report 42"));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let Entry::Occupied(entry) = map.entry(42).unwrap() else {
+                panic!("key 42 is in the map");
+            };
+            entry.remove();
+        }));
+        assert!(result.is_err());
+        //  the entry was removed before the callback ran
+        map.check_invariants().unwrap();
+        assert_eq!(map.len(), 99);
+        assert_eq!(map.get(42), None);
+        for i in (0..100).filter(|&i| i != 42) {
+            assert_eq!(map.get(i), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_entry_remove_reports_the_delete() {
+        let mut map: HashMap<i32, String> = HashMap::new(16);
+        map.insert(1, "one".to_string()).unwrap();
+        let deleted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = deleted.clone();
+        map.on_delete(move |key| seen.lock().unwrap().push(*key));
+
+        let Entry::Occupied(entry) = map.entry(1).unwrap() else {
+            panic!("key 1 is in the map");
+        };
+        assert_eq!(entry.remove(), "one");
+        assert_eq!(*deleted.lock().unwrap(), vec![1]);
+        map.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_vacant_insert_survives_a_panicking_callback() {
+        let mut map = HashMap::with_seed(16, 0);
+        for i in 0..10 {
+            map.insert(i, i).unwrap();
+        }
+        map.delete(3).unwrap();
+        map.on_insert(|key| assert_ne!(*key, 3, "refusing to report 3"));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.insert(3, 30).unwrap();
+        }));
+        assert!(result.is_err());
+        //  the entry was stored before the callback ran
+        map.check_invariants().unwrap();
+        assert_eq!(map.len(), 10);
+        assert_eq!(map.get(3), Some(30));
+    }
 }
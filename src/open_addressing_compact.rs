@@ -1,32 +1,59 @@
 #![allow(dead_code)]
-use anyhow::{anyhow, bail};
-use std::{
-    fmt::Display,
-    hash::{DefaultHasher, Hash, Hasher},
+use crate::builder::{FromBuilder, HashMapBuilder};
+use crate::group::{Group, DELETED_TAG, EMPTY_TAG, GROUP_WIDTH};
+use crate::snapshot::Snapshot;
+use crate::{
+    CapacityPolicy, FixedSeedState, HashMapError, OccupiedError, RandomState, TryReserveError,
+};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+use core::{
+    borrow::Borrow,
+    fmt::{Debug, Display},
+    hash::{BuildHasher, Hash},
+    mem::MaybeUninit,
+    ops::AddAssign,
 };
 
-pub trait Key: Hash + Clone + PartialEq + Display + Default {}
-impl<T> Key for T where T: Hash + Clone + PartialEq + Display + Default {}
+pub trait Key: Hash + Clone + PartialEq + Debug {}
+impl<T> Key for T where T: Hash + Clone + PartialEq + Debug {}
 
-pub trait Value: Clone + Default {}
-impl<T> Value for T where T: Clone + Default {}
+pub trait Value: Clone {}
+impl<T> Value for T where T: Clone {}
 
 const LOAD_FACTOR_LIMIT: f64 = 0.7;
+const DEFAULT_GROWTH_FACTOR: f64 = 2.0;
+const TOMBSTONE_RATIO_LIMIT: f64 = 0.3;
 
 // 2 bits per entry: 00 = empty, 01 = deleted, 11 = occupied
 const EMPTY: u8 = 0b00;
 const DELETED: u8 = 0b01;
 const OCCUPIED: u8 = 0b11;
 
-pub struct HashMap<K, V>
+// Called with the old and new capacity each time the table grows
+type ResizeCallback = Box<dyn FnMut(usize, usize) + Send + Sync>;
+
+pub struct HashMap<K, V, S = RandomState>
 where
     K: Key,
     V: Value,
 {
     status_bits: Vec<u8>,
-    entries: Vec<(K, V)>,
+    // One control byte per slot: the 7-bit h2 fingerprint of an occupied
+    // slot's hash, or EMPTY_TAG / DELETED_TAG. Lookups scan these a group at
+    // a time and compare the full key only on a fingerprint match. The first
+    // group is mirrored past the end so a group load never has to wrap
+    fingerprints: Vec<u8>,
+    // Only slots whose status is OCCUPIED hold an initialized pair
+    entries: Vec<MaybeUninit<(K, V)>>,
     capacity: usize,
     size: usize,
+    tombstones: usize,
+    resizes: usize,
+    growth_factor: f64,
+    load_factor_limit: f64,
+    capacity_policy: CapacityPolicy,
+    on_resize: Option<ResizeCallback>,
+    hash_builder: S,
 }
 
 impl<K, V> HashMap<K, V>
@@ -34,28 +61,94 @@ where
     K: Key,
     V: Value,
 {
+    // `capacity` is rounded up to a power of two of at least 16. Anything too
+    // large to ever allocate, up to usize::MAX, starts the map at 16 instead
     pub fn new(capacity: usize) -> Self {
-        let initial_capacity = 16.max(capacity.next_power_of_two());
-        let status_size = (initial_capacity + 3) / 4; // Round up to nearest byte
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    // The power-of-two capacity that keeps `entries` pairs below `load_factor`,
+    // for sizing a map up front with `new`
+    pub fn capacity_for(entries: usize, load_factor: f64) -> usize {
+        crate::capacity::capacity_for_entries(entries, load_factor)
+    }
+
+    // Bytes `memory_usage` reports for a map made with `new(capacity_for(entries,
+    // ..))` at the default load factor limit once it holds `entries` pairs
+    pub fn estimate_memory(entries: usize) -> usize {
+        let capacity = Self::capacity_for(entries, LOAD_FACTOR_LIMIT);
+        capacity * core::mem::size_of::<(K, V)>() + capacity.div_ceil(4) + capacity + GROUP_WIDTH
+    }
+}
+
+impl<K, V> HashMap<K, V, FixedSeedState>
+where
+    K: Key,
+    V: Value,
+{
+    // Same seed and same operations give the same layout on every run
+    pub fn with_seed(capacity: usize, seed: u64) -> Self {
+        Self::with_hasher(capacity, FixedSeedState::new(seed))
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    // Like `new`, but keys are hashed with `hash_builder`
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        let initial_capacity =
+            crate::capacity::initial_capacity(capacity, core::mem::size_of::<(K, V)>());
+        let status_size = initial_capacity.div_ceil(4); // Round up to nearest byte
 
         Self {
             status_bits: vec![0; status_size],
-            entries: vec![(K::default(), V::default()); initial_capacity],
+            fingerprints: vec![EMPTY_TAG; initial_capacity + GROUP_WIDTH],
+            entries: Self::uninit_entries(initial_capacity),
             capacity: initial_capacity,
             size: 0,
+            tombstones: 0,
+            resizes: 0,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            load_factor_limit: LOAD_FACTOR_LIMIT,
+            capacity_policy: CapacityPolicy::default(),
+            on_resize: None,
+            hash_builder,
         }
     }
 
+    fn uninit_entries(capacity: usize) -> Vec<MaybeUninit<(K, V)>> {
+        let mut entries = Vec::with_capacity(capacity);
+        entries.resize_with(capacity, MaybeUninit::uninit);
+        entries
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
     fn hash(&self, key: &K) -> usize {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        (hasher.finish() as usize) % self.capacity
+        self.hash_with_fingerprint(key).0
+    }
+
+    fn hash_with_fingerprint(&self, key: &K) -> (usize, u8) {
+        self.split_hash(self.hash_builder.hash_one(key))
+    }
+
+    // Low bits of the hash pick the slot, the top 7 bits are the fingerprint
+    fn split_hash(&self, hash: u64) -> (usize, u8) {
+        ((hash as usize) % self.capacity, (hash >> 57) as u8)
+    }
+
+    fn matches(&self, index: usize, fingerprint: u8, key: &K) -> bool {
+        self.fingerprints[index] == fingerprint && self.slot(index).0 == *key
     }
 
     fn get_status(&self, index: usize) -> u8 {
-        let byte_idx = index / 4;
-        let bit_offset = (index % 4) * 2;
-        (self.status_bits[byte_idx] >> bit_offset) & 0b11
+        status_at(&self.status_bits, index)
     }
 
     fn set_status(&mut self, index: usize, status: u8) {
@@ -67,112 +160,853 @@ where
         self.status_bits[byte_idx] |= (status & 0b11) << bit_offset;
     }
 
-    pub fn get(&self, key: K) -> anyhow::Result<Option<V>> {
-        let index = self.hash(&key);
-        let mut current_index = index;
+    // Unchecked versions of the above for the probe loops.
+    //
+    // SAFETY: callers must pass `index < self.capacity`. `status_bits` holds
+    // `capacity.div_ceil(4)` bytes, so `index / 4` is then always in bounds.
+    // Probe indices are reduced modulo capacity, which upholds this
+    unsafe fn get_status_unchecked(&self, index: usize) -> u8 {
+        debug_assert!(index < self.capacity);
+        let byte = *self.status_bits.get_unchecked(index / 4);
+        (byte >> ((index % 4) * 2)) & 0b11
+    }
 
-        loop {
-            match self.get_status(current_index) {
-                EMPTY => return Ok(None),
-                OCCUPIED => {
-                    if self.entries[current_index].0 == key {
-                        return Ok(Some(self.entries[current_index].1.clone()));
-                    }
-                    current_index = (current_index + 1) % self.capacity;
-                }
-                DELETED => {
-                    current_index = (current_index + 1) % self.capacity;
+    // SAFETY: same contract as `get_status_unchecked`
+    unsafe fn set_status_unchecked(&mut self, index: usize, status: u8) {
+        debug_assert!(index < self.capacity);
+        let bit_offset = (index % 4) * 2;
+        let byte = self.status_bits.get_unchecked_mut(index / 4);
+        *byte &= !(0b11 << bit_offset);
+        *byte |= (status & 0b11) << bit_offset;
+    }
+
+    // Every access to `entries` goes through the four helpers below, which
+    // keep the invariant that a slot is initialized exactly when its status
+    // is OCCUPIED
+    fn slot(&self, index: usize) -> &(K, V) {
+        assert_eq!(self.get_status(index), OCCUPIED);
+        // SAFETY: the slot is OCCUPIED, so it holds an initialized pair
+        unsafe { self.entries[index].assume_init_ref() }
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut (K, V) {
+        assert_eq!(self.get_status(index), OCCUPIED);
+        // SAFETY: the slot is OCCUPIED, so it holds an initialized pair
+        unsafe { self.entries[index].assume_init_mut() }
+    }
+
+    // Moves the pair out of an occupied slot and marks it with `status`
+    fn take_slot(&mut self, index: usize, status: u8) -> (K, V) {
+        assert_eq!(self.get_status(index), OCCUPIED);
+        // SAFETY: the checked read above proved `index` is in range
+        unsafe { self.set_status_unchecked(index, status) };
+        let tag = if status == DELETED {
+            DELETED_TAG
+        } else {
+            EMPTY_TAG
+        };
+        self.set_fingerprint(index, tag);
+        // SAFETY: the slot was OCCUPIED and is no longer marked as such, so
+        // the pair is read out exactly once
+        unsafe { self.entries[index].assume_init_read() }
+    }
+
+    fn fill_slot(&mut self, index: usize, fingerprint: u8, pair: (K, V)) -> &mut (K, V) {
+        assert_ne!(self.get_status(index), OCCUPIED);
+        // SAFETY: the checked read above proved `index` is in range
+        unsafe { self.set_status_unchecked(index, OCCUPIED) };
+        self.set_fingerprint(index, fingerprint);
+        self.entries[index].write(pair)
+    }
+
+    fn set_fingerprint(&mut self, index: usize, fingerprint: u8) {
+        self.fingerprints[index] = fingerprint;
+        if index < GROUP_WIDTH {
+            self.fingerprints[self.capacity + index] = fingerprint;
+        }
+    }
+
+    // Returns the slot index holding the key, if it is present. Scans a whole
+    // group of control bytes per step and stops at the first group with an
+    // empty slot, since the key would have been placed before it
+    fn find_index(&self, key: &K) -> Option<usize> {
+        let (index, fingerprint) = self.hash_with_fingerprint(key);
+        let mut group_start = index;
+        let mut scanned = 0;
+
+        while scanned < self.capacity {
+            let group = Group::load(&self.fingerprints[group_start..]);
+            for bit in group.match_byte(fingerprint) {
+                let current_index = (group_start + bit) % self.capacity;
+                if self.slot(current_index).0 == *key {
+                    return Some(current_index);
                 }
-                _ => unreachable!("Invalid status bits"),
             }
-
-            if current_index == index {
-                return Ok(None);
+            if group.match_empty().any() {
+                return None;
             }
+            group_start = (group_start + GROUP_WIDTH) % self.capacity;
+            scanned += GROUP_WIDTH;
         }
+        None
+    }
+
+    pub fn get(&self, key: K) -> Option<V> {
+        self.get_ref(&key).cloned()
+    }
+
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        self.find_index(key).map(|index| &self.slot(index).1)
+    }
+
+    // Like `get_ref`, but also hands back the key as stored in the map
+    pub fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
+        self.find_index(key).map(|index| {
+            let (k, v) = self.slot(index);
+            (k, v)
+        })
+    }
+
+    // Returns the copy of `key` already stored, or stores `key` under a
+    // default value and returns a copy of it. With a key type that shares its
+    // contents on clone, such as Arc<str>, every equal key handed in comes
+    // back as the same allocation
+    pub fn intern(&mut self, key: K) -> Result<K, HashMapError>
+    where
+        V: Default,
+    {
+        Ok(match self.entry(key)? {
+            Entry::Occupied(entry) => entry.key().clone(),
+            Entry::Vacant(entry) => {
+                let key = entry.key().clone();
+                entry.insert(V::default());
+                key
+            }
+        })
+    }
+
+    pub fn get_many<'a>(&self, keys: impl IntoIterator<Item = &'a K>) -> Vec<Option<&V>>
+    where
+        K: 'a,
+    {
+        keys.into_iter().map(|key| self.get_ref(key)).collect()
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.find_index(&key)
+            .map(|index| &mut self.slot_mut(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        (0..self.capacity)
+            .filter(|&index| self.get_status(index) == OCCUPIED)
+            .map(|index| {
+                let (k, v) = self.slot(index);
+                (k, v)
+            })
+    }
+
+    // Whether any key maps to `value`. A linear scan over every entry, unlike
+    // the single lookup behind `contains_key`
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.iter().any(|(_, v)| v == value)
+    }
+
+    // Every entry ordered by key, for deterministic output in tests and dumps
+    pub fn iter_sorted(&self) -> Vec<(&K, &V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    // A digest of the entries that ignores their order, so maps that compare
+    // equal always share a fingerprint whatever their capacity or seed
+    pub fn fingerprint(&self) -> u64
+    where
+        V: Hash,
+    {
+        crate::hasher::fingerprint(self.iter())
+    }
+
+    // Copies the current contents into a read-only map that stays as it is
+    // while this one changes
+    pub fn snapshot(&self) -> Arc<Snapshot<K, V, S>>
+    where
+        S: Clone,
+    {
+        Arc::new(Snapshot::from_entries(
+            self.hash_builder.clone(),
+            self.len(),
+            self.iter(),
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find_index(key).is_some()
     }
 
     fn get_load_factor(&self) -> f64 {
-        self.size as f64 / self.capacity as f64
+        (self.size + self.tombstones) as f64 / self.capacity as f64
+    }
+
+    pub fn tombstone_ratio(&self) -> f64 {
+        self.tombstones as f64 / self.capacity as f64
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> anyhow::Result<()> {
-        if self.get_load_factor() >= LOAD_FACTOR_LIMIT {
-            self.resize()?;
+    // The smallest capacity that holds `entries` under the load factor limit
+    fn min_capacity(&self, entries: usize) -> usize {
+        self.checked_min_capacity(entries)
+            .expect("capacity overflow")
+    }
+
+    fn checked_min_capacity(&self, entries: usize) -> Option<usize> {
+        crate::capacity::capacity_for(entries, self.load_factor_limit)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.entries.len() * core::mem::size_of::<(K, V)>()
+            + self.status_bits.len()
+            + self.fingerprints.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Number of times the map has grown because it hit the load factor limit
+    pub fn resize_count(&self) -> usize {
+        self.resizes
+    }
+
+    // Calls `f` with the old and new capacity every time the map grows because
+    // it hit the load factor limit. Explicit `reserve` and `shrink_to_fit`
+    // calls are not reported
+    pub fn on_resize(&mut self, f: impl FnMut(usize, usize) + Send + Sync + 'static) {
+        self.on_resize = Some(Box::new(f));
+    }
+
+    pub fn load_factor_limit(&self) -> f64 {
+        self.load_factor_limit
+    }
+
+    pub fn growth_factor(&self) -> f64 {
+        self.growth_factor
+    }
+
+    // How much the table grows each time it hits the load factor limit. Below
+    // the default of 2 the map resizes more often but overshoots its size by less
+    pub fn set_growth_factor(&mut self, factor: f64) {
+        assert!(factor > 1.0, "the growth factor must be greater than 1");
+        self.growth_factor = factor;
+    }
+
+    pub fn capacity_policy(&self) -> CapacityPolicy {
+        self.capacity_policy
+    }
+
+    // Switches how the table is sized, rebuilding it straight away if the
+    // current capacity does not fit the new policy
+    pub fn set_capacity_policy(&mut self, policy: CapacityPolicy) {
+        self.capacity_policy = policy;
+        let capacity = policy.fit(self.capacity);
+        if capacity != self.capacity {
+            self.rehash(capacity);
         }
+    }
 
-        let index = self.hash(&key);
-        let mut current_index = index;
+    // The capacity the next resize grows to, at least one slot more than now
+    fn grown_capacity(&self) -> usize {
+        let grown = ((self.capacity as f64 * self.growth_factor) as usize).max(self.capacity + 1);
+        self.capacity_policy.fit(grown)
+    }
 
-        loop {
-            match self.get_status(current_index) {
-                EMPTY | DELETED => {
-                    self.entries[current_index] = (key, value);
-                    self.set_status(current_index, OCCUPIED);
-                    self.size += 1;
-                    return Ok(());
+    pub fn reserve(&mut self, additional: usize) {
+        let new_capacity = self
+            .capacity_policy
+            .fit(self.min_capacity(self.size + additional));
+        if new_capacity > self.capacity {
+            self.rehash(new_capacity);
+        }
+    }
+
+    // Like `reserve`, but reports an error instead of panicking when the table
+    // cannot be grown
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let new_capacity = self
+            .size
+            .checked_add(additional)
+            .and_then(|entries| self.checked_min_capacity(entries))
+            .map(|capacity| self.capacity_policy.fit(capacity))
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if new_capacity <= self.capacity {
+            return Ok(());
+        }
+        new_capacity
+            .checked_mul(core::mem::size_of::<(K, V)>())
+            .filter(|&bytes| bytes <= isize::MAX as usize)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let mut new_status = Vec::new();
+        new_status.try_reserve_exact(new_capacity.div_ceil(4))?;
+        new_status.resize(new_capacity.div_ceil(4), 0);
+        let mut new_fingerprints = Vec::new();
+        new_fingerprints.try_reserve_exact(new_capacity + GROUP_WIDTH)?;
+        new_fingerprints.resize(new_capacity + GROUP_WIDTH, EMPTY_TAG);
+        let mut new_entries = Vec::new();
+        new_entries.try_reserve_exact(new_capacity)?;
+        new_entries.resize_with(new_capacity, MaybeUninit::uninit);
+        self.rehash_into(new_status, new_fingerprints, new_entries);
+        Ok(())
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        let new_capacity = self.capacity_policy.fit(self.min_capacity(self.size));
+        if new_capacity < self.capacity {
+            self.rehash(new_capacity);
+        }
+    }
+
+    pub fn count_tombstones(&self) -> usize {
+        (0..self.capacity)
+            .filter(|&index| self.get_status(index) == DELETED)
+            .count()
+    }
+
+    // Walks the whole table checking the bookkeeping against the status bits
+    // and control bytes, for tests and fuzzing. Every occupied key must be
+    // found by a lookup at its own slot, which also rules out duplicates
+    // since only the first copy on a probe sequence is reachable
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.entries.len() != self.capacity
+            || self.status_bits.len() != self.capacity.div_ceil(4)
+            || self.fingerprints.len() != self.capacity + GROUP_WIDTH
+        {
+            return Err(format!(
+                "table lengths do not match capacity {}",
+                self.capacity
+            ));
+        }
+        let mut occupied = 0;
+        let mut deleted = 0;
+        for index in 0..self.capacity {
+            let tag = self.fingerprints[index];
+            match self.get_status(index) {
+                EMPTY if tag != EMPTY_TAG => {
+                    return Err(format!("empty slot {index} has control byte {tag:#x}"))
                 }
+                DELETED if tag != DELETED_TAG => {
+                    return Err(format!("deleted slot {index} has control byte {tag:#x}"))
+                }
+                EMPTY => {}
+                DELETED => deleted += 1,
                 OCCUPIED => {
-                    if self.entries[current_index].0 == key {
-                        self.entries[current_index].1 = value;
-                        return Ok(());
+                    occupied += 1;
+                    let key = &self.slot(index).0;
+                    if tag != self.hash_with_fingerprint(key).1 {
+                        return Err(format!(
+                            "key {key:?} at slot {index} has a stale fingerprint {tag:#x}"
+                        ));
+                    }
+                    match self.find_index(key) {
+                        Some(found) if found == index => {}
+                        Some(found) => {
+                            return Err(format!(
+                            "key {key:?} at slot {index} is shadowed by a duplicate at slot {found}"
+                        ))
+                        }
+                        None => return Err(format!("key {key:?} at slot {index} is not findable")),
                     }
-                    current_index = (current_index + 1) % self.capacity;
                 }
-                _ => unreachable!("Invalid status bits"),
+                status => return Err(format!("slot {index} has invalid status {status:#04b}")),
             }
+        }
+        for index in 0..GROUP_WIDTH.min(self.capacity) {
+            if self.fingerprints[self.capacity + index] != self.fingerprints[index] {
+                return Err(format!(
+                    "mirrored control byte for slot {index} is out of sync"
+                ));
+            }
+        }
+        if occupied != self.size {
+            return Err(format!(
+                "size is {} but {occupied} slots are occupied",
+                self.size
+            ));
+        }
+        if deleted != self.tombstones {
+            return Err(format!(
+                "tombstone count is {} but {deleted} slots are deleted",
+                self.tombstones
+            ));
+        }
+        Ok(())
+    }
 
-            if current_index == index {
-                bail!("HashMap is full");
+    #[cfg(test)]
+    fn set_size_for_test(&mut self, size: usize) {
+        self.size = size;
+    }
+
+    // The slot `key` hashes to and the slot it actually sits in, for looking
+    // at how far collisions push keys. Panics if `key` is not in the map
+    #[cfg(test)]
+    pub(crate) fn find_slot(&self, key: &K) -> (usize, usize) {
+        let actual = self
+            .find_index(key)
+            .expect("find_slot is only asked about keys in the map");
+        (self.hash(key), actual)
+    }
+
+    // Goes through `entry` so an existing key is always updated in place, even
+    // when a tombstone earlier on its probe sequence would take a new key
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        match self.entry(key)? {
+            Entry::Occupied(entry) => *entry.into_mut() = value,
+            Entry::Vacant(entry) => {
+                entry.insert(value);
             }
         }
+        Ok(())
     }
 
-    fn resize(&mut self) -> anyhow::Result<()> {
-        let new_capacity = self.capacity * 2;
-        let new_status_size = (new_capacity + 3) / 4;
+    // Reserves room for the whole batch up front so the loop below never resizes
+    pub fn insert_many(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), HashMapError> {
+        let pairs = pairs.into_iter();
+        self.reserve(pairs.size_hint().0);
+        for (key, value) in pairs {
+            self.insert(key, value)?;
+        }
+        Ok(())
+    }
 
-        let mut new_status = vec![0; new_status_size];
-        let mut new_entries = vec![(K::default(), V::default()); new_capacity];
+    // Finds the slot for `key` in a single probe, growing the table first if needed
+    pub fn entry(&mut self, key: K) -> Result<Entry<'_, K, V, S>, HashMapError> {
+        let hash = self.hash_builder.hash_one(&key);
+        Ok(match self.raw_entry_mut().from_hash(hash, |k| *k == key)? {
+            RawEntryMut::Occupied(entry) => Entry::Occupied(entry),
+            RawEntryMut::Vacant(entry) => Entry::Vacant(VacantEntry {
+                map: entry.map,
+                key,
+                index: entry.index,
+                fingerprint: entry.fingerprint,
+            }),
+        })
+    }
+
+    // Like `entry`, but looks the key up by reference and only builds an
+    // owned key when the entry is vacant, so updating a key that is already
+    // there costs no clone
+    pub fn entry_ref<'q, Q>(&mut self, key: &'q Q) -> Result<Entry<'_, K, V, S>, HashMapError>
+    where
+        K: Borrow<Q> + From<&'q Q>,
+        Q: Hash + PartialEq + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        Ok(
+            match self
+                .raw_entry_mut()
+                .from_hash(hash, |k| k.borrow() == key)?
+            {
+                RawEntryMut::Occupied(entry) => Entry::Occupied(entry),
+                RawEntryMut::Vacant(entry) => Entry::Vacant(VacantEntry {
+                    map: entry.map,
+                    key: K::from(key),
+                    index: entry.index,
+                    fingerprint: entry.fingerprint,
+                }),
+            },
+        )
+    }
+
+    // Like `entry`, but the slot can be found from a hash the caller already
+    // has, so a key hashed once can be looked up and inserted without
+    // hashing it again
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S> {
+        RawEntryBuilderMut { map: self }
+    }
+
+    pub fn get_or_insert_with_key<F: FnOnce(&K) -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, HashMapError> {
+        Ok(self.entry(key)?.or_insert_with_key(f))
+    }
 
-        // Keep track of old capacity for rehashing
-        let old_capacity = self.capacity;
+    pub fn compute_if_absent<F: FnOnce(&K) -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, HashMapError> {
+        self.get_or_insert_with_key(key, f)
+    }
+
+    // Replaces the value when `f` returns Some and removes the key when it returns None
+    pub fn compute_if_present<F: FnOnce(&K, V) -> Option<V>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Option<&mut V> {
+        let index = self.find_index(&key)?;
+        let fingerprint = self.fingerprints[index];
+        let (key, value) = self.take_slot(index, DELETED);
+        // the entry counts as removed while `f` runs, so a panic in `f`
+        // leaves the counters matching the table
+        self.size -= 1;
+        self.tombstones += 1;
+        match f(&key, value) {
+            Some(value) => {
+                self.size += 1;
+                self.tombstones -= 1;
+                Some(&mut self.fill_slot(index, fingerprint, (key, value)).1)
+            }
+            None => None,
+        }
+    }
+
+    // Builds a map with the same keys and slot layout but transformed values
+    pub fn map_values<W: Value, F: FnMut(&V) -> W>(&self, mut f: F) -> HashMap<K, W, S>
+    where
+        S: Clone,
+    {
+        let entries = (0..self.capacity)
+            .map(|index| match self.get_status(index) {
+                OCCUPIED => {
+                    let (key, value) = self.slot(index);
+                    MaybeUninit::new((key.clone(), f(value)))
+                }
+                _ => MaybeUninit::uninit(),
+            })
+            .collect();
+        HashMap {
+            status_bits: self.status_bits.clone(),
+            fingerprints: self.fingerprints.clone(),
+            entries,
+            capacity: self.capacity,
+            size: self.size,
+            tombstones: self.tombstones,
+            resizes: 0,
+            growth_factor: self.growth_factor,
+            load_factor_limit: self.load_factor_limit,
+            capacity_policy: self.capacity_policy,
+            on_resize: None,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+
+    // Keeps the entries `f` maps to Some, in a map sized for just those entries
+    pub fn filter_map<W: Value, F: FnMut(&K, &V) -> Option<W>>(&self, mut f: F) -> HashMap<K, W, S>
+    where
+        S: Clone,
+    {
+        let kept: Vec<(K, W)> = (0..self.capacity)
+            .filter(|&index| self.get_status(index) == OCCUPIED)
+            .filter_map(|index| {
+                let (key, value) = self.slot(index);
+                f(key, value).map(|value| (key.clone(), value))
+            })
+            .collect();
+        let mut map =
+            HashMap::with_hasher(self.min_capacity(kept.len()), self.hash_builder.clone());
+        for (key, value) in kept {
+            map.insert(key, value)
+                .expect("map was sized to hold every kept entry");
+        }
+        map
+    }
+
+    // Copies `pairs` into a new map hashing like this one, sized for `entries`
+    fn collect_pairs<'a>(&self, entries: usize, pairs: impl Iterator<Item = (&'a K, &'a V)>) -> Self
+    where
+        K: 'a,
+        V: 'a,
+        S: Clone,
+    {
+        let mut map = HashMap::with_hasher(self.min_capacity(entries), self.hash_builder.clone());
+        for (key, value) in pairs {
+            map.insert(key.clone(), value.clone())
+                .expect("map was sized to hold every entry");
+        }
+        map
+    }
+
+    // Every entry in either map, with this map's value for a key in both
+    pub fn union(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let only_other = other.iter().filter(|(key, _)| !self.contains_key(key));
+        self.collect_pairs(self.len() + other.len(), self.iter().chain(only_other))
+    }
+
+    // The entries of this map whose keys are also in `other`. Walks whichever
+    // map is smaller and looks each key up in the other one
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let entries = self.len().min(other.len());
+        if self.len() <= other.len() {
+            self.collect_pairs(
+                entries,
+                self.iter().filter(|(key, _)| other.contains_key(key)),
+            )
+        } else {
+            let shared = other.iter().filter_map(|(key, _)| self.get_key_value(key));
+            self.collect_pairs(entries, shared)
+        }
+    }
+
+    // The entries of this map whose keys are not in `other`
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        self.collect_pairs(
+            self.len(),
+            self.iter().filter(|(key, _)| !other.contains_key(key)),
+        )
+    }
+
+    // The entries whose keys are in exactly one of the two maps
+    pub fn symmetric_difference(&self, other: &Self) -> Self
+    where
+        S: Clone,
+    {
+        let only_self = self.iter().filter(|(key, _)| !other.contains_key(key));
+        let only_other = other.iter().filter(|(key, _)| !self.contains_key(key));
+        self.collect_pairs(self.len() + other.len(), only_self.chain(only_other))
+    }
+
+    // Moves every entry out of `other`, which keeps its capacity for reuse
+    pub fn append(&mut self, other: &mut Self) -> Result<(), HashMapError> {
+        self.reserve(other.size);
+        for index in 0..other.capacity {
+            if other.get_status(index) == OCCUPIED {
+                let (key, value) = other.take_slot(index, EMPTY);
+                match self.entry(key)? {
+                    Entry::Occupied(entry) => *entry.into_mut() = value,
+                    Entry::Vacant(entry) => {
+                        entry.insert(value);
+                    }
+                }
+            }
+        }
+        other.status_bits.fill(0);
+        other.fingerprints.fill(EMPTY_TAG);
+        other.size = 0;
+        other.tombstones = 0;
+        Ok(())
+    }
+
+    // Removes the entries matching `pred` and returns them as a new map
+    pub fn split_off<F: FnMut(&K, &V) -> bool>(&mut self, mut pred: F) -> Self
+    where
+        S: Clone,
+    {
+        let mut split = Vec::new();
+        for index in 0..self.capacity {
+            if self.get_status(index) == OCCUPIED {
+                let (key, value) = self.slot(index);
+                if pred(key, value) {
+                    // counted as each entry leaves, so a panic in `pred`
+                    // leaves the counters matching the table
+                    split.push(self.take_slot(index, DELETED));
+                    self.size -= 1;
+                    self.tombstones += 1;
+                }
+            }
+        }
+        let mut map = Self::with_hasher(self.min_capacity(split.len()), self.hash_builder.clone());
+        for (key, value) in split {
+            map.insert(key, value)
+                .expect("map was sized to hold every split entry");
+        }
+        map
+    }
+
+    // Inserts only when `key` is absent, handing the rejected value back otherwise
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<K, V>> {
+        let entry = self
+            .entry(key.clone())
+            .expect("growing the map always leaves a free slot");
+        match entry {
+            Entry::Occupied(entry) => Err(OccupiedError {
+                key,
+                existing: entry.into_mut().clone(),
+                value,
+            }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+    fn resize(&mut self) -> Result<(), HashMapError> {
+        self.resizes += 1;
+        let (old_capacity, new_capacity) = (self.capacity, self.grown_capacity());
+        self.rehash(new_capacity);
+        if let Some(on_resize) = self.on_resize.as_mut() {
+            on_resize(old_capacity, new_capacity);
+        }
+        Ok(())
+    }
+
+    // Rebuilds the table at its current capacity, dropping all tombstones
+    fn rehash_in_place(&mut self) {
+        self.rehash(self.capacity);
+    }
+
+    fn rehash(&mut self, new_capacity: usize) {
+        let new_status_size = new_capacity.div_ceil(4);
+        self.rehash_into(
+            vec![0; new_status_size],
+            vec![EMPTY_TAG; new_capacity + GROUP_WIDTH],
+            Self::uninit_entries(new_capacity),
+        );
+    }
+
+    fn rehash_into(
+        &mut self,
+        new_status: Vec<u8>,
+        new_fingerprints: Vec<u8>,
+        new_entries: Vec<MaybeUninit<(K, V)>>,
+    ) {
+        let new_capacity = new_entries.len();
+
+        // Each old slot is read out once and the old table never drops its
+        // slots, so a hash that panics part way through leaks the entries not
+        // yet moved rather than dropping any twice. The map is not left
+        // consistent in that case: `size` still counts the leaked entries
+        let old_status = core::mem::replace(&mut self.status_bits, new_status);
+        self.fingerprints = new_fingerprints;
+        let old_entries = core::mem::replace(&mut self.entries, new_entries);
         self.capacity = new_capacity;
+        self.tombstones = 0;
 
         // Rehash all existing entries
-        for i in 0..old_capacity {
-            if self.get_status(i) == OCCUPIED {
-                let (key, value) = std::mem::take(&mut self.entries[i]);
-                let mut new_index = self.hash(&key);
+        for (i, old_slot) in old_entries.iter().enumerate() {
+            if status_at(&old_status, i) == OCCUPIED {
+                // SAFETY: the old slot was OCCUPIED and the old table is
+                // discarded without dropping its slots, so each pair moves once
+                let (key, value) = unsafe { old_slot.assume_init_read() };
+                let (mut new_index, fingerprint) = self.hash_with_fingerprint(&key);
 
                 // Find new slot
-                while (new_status[new_index / 4] >> ((new_index % 4) * 2)) & 0b11 == OCCUPIED {
+                // SAFETY: probe indices are always reduced modulo capacity
+                while unsafe { self.get_status_unchecked(new_index) } == OCCUPIED {
                     new_index = (new_index + 1) % new_capacity;
                 }
 
-                new_entries[new_index] = (key, value);
-                let byte_idx = new_index / 4;
-                let bit_offset = (new_index % 4) * 2;
-                new_status[byte_idx] |= OCCUPIED << bit_offset;
+                self.fill_slot(new_index, fingerprint, (key, value));
             }
         }
+    }
 
-        self.status_bits = new_status;
-        self.entries = new_entries;
-        Ok(())
+    // Removes and returns the entry in the first occupied slot, leaving a
+    // tombstone, for draining a map one entry at a time
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let index = (0..self.capacity).find(|&index| self.get_status(index) == OCCUPIED)?;
+        let pair = self.take_slot(index, DELETED);
+        self.size -= 1;
+        self.tombstones += 1;
+        Some(pair)
+    }
+
+    // Removes every entry, keeping the table at its current capacity
+    pub fn clear(&mut self) {
+        self.drop_pairs();
+        self.status_bits.fill(0);
+        self.fingerprints.fill(EMPTY_TAG);
+        self.size = 0;
+        self.tombstones = 0;
+    }
+
+    // Like `clear`, but also gives the table back and starts over at the
+    // smallest capacity, so a long-lived map does not keep the peak size of
+    // an earlier phase
+    pub fn reset(&mut self) {
+        self.clear();
+        let capacity = self.capacity_policy.fit(crate::capacity::MIN_CAPACITY);
+        self.status_bits = vec![0; capacity.div_ceil(4)];
+        self.fingerprints = vec![EMPTY_TAG; capacity + GROUP_WIDTH];
+        self.entries = Self::uninit_entries(capacity);
+        self.capacity = capacity;
+    }
+
+    // Keeps only the entries `f` returns true for
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, f: F) {
+        self.retain_count(f);
     }
 
-    pub fn delete(&mut self, key: K) -> anyhow::Result<()> {
-        let index = self.hash(&key);
+    // Like `retain`, but returns how many entries were removed. Only live
+    // entries are offered to `f`, so the count is exactly the number of
+    // slots that turned into tombstones
+    pub fn retain_count<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> usize {
+        let mut removed = 0;
+        for index in 0..self.capacity {
+            if self.get_status(index) == OCCUPIED {
+                let (key, value) = self.slot(index);
+                if !f(key, value) {
+                    self.take_slot(index, DELETED);
+                    self.size -= 1;
+                    self.tombstones += 1;
+                    removed += 1;
+                }
+            }
+        }
+        removed
+    }
+
+    // Deletes every key in `keys`, returning how many were present. Tombstones
+    // are only cleaned up once at the end, rather than as each delete adds one
+    pub fn delete_many<'a>(&mut self, keys: impl IntoIterator<Item = &'a K>) -> usize
+    where
+        K: 'a,
+    {
+        let mut removed = 0;
+        for key in keys {
+            if let Some(index) = self.find_index(key) {
+                self.take_slot(index, DELETED);
+                removed += 1;
+            }
+        }
+        self.size -= removed;
+        self.tombstones += removed;
+        if self.tombstone_ratio() > TOMBSTONE_RATIO_LIMIT {
+            self.rehash_in_place();
+        }
+        removed
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        let (index, fingerprint) = self.hash_with_fingerprint(&key);
         let mut current_index = index;
 
         loop {
-            match self.get_status(current_index) {
+            // SAFETY: probe indices are always reduced modulo capacity
+            match unsafe { self.get_status_unchecked(current_index) } {
                 EMPTY => return Ok(()),
                 OCCUPIED => {
-                    if self.entries[current_index].0 == key {
-                        self.set_status(current_index, DELETED);
+                    if self.matches(current_index, fingerprint, &key) {
+                        debug_assert!(self.size > 0, "deleting from a map whose size is 0");
+                        self.take_slot(current_index, DELETED);
                         self.size -= 1;
+                        self.tombstones += 1;
                         return Ok(());
                     }
                     current_index = (current_index + 1) % self.capacity;
@@ -180,28 +1014,494 @@ where
                 DELETED => {
                     current_index = (current_index + 1) % self.capacity;
                 }
-                _ => unreachable!("Invalid status bits"),
+                // 0b10 is never written, so the status bits are corrupt
+                _ => return Err(HashMapError::HashInvariantViolated),
+            }
+
+            if current_index == index {
+                return Err(HashMapError::KeyNotFound);
+            }
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Key,
+    V: Value + AddAssign + From<u8>,
+    S: BuildHasher,
+{
+    // Bumps the count for `key`, starting it at zero if absent
+    pub fn increment(&mut self, key: K) -> Result<(), HashMapError> {
+        *self.entry(key)?.or_insert(V::from(0)) += V::from(1);
+        Ok(())
+    }
+
+    // The `n` keys with the highest counts, highest first
+    pub fn most_common(&self, n: usize) -> Vec<(K, V)>
+    where
+        V: Ord,
+    {
+        let mut counts: Vec<(K, V)> = (0..self.capacity)
+            .filter(|&index| self.get_status(index) == OCCUPIED)
+            .map(|index| self.slot(index).clone())
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1));
+        counts.truncate(n);
+        counts
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+{
+    // Drops every pair in place. The caller must mark the slots free again
+    // or never read them, since they no longer hold initialized pairs
+    fn drop_pairs(&mut self) {
+        if !core::mem::needs_drop::<(K, V)>() {
+            return;
+        }
+        for index in 0..self.capacity {
+            if status_at(&self.status_bits, index) == OCCUPIED {
+                // SAFETY: OCCUPIED slots hold an initialized pair, and the
+                // caller guarantees it is not read again
+                unsafe { self.entries[index].assume_init_drop() };
+            }
+        }
+    }
+
+    // Writes a clone of every pair in `self` into the same slot of `entries`,
+    // which must be at least `self.capacity` long. A panicking clone leaks the
+    // pairs already written instead of dropping them
+    fn clone_pairs_into(&self, entries: &mut [MaybeUninit<(K, V)>]) {
+        for (index, slot) in entries.iter_mut().enumerate().take(self.capacity) {
+            if status_at(&self.status_bits, index) == OCCUPIED {
+                // SAFETY: OCCUPIED slots hold an initialized pair
+                let pair = unsafe { self.entries[index].assume_init_ref() };
+                slot.write(pair.clone());
+            }
+        }
+    }
+}
+
+impl<K, V, S> Drop for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+{
+    fn drop(&mut self) {
+        self.drop_pairs();
+    }
+}
+
+// A clone starts without the original's on_resize callback
+impl<K, V, S> Clone for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        let mut entries = Vec::with_capacity(self.capacity);
+        entries.resize_with(self.capacity, MaybeUninit::uninit);
+        self.clone_pairs_into(&mut entries);
+        Self {
+            status_bits: self.status_bits.clone(),
+            fingerprints: self.fingerprints.clone(),
+            entries,
+            capacity: self.capacity,
+            size: self.size,
+            tombstones: self.tombstones,
+            resizes: self.resizes,
+            growth_factor: self.growth_factor,
+            load_factor_limit: self.load_factor_limit,
+            capacity_policy: self.capacity_policy,
+            on_resize: None,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+
+    // Copies into the existing control bytes and entry storage, which are only
+    // reallocated when they are smaller than `source`'s. Keeps the on_resize
+    // callback registered on `self`
+    fn clone_from(&mut self, source: &Self) {
+        // Empty the map first, so a panicking clone below leaves an empty map
+        // that leaks some pairs rather than one with uninitialized OCCUPIED slots
+        self.drop_pairs();
+        self.status_bits.clear();
+        self.status_bits.resize(source.status_bits.len(), 0);
+        self.fingerprints.clear();
+        self.fingerprints
+            .resize(source.fingerprints.len(), EMPTY_TAG);
+        self.entries.clear();
+        self.entries
+            .resize_with(source.capacity, MaybeUninit::uninit);
+        self.capacity = source.capacity;
+        self.size = 0;
+        self.tombstones = 0;
+
+        source.clone_pairs_into(&mut self.entries);
+        self.status_bits.copy_from_slice(&source.status_bits);
+        self.fingerprints.copy_from_slice(&source.fingerprints);
+        self.size = source.size;
+        self.tombstones = source.tombstones;
+        self.resizes = source.resizes;
+        self.growth_factor = source.growth_factor;
+        self.load_factor_limit = source.load_factor_limit;
+        self.capacity_policy = source.capacity_policy;
+        self.hash_builder.clone_from(&source.hash_builder);
+    }
+}
+
+fn status_at(status_bits: &[u8], index: usize) -> u8 {
+    let byte_idx = index / 4;
+    let bit_offset = (index % 4) * 2;
+    (status_bits[byte_idx] >> bit_offset) & 0b11
+}
+
+impl<K, V, S> FromBuilder<S> for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    fn from_builder(builder: HashMapBuilder<S>) -> Self {
+        let mut map = Self::with_hasher(builder.capacity, builder.hash_builder);
+        if let Some(limit) = builder.load_factor_limit {
+            map.load_factor_limit = limit;
+        }
+        if let Some(factor) = builder.growth_factor {
+            map.set_growth_factor(factor);
+        }
+        map.set_capacity_policy(builder.capacity_policy);
+        map
+    }
+}
+
+impl<K, V, S> PartialEq for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value + PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get_ref(key) == Some(value))
+    }
+}
+
+impl<K, V, S> Eq for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value + Eq,
+    S: BuildHasher,
+{
+}
+
+// Renders `{k1: v1, k2: v2}` in iteration order, for quick dumps
+impl<K, V, S> Display for HashMap<K, V, S>
+where
+    K: Key + Display,
+    V: Value + Display,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("{")?;
+        for (i, (key, value)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", key, value)?;
+        }
+        f.write_str("}")
+    }
+}
+
+// Reserves room for the iterator's lower size hint up front, so a well
+// hinted iterator grows the table at most once. A hint of zero reserves
+// nothing and the map grows as pairs arrive
+impl<K, V, S> Extend<(K, V)> for HashMap<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        self.reserve(lower);
+        for (key, value) in iter {
+            self.insert(key, value)
+                .expect("growing the map always leaves a free slot");
+        }
+    }
+}
+
+pub enum Entry<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: &'a mut HashMap<K, V, S>,
+    key: K,
+    index: usize,
+    fingerprint: u8,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with_key(|_| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        self.or_insert_with_key(|_| f())
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    // Applies `f` to the value if the key is present. The entry already holds
+    // the slot, so a following `or_insert` does not probe again
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = f(&entry.key);
+                entry.insert(value)
+            }
+        }
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        &self.map.slot(self.index).0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.slot(self.index).1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.slot_mut(self.index).1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.slot_mut(self.index).1
+    }
+
+    // Replaces the value, returning the old one
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    // Leaves a tombstone, like `delete`
+    pub fn remove(self) -> V {
+        let (_, value) = self.map.take_slot(self.index, DELETED);
+        self.map.size -= 1;
+        self.map.tombstones += 1;
+        value
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        let map = self.map;
+        if map.get_status(self.index) == DELETED {
+            map.tombstones -= 1;
+        }
+        map.size += 1;
+        &mut map
+            .fill_slot(self.index, self.fingerprint, (self.key, value))
+            .1
+    }
+}
+
+pub struct RawEntryBuilderMut<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: &'a mut HashMap<K, V, S>,
+}
+
+pub enum RawEntryMut<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(RawVacantEntryMut<'a, K, V, S>),
+}
+
+pub struct RawVacantEntryMut<'a, K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+    fingerprint: u8,
+}
+
+impl<'a, K, V, S> RawEntryBuilderMut<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    pub fn from_key(self, key: &K) -> Result<RawEntryMut<'a, K, V, S>, HashMapError> {
+        let hash = self.map.hash_builder.hash_one(key);
+        self.from_hash(hash, |k| k == key)
+    }
+
+    // Probes from the slot `hash` picks, returning the first entry
+    // `is_match` accepts. `hash` must be what `hasher()` gives the key being
+    // looked for, since it also supplies the fingerprint. Grows the table
+    // first if needed, so a vacant entry always has room
+    pub fn from_hash<F: FnMut(&K) -> bool>(
+        self,
+        hash: u64,
+        mut is_match: F,
+    ) -> Result<RawEntryMut<'a, K, V, S>, HashMapError> {
+        let map = self.map;
+        if map.get_load_factor() >= map.load_factor_limit {
+            if map.tombstone_ratio() > TOMBSTONE_RATIO_LIMIT {
+                map.rehash_in_place();
+            } else {
+                map.resize()?;
+            }
+        }
+
+        let (index, fingerprint) = map.split_hash(hash);
+        let mut current_index = index;
+        let mut first_deleted = None;
+
+        loop {
+            // SAFETY: probe indices are always reduced modulo capacity
+            match unsafe { map.get_status_unchecked(current_index) } {
+                EMPTY => {
+                    let index = first_deleted.unwrap_or(current_index);
+                    return Ok(RawEntryMut::Vacant(RawVacantEntryMut {
+                        map,
+                        index,
+                        fingerprint,
+                    }));
+                }
+                DELETED => {
+                    first_deleted.get_or_insert(current_index);
+                }
+                OCCUPIED => {
+                    if map.fingerprints[current_index] == fingerprint
+                        && is_match(&map.slot(current_index).0)
+                    {
+                        return Ok(RawEntryMut::Occupied(OccupiedEntry {
+                            map,
+                            index: current_index,
+                        }));
+                    }
+                }
+                // 0b10 is never written, so the status bits are corrupt
+                _ => return Err(HashMapError::HashInvariantViolated),
             }
 
+            current_index = (current_index + 1) % map.capacity;
             if current_index == index {
-                return Err(anyhow!("Key not found"));
+                return match first_deleted {
+                    Some(index) => Ok(RawEntryMut::Vacant(RawVacantEntryMut {
+                        map,
+                        index,
+                        fingerprint,
+                    })),
+                    None => Err(HashMapError::Full),
+                };
             }
         }
     }
 }
 
+impl<'a, K, V, S> RawVacantEntryMut<'a, K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    // `key` must hash to the value the entry was found with
+    pub fn insert(self, key: K, value: V) -> &'a mut V {
+        VacantEntry {
+            map: self.map,
+            key,
+            index: self.index,
+            fingerprint: self.fingerprint,
+        }
+        .insert(value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    map_tests!();
+    open_map_tests!();
+
     #[test]
     fn test_hashmap() {
         let mut map: HashMap<String, String> = HashMap::new(10);
         map.insert("key".to_string(), "value".to_string()).unwrap();
-        assert_eq!(
-            map.get("key".to_string()).unwrap(),
-            Some("value".to_string())
-        );
+        assert_eq!(map.get("key".to_string()), Some("value".to_string()));
     }
 
     #[test]
@@ -215,7 +1515,7 @@ mod tests {
 
         for i in 0..100 {
             let key = format!("Key{i}");
-            let value = map.get(key).unwrap();
+            let value = map.get(key);
             assert_eq!(value, Some(format!("Value{i}")));
         }
     }
@@ -240,13 +1540,63 @@ mod tests {
         for i in 0..100 {
             let key = format!("Key{i}");
             if i % 5 == 0 {
-                assert_eq!(map.get(key).unwrap(), None);
+                assert_eq!(map.get(key), None);
             } else {
-                assert_eq!(map.get(key).unwrap(), Some(format!("Value{i}")));
+                assert_eq!(map.get(key), Some(format!("Value{i}")));
             }
         }
     }
 
+    #[test]
+    fn test_count_tombstones() {
+        let mut map: HashMap<u64, u64> = HashMap::new(64);
+        for i in 0..40 {
+            map.insert(i, i).unwrap();
+        }
+        for i in (0..40).step_by(4) {
+            map.delete(i).unwrap();
+        }
+        assert_eq!(map.count_tombstones(), 10);
+        assert_eq!(map.count_tombstones(), map.tombstones);
+
+        map.rehash_in_place();
+        assert_eq!(map.count_tombstones(), 0);
+        for i in 0..40 {
+            let expected = if i % 4 == 0 { None } else { Some(i) };
+            assert_eq!(map.get(i), expected);
+        }
+    }
+
+    #[test]
+    fn test_error_variants() {
+        let mut map: HashMap<u64, u64> = HashMap::new(16);
+        // Fill every slot behind the map's back so no resize is triggered
+        for i in 0..map.capacity {
+            let (_, fingerprint) = map.hash_with_fingerprint(&(i as u64));
+            map.fill_slot(i, fingerprint, (i as u64, i as u64));
+        }
+        match map.insert(100, 100) {
+            Err(HashMapError::Full) => {}
+            other => panic!("expected Full, got {:?}", other),
+        }
+        match map.delete(100) {
+            Err(HashMapError::KeyNotFound) => {}
+            other => panic!("expected KeyNotFound, got {:?}", other),
+        }
+
+        let mut map: HashMap<u64, u64> = HashMap::new(16);
+        let (index, _) = map.hash_with_fingerprint(&7);
+        map.set_status(index, 0b10);
+        match map.delete(7) {
+            Err(HashMapError::HashInvariantViolated) => {}
+            other => panic!("expected HashInvariantViolated, got {:?}", other),
+        }
+        match map.insert(7, 7) {
+            Err(HashMapError::HashInvariantViolated) => {}
+            other => panic!("expected HashInvariantViolated, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_status_bits() {
         let mut map: HashMap<u64, u64> = HashMap::new(16);
@@ -266,5 +1616,173 @@ mod tests {
         map.set_status(3, EMPTY);
         assert_eq!(map.get_status(2), OCCUPIED);
         assert_eq!(map.get_status(3), EMPTY);
+
+        // None of these slots were actually filled, so don't let drop see them
+        map.status_bits.fill(0);
+    }
+
+    thread_local! {
+        static VALUE_CLONES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    #[derive(Default, Debug, PartialEq)]
+    struct CountedClone(u64);
+
+    impl Clone for CountedClone {
+        fn clone(&self) -> Self {
+            VALUE_CLONES.with(|clones| clones.set(clones.get() + 1));
+            CountedClone(self.0)
+        }
+    }
+
+    #[derive(Hash, Clone, PartialEq, Debug)]
+    struct Id(std::num::NonZeroU32);
+
+    impl Display for Id {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "#{}", self.0)
+        }
+    }
+
+    #[test]
+    fn test_non_default_key() {
+        let mut map: HashMap<Id, &str> = HashMap::new(16);
+        for i in 1..100 {
+            map.insert(Id(std::num::NonZeroU32::new(i).unwrap()), "value")
+                .unwrap();
+        }
+        let key = Id(std::num::NonZeroU32::new(42).unwrap());
+        assert_eq!(map.get(key.clone()), Some("value"));
+        map.delete(key.clone()).unwrap();
+        assert_eq!(map.get(key), None);
+        assert_eq!(map.len(), 98);
+    }
+
+    #[test]
+    fn test_drops_only_occupied_slots() {
+        let value = std::rc::Rc::new(());
+        {
+            let mut map: HashMap<u32, std::rc::Rc<()>> = HashMap::new(16);
+            for i in 0..100 {
+                map.insert(i, value.clone()).unwrap();
+            }
+            for i in 0..30 {
+                map.delete(i).unwrap();
+            }
+            assert_eq!(std::rc::Rc::strong_count(&value), 71);
+            map.compute_if_present(50, |_, _| None);
+            map.insert(7, value.clone()).unwrap();
+            assert_eq!(std::rc::Rc::strong_count(&value), 71);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_fingerprint_collisions() {
+        let mut map: HashMap<String, usize> = HashMap::new(16);
+        let (_, target) = map.hash_with_fingerprint(&"key0".to_string());
+        // Keys sharing one fingerprint can only be told apart by the full comparison
+        let keys: Vec<String> = (0..)
+            .map(|i| format!("key{i}"))
+            .filter(|key| map.hash_with_fingerprint(key).1 == target)
+            .take(40)
+            .collect();
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(key.clone(), i).unwrap();
+        }
+        assert_eq!(map.len(), 40);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(map.get(key.clone()), Some(i));
+        }
+        for key in keys.iter().step_by(2) {
+            map.delete(key.clone()).unwrap();
+        }
+        for (i, key) in keys.iter().enumerate() {
+            let expected = if i % 2 == 0 { None } else { Some(i) };
+            assert_eq!(map.get(key.clone()), expected);
+        }
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut map: HashMap<i32, i32> = HashMap::new(16);
+        for i in 0..40 {
+            map.insert(i, i).unwrap();
+            map.check_invariants().unwrap();
+        }
+        for i in 0..20 {
+            map.delete(i).unwrap();
+            map.check_invariants().unwrap();
+        }
+
+        map.set_size_for_test(3);
+        assert!(map.check_invariants().unwrap_err().contains("size"));
+        map.set_size_for_test(20);
+        map.check_invariants().unwrap();
+
+        // A status code no slot should ever hold
+        let empty = (0..map.capacity)
+            .find(|&index| map.get_status(index) == EMPTY)
+            .unwrap();
+        map.set_status(empty, 0b10);
+        assert!(map
+            .check_invariants()
+            .unwrap_err()
+            .contains("invalid status"));
+        map.set_status(empty, EMPTY);
+
+        // A control byte that no longer matches its key
+        let index = map.find_index(&25).unwrap();
+        map.set_fingerprint(index, map.fingerprints[index] ^ 1);
+        assert!(map.check_invariants().unwrap_err().contains("fingerprint"));
+    }
+
+    #[test]
+    fn test_with_seed_is_reproducible() {
+        let build = || {
+            let mut map = HashMap::with_seed(16, 42);
+            for i in 0..100 {
+                map.insert(i, i * 2).unwrap();
+            }
+            for i in (0..100).step_by(3) {
+                map.delete(i).unwrap();
+            }
+            map
+        };
+        let (first, second) = (build(), build());
+        assert_eq!(first.status_bits, second.status_bits);
+        assert_eq!(first.fingerprints, second.fingerprints);
+        assert!(first.iter().eq(second.iter()));
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut original = HashMap::with_seed(16, 0);
+        for i in 0..500 {
+            original.insert(i, i.to_string()).unwrap();
+        }
+        original.delete(7).unwrap();
+
+        let mut copy = original.clone();
+        assert!(copy == original);
+        copy.check_invariants().unwrap();
+        copy.insert(0, "changed".to_string()).unwrap();
+        copy.delete(1).unwrap();
+        copy.insert(7, "back".to_string()).unwrap();
+        assert_eq!(original.get(0), Some("0".to_string()));
+        assert_eq!(original.get(1), Some("1".to_string()));
+        assert_eq!(original.get(7), None);
+        assert_eq!(original.len(), 499);
+
+        // clone_from refills a larger map without reallocating its table
+        let mut target = HashMap::with_seed(4096, 0);
+        for i in 0..2_000 {
+            target.insert(i * 7, "stale".to_string()).unwrap();
+        }
+        let table = target.entries.as_ptr();
+        target.clone_from(&original);
+        assert!(target == original);
+        assert_eq!(target.entries.as_ptr(), table);
+        target.check_invariants().unwrap();
     }
 }
@@ -0,0 +1,127 @@
+//  A map that can be shared between threads. Keys are split across a fixed
+//  number of open addressing maps by hash, each behind its own RwLock, so
+//  an operation only locks the shard its key lives in and operations on
+//  different shards never wait on each other
+use std::hash::BuildHasher;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::open_addressing::{self, Key, Value};
+use crate::{HashMapError, RandomState};
+
+pub struct ShardedMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    shards: Vec<RwLock<open_addressing::HashMap<K, V>>>,
+    //  picks the shard; each shard hashes with its own keys for the slot
+    hash_builder: RandomState,
+}
+
+impl<K, V> ShardedMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "a ShardedMap needs at least one shard");
+        Self {
+            shards: (0..shards)
+                .map(|_| RwLock::new(open_addressing::HashMap::new(16)))
+                .collect(),
+            hash_builder: RandomState::new(),
+        }
+    }
+
+    fn shard_index(&self, key: &K) -> usize {
+        (self.hash_builder.hash_one(key) as usize) % self.shards.len()
+    }
+
+    fn read(&self, index: usize) -> RwLockReadGuard<'_, open_addressing::HashMap<K, V>> {
+        self.shards[index]
+            .read()
+            .expect("a shard lock was poisoned")
+    }
+
+    fn write(&self, index: usize) -> RwLockWriteGuard<'_, open_addressing::HashMap<K, V>> {
+        self.shards[index]
+            .write()
+            .expect("a shard lock was poisoned")
+    }
+
+    pub fn get(&self, key: K) -> Option<V> {
+        self.read(self.shard_index(&key)).get(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.read(self.shard_index(key)).contains_key(key)
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Result<(), HashMapError> {
+        self.write(self.shard_index(&key)).insert(key, value)
+    }
+
+    pub fn delete(&self, key: K) -> Result<(), HashMapError> {
+        self.write(self.shard_index(&key)).delete(key)
+    }
+
+    //  Locks each shard in turn, so concurrent writers can make the total
+    //  stale by the time it is returned
+    pub fn len(&self) -> usize {
+        (0..self.shards.len()).map(|i| self.read(i).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        let map: ShardedMap<u64, u64> = ShardedMap::new(8);
+        let writers = 4;
+        let per_writer = 2_000;
+
+        thread::scope(|scope| {
+            for w in 0..writers {
+                let map = &map;
+                scope.spawn(move || {
+                    let keys = w * per_writer..(w + 1) * per_writer;
+                    for key in keys.clone() {
+                        map.insert(key, key * 10).unwrap();
+                    }
+                    //  drop every other key again
+                    for key in keys.step_by(2) {
+                        map.delete(key).unwrap();
+                    }
+                });
+            }
+            for _ in 0..4 {
+                let map = &map;
+                scope.spawn(move || {
+                    for key in 0..writers * per_writer {
+                        //  a key is either missing or holds the value it was written with
+                        if let Some(value) = map.get(key) {
+                            assert_eq!(value, key * 10);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(map.len() as u64, writers * per_writer / 2);
+        for key in 0..writers * per_writer {
+            let expected = (key % 2 == 1).then_some(key * 10);
+            assert_eq!(map.get(key), expected);
+        }
+    }
+}
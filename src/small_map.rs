@@ -0,0 +1,236 @@
+//  A map for the common case of only a handful of entries. Up to N pairs are
+//  kept inline in a fixed array and found by linear search, so a tiny map
+//  never allocates. Inserting a new key into a full array moves everything
+//  into an open addressing map, which the map keeps using from then on
+use crate::open_addressing::{self, Key, Value};
+use crate::HashMapError;
+
+enum Storage<K, V, const N: usize>
+where
+    K: Key,
+    V: Value,
+{
+    //  The first `len` slots are Some, the rest None
+    Inline {
+        pairs: [Option<(K, V)>; N],
+        len: usize,
+    },
+    Spilled(open_addressing::HashMap<K, V>),
+}
+
+pub struct SmallMap<K, V, const N: usize>
+where
+    K: Key,
+    V: Value,
+{
+    storage: Storage<K, V, N>,
+}
+
+impl<K, V, const N: usize> SmallMap<K, V, N>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new() -> Self {
+        Self {
+            storage: Storage::Inline {
+                pairs: core::array::from_fn(|_| None),
+                len: 0,
+            },
+        }
+    }
+
+    //  Whether the entries have moved out of the inline array
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, Storage::Spilled(_))
+    }
+
+    fn position(pairs: &[Option<(K, V)>], key: &K) -> Option<usize> {
+        pairs
+            .iter()
+            .map_while(Option::as_ref)
+            .position(|(k, _)| k == key)
+    }
+
+    pub fn get(&self, key: K) -> Option<V> {
+        self.get_ref(&key).cloned()
+    }
+
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        match &self.storage {
+            Storage::Inline { pairs, .. } => {
+                let i = Self::position(pairs, key)?;
+                pairs[i].as_ref().map(|(_, v)| v)
+            }
+            Storage::Spilled(map) => map.get_ref(key),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        match &mut self.storage {
+            Storage::Inline { pairs, .. } => {
+                let i = Self::position(pairs, &key)?;
+                pairs[i].as_mut().map(|(_, v)| v)
+            }
+            Storage::Spilled(map) => map.get_mut(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_ref(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        match &mut self.storage {
+            Storage::Spilled(map) => return map.insert(key, value),
+            Storage::Inline { pairs, len } => {
+                if let Some(i) = Self::position(pairs, &key) {
+                    pairs[i] = Some((key, value));
+                    return Ok(());
+                }
+                if *len < N {
+                    pairs[*len] = Some((key, value));
+                    *len += 1;
+                    return Ok(());
+                }
+            }
+        }
+        self.spill()?;
+        let Storage::Spilled(map) = &mut self.storage else {
+            unreachable!("spill always leaves a backing map")
+        };
+        map.insert(key, value)
+    }
+
+    //  Moves the inline pairs into a backing map with room to grow past N
+    fn spill(&mut self) -> Result<(), HashMapError> {
+        let mut map = open_addressing::HashMap::new(2 * N);
+        let Storage::Inline { pairs, .. } = &mut self.storage else {
+            return Ok(());
+        };
+        for (key, value) in pairs.iter_mut().map_while(Option::take) {
+            map.insert(key, value)?;
+        }
+        self.storage = Storage::Spilled(map);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        match &mut self.storage {
+            Storage::Inline { pairs, len } => {
+                if let Some(i) = Self::position(pairs, &key) {
+                    //  keep the occupied slots contiguous
+                    pairs[i] = None;
+                    pairs.swap(i, *len - 1);
+                    *len -= 1;
+                }
+                Ok(())
+            }
+            Storage::Spilled(map) => map.delete(key),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Inline { len, .. } => *len,
+            Storage::Spilled(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let (inline, spilled) = match &self.storage {
+            Storage::Inline { pairs, .. } => (Some(pairs), None),
+            Storage::Spilled(map) => (None, Some(map)),
+        };
+        let inline = inline
+            .into_iter()
+            .flat_map(|pairs| pairs.iter().map_while(Option::as_ref))
+            .map(|(k, v)| (k, v));
+        inline.chain(spilled.into_iter().flat_map(|map| map.iter()))
+    }
+}
+
+impl<K, V, const N: usize> Default for SmallMap<K, V, N>
+where
+    K: Key,
+    V: Value,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_inline() {
+        let mut map: SmallMap<String, i32, 4> = SmallMap::new();
+        for i in 0..4 {
+            map.insert(format!("key_{i}"), i).unwrap();
+        }
+        map.insert("key_2".to_string(), 20).unwrap();
+        assert!(!map.is_spilled());
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.get("key_2".to_string()), Some(20));
+
+        map.delete("key_0".to_string()).unwrap();
+        assert_eq!(map.len(), 3);
+        assert!(!map.contains_key(&"key_0".to_string()));
+        *map.get_mut("key_3".to_string()).unwrap() += 1;
+        assert_eq!(map.get("key_3".to_string()), Some(4));
+        assert_eq!(map.iter().count(), 3);
+    }
+
+    #[test]
+    fn test_spill_boundary() {
+        let mut map: SmallMap<i32, i32, 8> = SmallMap::new();
+        for i in 0..8 {
+            map.insert(i, i).unwrap();
+        }
+        assert!(!map.is_spilled());
+        //  updating a present key in a full array does not spill
+        map.insert(7, 70).unwrap();
+        assert!(!map.is_spilled());
+
+        map.insert(8, 8).unwrap();
+        assert!(map.is_spilled());
+        assert_eq!(map.len(), 9);
+        assert_eq!(map.get(7), Some(70));
+        for i in 0..9 {
+            assert!(map.contains_key(&i));
+        }
+    }
+
+    #[test]
+    fn test_matches_backing_map_after_spill() {
+        let mut rng = rand::thread_rng();
+        let mut small: SmallMap<u32, u32, 4> = SmallMap::new();
+        let mut backing = open_addressing::HashMap::new(16);
+        for _ in 0..2_000 {
+            let key = rng.gen_range(0..32);
+            if rng.gen_bool(0.7) {
+                let value = rng.gen();
+                small.insert(key, value).unwrap();
+                backing.insert(key, value).unwrap();
+            } else {
+                small.delete(key).unwrap();
+                backing.delete(key).unwrap();
+            }
+            assert_eq!(small.len(), backing.len());
+            assert_eq!(small.get(key), backing.get(key));
+        }
+        assert!(small.is_spilled());
+        let mut pairs: Vec<_> = small.iter().collect();
+        let mut expected: Vec<_> = backing.iter().collect();
+        pairs.sort();
+        expected.sort();
+        assert_eq!(pairs, expected);
+    }
+}
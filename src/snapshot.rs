@@ -0,0 +1,67 @@
+//  A frozen copy of a map's contents, taken with `snapshot` on any of the
+//  three maps. It is handed out behind an Arc, so a checkpoint can be shared
+//  between readers for the price of a reference count while the live map
+//  keeps changing underneath. Every snapshot is stored as an open addressing
+//  table, whichever map it was taken from
+use core::hash::BuildHasher;
+
+use crate::open_addressing::{self, Key, Value};
+use crate::RandomState;
+
+pub struct Snapshot<K, V, S = RandomState>
+where
+    K: Key,
+    V: Value,
+{
+    map: open_addressing::HashMap<K, V, S>,
+}
+
+impl<K, V, S> Snapshot<K, V, S>
+where
+    K: Key,
+    V: Value,
+    S: BuildHasher,
+{
+    //  Copies `len` entries into a table sized for them up front
+    pub(crate) fn from_entries<'a>(
+        hash_builder: S,
+        len: usize,
+        entries: impl Iterator<Item = (&'a K, &'a V)>,
+    ) -> Self
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let mut map = open_addressing::HashMap::with_hasher(0, hash_builder);
+        map.reserve(len);
+        for (key, value) in entries {
+            map.insert(key.clone(), value.clone())
+                .expect("the table was reserved for every entry");
+        }
+        Self { map }
+    }
+
+    pub fn get(&self, key: K) -> Option<V> {
+        self.map.get(key)
+    }
+
+    pub fn get_ref(&self, key: &K) -> Option<&V> {
+        self.map.get_ref(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
@@ -0,0 +1,190 @@
+//  A map whose entries expire a fixed time after they were last inserted.
+//  Each value is stored next to its insertion time; an expired entry reads
+//  as absent and is removed the next time `get` touches it, while
+//  `purge_expired` sweeps the whole table
+use std::time::{Duration, Instant};
+
+use crate::open_addressing::{self, Key, Value};
+use crate::HashMapError;
+
+//  Where the map reads the current time, so tests can move it by hand
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub struct TtlMap<K, V, C = SystemClock>
+where
+    K: Key,
+    V: Value,
+{
+    map: open_addressing::HashMap<K, (V, Instant)>,
+    ttl: Duration,
+    clock: C,
+}
+
+impl<K, V> TtlMap<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_clock(ttl, SystemClock)
+    }
+}
+
+impl<K, V, C> TtlMap<K, V, C>
+where
+    K: Key,
+    V: Value,
+    C: Clock,
+{
+    pub fn with_clock(ttl: Duration, clock: C) -> Self {
+        Self {
+            map: open_addressing::HashMap::new(16),
+            ttl,
+            clock,
+        }
+    }
+
+    fn is_expired(&self, inserted: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(inserted) >= self.ttl
+    }
+
+    //  Inserting an existing key replaces its value and restarts its timer
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        let now = self.clock.now();
+        self.map.insert(key, (value, now))
+    }
+
+    pub fn get(&mut self, key: K) -> Option<V> {
+        let now = self.clock.now();
+        let (value, inserted) = self.map.get_ref(&key)?;
+        if !self.is_expired(*inserted, now) {
+            return Some(value.clone());
+        }
+        self.map
+            .delete(key)
+            .expect("the key was just found in the map");
+        None
+    }
+
+    pub fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        self.map.delete(key)
+    }
+
+    //  Removes every expired entry and shrinks the table to fit the rest.
+    //  Returns how many entries were removed
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.clock.now();
+        let expired: Vec<K> = self
+            .map
+            .iter()
+            .filter(|(_, (_, inserted))| self.is_expired(*inserted, now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.map
+                .delete(key.clone())
+                .expect("the key was just found in the map");
+        }
+        self.map.shrink_to_fit();
+        expired.len()
+    }
+
+    //  Live entries plus any that have expired but not been purged yet
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Clone)]
+    struct MockClock(Rc<Cell<Instant>>);
+
+    impl MockClock {
+        fn new() -> Self {
+            MockClock(Rc::new(Cell::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(Duration::from_secs(10), clock.clone());
+        map.insert("a".to_string(), 1).unwrap();
+        clock.advance(Duration::from_secs(6));
+        map.insert("b".to_string(), 2).unwrap();
+        assert_eq!(map.get("a".to_string()), Some(1));
+
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(map.get("a".to_string()), None);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get("b".to_string()), Some(2));
+
+        //  reinserting restarts the timer
+        map.insert("b".to_string(), 3).unwrap();
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(map.get("b".to_string()), Some(3));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(map.get("b".to_string()), None);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(Duration::from_secs(60), clock.clone());
+        for i in 0..1_000 {
+            map.insert(i, i).unwrap();
+        }
+        clock.advance(Duration::from_secs(30));
+        for i in 1_000..1_010 {
+            map.insert(i, i).unwrap();
+        }
+        let full_capacity = map.capacity();
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(map.purge_expired(), 1_000);
+        assert_eq!(map.len(), 10);
+        assert!(map.capacity() < full_capacity);
+        for i in 1_000..1_010 {
+            assert_eq!(map.get(i), Some(i));
+        }
+    }
+}
@@ -1,68 +1,223 @@
 #![allow(dead_code)]
+use std::fmt::Display;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+use hdrhistogram::Histogram;
 use rand::Rng;
 
+use crate::HashMapError;
+
 pub trait HashMapBehavior<K, V> {
-    fn new(capacity: usize) -> Self;
-    fn insert(&mut self, key: K, value: V) -> anyhow::Result<()>;
-    fn get(&self, key: K) -> anyhow::Result<Option<V>>;
-    fn delete(&mut self, key: K) -> anyhow::Result<()>;
+    fn new(capacity: usize) -> Self
+    where
+        Self: Sized;
+    fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError>;
+    fn get(&self, key: K) -> Option<V>;
+    fn get_ref(&self, key: &K) -> Option<&V>;
+    fn contains_key(&self, key: &K) -> bool;
+    fn get_mut(&mut self, key: K) -> Option<&mut V>;
+    fn delete(&mut self, key: K) -> Result<(), HashMapError>;
+    fn reserve(&mut self, additional: usize);
+    fn capacity(&self) -> usize;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    fn resize_count(&self) -> usize;
+    fn clear(&mut self);
 }
 
-// Implement for all HashMap variants
-impl<K: crate::chaining::Key, V: crate::chaining::Value> HashMapBehavior<K, V>
-    for crate::chaining::HashMap<K, V>
+// Implement for all HashMap variants. Maps are built with `S::default()`, so
+// naming `FixedSeedState` as the hasher gives the same layout on every run
+impl<K, V, S> HashMapBehavior<K, V> for crate::chaining::HashMap<K, V, S>
+where
+    K: crate::chaining::Key,
+    V: crate::chaining::Value,
+    S: BuildHasher + Default,
 {
     fn new(capacity: usize) -> Self {
-        Self::new(capacity)
+        Self::with_hasher(capacity, S::default())
     }
-    fn insert(&mut self, key: K, value: V) -> anyhow::Result<()> {
+    fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
         self.insert(key, value)
     }
-    fn get(&self, key: K) -> anyhow::Result<Option<V>> {
+    fn get(&self, key: K) -> Option<V> {
         self.get(key)
     }
-    fn delete(&mut self, key: K) -> anyhow::Result<()> {
+    fn get_ref(&self, key: &K) -> Option<&V> {
+        self.get_ref(key)
+    }
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+    fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+    fn delete(&mut self, key: K) -> Result<(), HashMapError> {
         self.delete(key)
     }
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+    fn resize_count(&self) -> usize {
+        self.resize_count()
+    }
+    fn clear(&mut self) {
+        self.clear()
+    }
 }
 
-impl<K: crate::open_addressing::Key, V: crate::open_addressing::Value> HashMapBehavior<K, V>
-    for crate::open_addressing::HashMap<K, V>
+impl<K, V, S> HashMapBehavior<K, V> for crate::open_addressing::HashMap<K, V, S>
+where
+    K: crate::open_addressing::Key,
+    V: crate::open_addressing::Value,
+    S: BuildHasher + Default,
 {
     fn new(capacity: usize) -> Self {
-        Self::new(capacity)
+        Self::with_hasher(capacity, S::default())
     }
-    fn insert(&mut self, key: K, value: V) -> anyhow::Result<()> {
+    fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
         self.insert(key, value)
     }
-    fn get(&self, key: K) -> anyhow::Result<Option<V>> {
+    fn get(&self, key: K) -> Option<V> {
         self.get(key)
     }
-    fn delete(&mut self, key: K) -> anyhow::Result<()> {
+    fn get_ref(&self, key: &K) -> Option<&V> {
+        self.get_ref(key)
+    }
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+    fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+    fn delete(&mut self, key: K) -> Result<(), HashMapError> {
         self.delete(key)
     }
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+    fn resize_count(&self) -> usize {
+        self.resize_count()
+    }
+    fn clear(&mut self) {
+        self.clear()
+    }
 }
 
-impl<K: crate::open_addressing_compact::Key, V: crate::open_addressing_compact::Value>
-    HashMapBehavior<K, V> for crate::open_addressing_compact::HashMap<K, V>
+impl<K, V, S> HashMapBehavior<K, V> for crate::open_addressing_compact::HashMap<K, V, S>
+where
+    K: crate::open_addressing_compact::Key,
+    V: crate::open_addressing_compact::Value,
+    S: BuildHasher + Default,
 {
     fn new(capacity: usize) -> Self {
-        Self::new(capacity)
+        Self::with_hasher(capacity, S::default())
     }
-    fn insert(&mut self, key: K, value: V) -> anyhow::Result<()> {
+    fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
         self.insert(key, value)
     }
-    fn get(&self, key: K) -> anyhow::Result<Option<V>> {
+    fn get(&self, key: K) -> Option<V> {
         self.get(key)
     }
-    fn delete(&mut self, key: K) -> anyhow::Result<()> {
+    fn get_ref(&self, key: &K) -> Option<&V> {
+        self.get_ref(key)
+    }
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+    fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+    fn delete(&mut self, key: K) -> Result<(), HashMapError> {
         self.delete(key)
     }
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+    fn resize_count(&self) -> usize {
+        self.resize_count()
+    }
+    fn clear(&mut self) {
+        self.clear()
+    }
+}
+
+// Baseline to compare the custom maps against
+impl<K: std::hash::Hash + Eq, V: Clone> HashMapBehavior<K, V> for std::collections::HashMap<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self::with_capacity(capacity)
+    }
+    fn insert(&mut self, key: K, value: V) -> Result<(), HashMapError> {
+        self.insert(key, value);
+        Ok(())
+    }
+    fn get(&self, key: K) -> Option<V> {
+        self.get(&key).cloned()
+    }
+    fn get_ref(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+    fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.get_mut(&key)
+    }
+    fn delete(&mut self, key: K) -> Result<(), HashMapError> {
+        self.remove(&key);
+        Ok(())
+    }
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+    fn resize_count(&self) -> usize {
+        0 // resizes are not observable on the std map
+    }
+    fn clear(&mut self) {
+        self.clear()
+    }
 }
 
 pub struct LoadFactorWorkload {
     pub size: usize,
     pub value_size: usize,
+    pub preallocate: bool, // reserve space for all entries up front so no resizes happen
 }
 
 #[derive(Clone)]
@@ -70,6 +225,65 @@ pub enum KeyPattern {
     Uniform,
     Clustered,
     Sequential,
+    Zipfian { s: f64 },
+    Gaussian { mean: f64, stddev: f64 },
+}
+
+// Samples ranks in 0..key_space where rank k is drawn with probability proportional to 1 / (k + 1)^s
+pub struct ZipfSampler {
+    cdf: Vec<f64>,
+}
+
+impl ZipfSampler {
+    pub fn new(key_space: usize, s: f64) -> Self {
+        let weights: Vec<f64> = (1..=key_space.max(1))
+            .map(|rank| 1.0 / (rank as f64).powf(s))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let mut running = 0.0;
+        let cdf = weights
+            .iter()
+            .map(|weight| {
+                running += weight / total;
+                running
+            })
+            .collect();
+        Self { cdf }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        let u: f64 = rng.gen();
+        self.cdf.partition_point(|&p| p < u).min(self.cdf.len() - 1)
+    }
+}
+
+// Box-Muller transform, mapped onto non-negative integer keys
+pub fn sample_gaussian_key<R: Rng>(rng: &mut R, mean: f64, stddev: f64) -> u64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+    (mean + z * stddev).round().max(0.0) as u64
+}
+
+// Keys whose hash under `DefaultHasher::new()`, which is what a map seeded
+// with `FixedSeedState::new(0)` uses, ends in `zero_bits` zero bits. A
+// power-of-two table then only ever uses one slot in 2^zero_bits, so every
+// chain or probe run is that many times longer than it should be, and a table
+// of up to 2^zero_bits slots puts every key in slot 0. Finding each key takes
+// about 2^zero_bits hashes, so build them before timing anything
+pub fn adversarial_keys<K: std::hash::Hash>(
+    count: usize,
+    zero_bits: u32,
+    make_key: impl Fn(u64) -> K,
+) -> Vec<K> {
+    let hasher = std::hash::BuildHasherDefault::<std::hash::DefaultHasher>::default();
+    // 64 or more zero bits means the whole hash has to be zero
+    let mask = 1u64.checked_shl(zero_bits).map_or(u64::MAX, |bit| bit - 1);
+    (0..)
+        .map(make_key)
+        .filter(|key| hasher.hash_one(key) & mask == 0)
+        .take(count)
+        .collect()
 }
 
 pub struct KeyDistributionWorkload {
@@ -79,9 +293,177 @@ pub struct KeyDistributionWorkload {
 
 pub struct OperationMixWorkload {
     pub initial_size: usize,
+    // Keys are drawn from `0..key_space`, which is at least `initial_size`.
+    // Anything past `initial_size` starts out absent, so reads and deletes
+    // can miss and writes can grow the map
+    pub key_space: usize,
     pub operations: usize,
     pub read_pct: u8,
-    pub write_pct: u8, // delete_pct is implied as 100 - (read_pct + write_pct)
+    pub write_pct: u8,
+    pub update_pct: u8, // delete_pct is implied as 100 - (read_pct + write_pct + update_pct)
+    pub value_size: usize,
+}
+
+pub struct ChurnWorkload {
+    pub initial_size: usize,
+    pub rounds: usize,
+}
+
+pub struct WorkloadStats {
+    pub elapsed: Duration,
+    pub operations: usize,
+    pub final_size: usize,
+    pub final_capacity: usize,
+    pub resizes: usize,
+    // Per-operation latency, for runs that time every operation
+    pub latency: Option<LatencyPercentiles>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    // How many operations were timed
+    pub samples: u64,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+    pub max: Duration,
+}
+
+// Records how long each operation of a workload takes. The whole-run time
+// averages a resize away; the tail of this histogram is where a stop-the-world
+// rehash shows up
+pub struct LatencyRecorder {
+    histogram: Histogram<u64>,
+}
+
+impl LatencyRecorder {
+    pub fn new() -> Self {
+        Self {
+            // nanoseconds to three significant figures, from 1ns up to a
+            // minute; anything slower is recorded as a minute
+            histogram: Histogram::new_with_bounds(1, 60_000_000_000, 3)
+                .expect("the bounds and precision are valid"),
+        }
+    }
+
+    // Runs `op` and records how long it took
+    pub fn time<T>(&mut self, op: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = op();
+        self.record(start.elapsed());
+        result
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        self.histogram
+            .saturating_record(elapsed.as_nanos().min(u64::MAX as u128) as u64);
+    }
+
+    pub fn len(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    pub fn percentiles(&self) -> LatencyPercentiles {
+        let at = |quantile| Duration::from_nanos(self.histogram.value_at_quantile(quantile));
+        LatencyPercentiles {
+            samples: self.len(),
+            p50: at(0.5),
+            p99: at(0.99),
+            p999: at(0.999),
+            max: Duration::from_nanos(self.histogram.max()),
+        }
+    }
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorkloadStats {
+    // Times a workload run and records the state of the map it leaves behind
+    pub fn measure<K, V, M: HashMapBehavior<K, V>>(
+        operations: usize,
+        run: impl FnOnce() -> M,
+    ) -> Self {
+        let start = Instant::now();
+        let map = run();
+        Self::of_map(operations, start.elapsed(), &map)
+    }
+
+    // Records the state of a map a workload has already run on
+    pub fn of_map<K, V, M: HashMapBehavior<K, V>>(
+        operations: usize,
+        elapsed: Duration,
+        map: &M,
+    ) -> Self {
+        Self {
+            elapsed,
+            operations,
+            final_size: map.len(),
+            final_capacity: map.capacity(),
+            resizes: map.resize_count(),
+            latency: None,
+        }
+    }
+
+    // Operations per second
+    pub fn throughput(&self) -> f64 {
+        self.operations as f64 / self.elapsed.as_secs_f64()
+    }
+
+    // JSON has no infinity or NaN, so a run too short to time reports a null
+    // throughput
+    pub fn to_json(&self) -> String {
+        let throughput = match self.throughput() {
+            throughput if throughput.is_finite() => throughput.to_string(),
+            _ => "null".to_string(),
+        };
+        let latency = match &self.latency {
+            Some(latency) => format!(
+                ",\"latency_ns\":{{\"p50\":{},\"p99\":{},\"p999\":{},\"max\":{}}}",
+                latency.p50.as_nanos(),
+                latency.p99.as_nanos(),
+                latency.p999.as_nanos(),
+                latency.max.as_nanos()
+            ),
+            None => String::new(),
+        };
+        format!(
+            "{{\"elapsed_secs\":{},\"operations\":{},\"throughput\":{},\"final_size\":{},\"final_capacity\":{},\"resizes\":{}{}}}",
+            self.elapsed.as_secs_f64(),
+            self.operations,
+            throughput,
+            self.final_size,
+            self.final_capacity,
+            self.resizes,
+            latency
+        )
+    }
+}
+
+impl Display for WorkloadStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "elapsed: {:?}", self.elapsed)?;
+        writeln!(f, "operations: {}", self.operations)?;
+        writeln!(f, "throughput: {:.0} ops/sec", self.throughput())?;
+        writeln!(f, "final size: {}", self.final_size)?;
+        writeln!(f, "final capacity: {}", self.final_capacity)?;
+        write!(f, "resizes: {}", self.resizes)?;
+        if let Some(latency) = &self.latency {
+            write!(
+                f,
+                "\nlatency: p50 {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+                latency.p50, latency.p99, latency.p999, latency.max
+            )?;
+        }
+        Ok(())
+    }
 }
 
 pub mod generators {
@@ -89,26 +471,34 @@ pub mod generators {
 
     pub fn run_load_factor_workload<M: HashMapBehavior<String, String>>(
         workload: &LoadFactorWorkload,
-    ) {
+    ) -> M {
         let mut map = M::new(16);
+        if workload.preallocate {
+            map.reserve(workload.size);
+        }
         for i in 0..workload.size {
             map.insert(format!("key_{}", i), "x".repeat(workload.value_size))
                 .unwrap();
         }
+        map
     }
 
     pub fn run_load_factor_workload_integers<M: HashMapBehavior<u64, u64>>(
         workload: &LoadFactorWorkload,
-    ) {
+    ) -> M {
         let mut map = M::new(16);
+        if workload.preallocate {
+            map.reserve(workload.size);
+        }
         for i in 0..workload.size {
             map.insert(i as u64, i as u64).unwrap();
         }
+        map
     }
 
     pub fn run_key_distribution_workload<M: HashMapBehavior<String, String>>(
         workload: &KeyDistributionWorkload,
-    ) {
+    ) -> M {
         let mut map = M::new(workload.size);
         let mut rng = rand::thread_rng();
 
@@ -121,7 +511,7 @@ pub mod generators {
             }
             KeyPattern::Clustered => {
                 for i in 0..workload.size {
-                    let cluster = i / (workload.size / 10); // 10 clusters
+                    let cluster = i / (workload.size / 10).max(1); // 10 clusters
                     map.insert(format!("cluster_{}_{}", cluster, i), "value".to_string())
                         .unwrap();
                 }
@@ -132,12 +522,30 @@ pub mod generators {
                         .unwrap();
                 }
             }
+            KeyPattern::Zipfian { s } => {
+                let sampler = ZipfSampler::new(workload.size, s);
+                for _ in 0..workload.size {
+                    map.insert(
+                        format!("key_{}", sampler.sample(&mut rng)),
+                        "value".to_string(),
+                    )
+                    .unwrap();
+                }
+            }
+            KeyPattern::Gaussian { mean, stddev } => {
+                for _ in 0..workload.size {
+                    let key = sample_gaussian_key(&mut rng, mean, stddev);
+                    map.insert(format!("key_{}", key), "value".to_string())
+                        .unwrap();
+                }
+            }
         }
+        map
     }
 
     pub fn run_key_distribution_workload_integers<M: HashMapBehavior<u64, u64>>(
         workload: &KeyDistributionWorkload,
-    ) {
+    ) -> M {
         let mut map = M::new(workload.size);
         let mut rng = rand::thread_rng();
 
@@ -149,7 +557,7 @@ pub mod generators {
             }
             KeyPattern::Clustered => {
                 for i in 0..workload.size {
-                    let cluster = i / (workload.size / 10); // 10 clusters
+                    let cluster = i / (workload.size / 10).max(1); // 10 clusters
                     map.insert((cluster as u64) << 32 | (i as u64), 42).unwrap();
                 }
             }
@@ -158,37 +566,530 @@ pub mod generators {
                     map.insert(i as u64, 42).unwrap();
                 }
             }
+            KeyPattern::Zipfian { s } => {
+                let sampler = ZipfSampler::new(workload.size, s);
+                for _ in 0..workload.size {
+                    map.insert(sampler.sample(&mut rng) as u64, 42).unwrap();
+                }
+            }
+            KeyPattern::Gaussian { mean, stddev } => {
+                for _ in 0..workload.size {
+                    map.insert(sample_gaussian_key(&mut rng, mean, stddev), 42)
+                        .unwrap();
+                }
+            }
+        }
+        map
+    }
+
+    // Inserts `keys` into a map sized for them, then looks every one of them
+    // up. Meant for keys from `adversarial_keys`, whose long chains make
+    // both phases slow on a map that hashes the way they were crafted for
+    pub fn run_adversarial_workload<M: HashMapBehavior<u64, u64>>(keys: &[u64]) -> M {
+        let mut map = M::new(keys.len());
+        for &key in keys {
+            map.insert(key, 42).unwrap();
+        }
+        for key in keys {
+            assert!(map.contains_key(key));
+        }
+        map
+    }
+
+    pub fn run_churn_workload<M: HashMapBehavior<String, String>>(workload: &ChurnWorkload) -> M {
+        let mut map = M::new(workload.initial_size);
+        for i in 0..workload.initial_size {
+            map.insert(format!("key_{}", i), "initial".to_string())
+                .unwrap();
+        }
+
+        // Every round turns the whole key space into tombstones and then fills it back in
+        for round in 0..workload.rounds {
+            for i in 0..workload.initial_size {
+                map.delete(format!("key_{}", i)).unwrap();
+            }
+            for i in 0..workload.initial_size {
+                map.insert(format!("key_{}", i), format!("round_{}", round))
+                    .unwrap();
+            }
+        }
+        map
+    }
+
+    // Read-modify-write of an existing value in a single lookup
+    pub fn apply_update<M: HashMapBehavior<String, String>>(map: &mut M, key: String) {
+        if let Some(value) = map.get_mut(key) {
+            value.make_ascii_uppercase();
         }
     }
 
     pub fn run_operation_mix_workload<M: HashMapBehavior<String, String>>(
         workload: &OperationMixWorkload,
-    ) {
-        // Returns map and operations performed
+    ) -> M {
+        // Returns the map after all operations have been performed
         let mut map = M::new(workload.initial_size);
-        let mut rng = rand::thread_rng();
+        apply_operation_mix(&mut map, workload, &mut rand::thread_rng(), None);
+        map
+    }
+
+    // Like `run_operation_mix_workload`, but times every operation of the mix
+    // and reports the percentiles in the stats. Prepopulation is not timed
+    pub fn run_operation_mix_workload_with_latency<M: HashMapBehavior<String, String>>(
+        workload: &OperationMixWorkload,
+    ) -> WorkloadStats {
+        let mut latency = LatencyRecorder::new();
+        let start = Instant::now();
+        let mut map = M::new(workload.initial_size);
+        apply_operation_mix(
+            &mut map,
+            workload,
+            &mut rand::thread_rng(),
+            Some(&mut latency),
+        );
+        let operations = workload.initial_size + workload.operations;
+        let mut stats = WorkloadStats::of_map(operations, start.elapsed(), &map);
+        stats.latency = Some(latency.percentiles());
+        stats
+    }
+
+    // Like `run_load_factor_workload`, but times every insert. Unless the map
+    // is preallocated, the inserts that resize it make up the tail
+    pub fn run_load_factor_workload_with_latency<M: HashMapBehavior<String, String>>(
+        workload: &LoadFactorWorkload,
+    ) -> WorkloadStats {
+        let mut latency = LatencyRecorder::new();
+        let start = Instant::now();
+        let mut map = M::new(16);
+        if workload.preallocate {
+            map.reserve(workload.size);
+        }
+        for i in 0..workload.size {
+            let (key, value) = (format!("key_{}", i), "x".repeat(workload.value_size));
+            latency.time(|| map.insert(key, value)).unwrap();
+        }
+        let mut stats = WorkloadStats::of_map(workload.size, start.elapsed(), &map);
+        stats.latency = Some(latency.percentiles());
+        stats
+    }
+
+    // Runs the operation mix on `M` and on the std map, each with an RNG
+    // seeded from `seed` so both see the same operation stream, and reports
+    // whether they end up holding the same entries. The stats are for `M`
+    pub fn run_baseline_workload<M: HashMapBehavior<String, String>>(
+        workload: &OperationMixWorkload,
+        seed: u64,
+    ) -> (WorkloadStats, bool) {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let start = Instant::now();
+        let mut map = M::new(workload.initial_size);
+        apply_operation_mix(&mut map, workload, &mut StdRng::seed_from_u64(seed), None);
+        let operations = workload.initial_size + workload.operations;
+        let stats = WorkloadStats::of_map(operations, start.elapsed(), &map);
+
+        let mut baseline = std::collections::HashMap::new();
+        apply_operation_mix(
+            &mut baseline,
+            workload,
+            &mut StdRng::seed_from_u64(seed),
+            None,
+        );
+
+        // Every key the mix can touch, present or not, has to agree
+        let matches = map.len() == baseline.len()
+            && (0..workload.key_space).all(|i| {
+                let key = format!("key_{}", i);
+                map.get(key.clone()) == baseline.get(&key).cloned()
+            });
+        (stats, matches)
+    }
+
+    fn apply_operation_mix<M: HashMapBehavior<String, String>, R: Rng>(
+        map: &mut M,
+        workload: &OperationMixWorkload,
+        rng: &mut R,
+        mut latency: Option<&mut LatencyRecorder>,
+    ) {
+        assert!(workload.key_space > 0, "the key space must not be empty");
+        assert!(
+            workload.key_space >= workload.initial_size,
+            "the key space must cover the prepopulated keys"
+        );
+        // Widened so the sum cannot wrap before it is checked. Deletes take
+        // whatever is left, so the four categories always total 100
+        let read_end = u32::from(workload.read_pct);
+        let write_end = read_end + u32::from(workload.write_pct);
+        let update_end = write_end + u32::from(workload.update_pct);
+        assert!(
+            update_end <= 100,
+            "the read, write and update percentages add up to more than 100"
+        );
         let mut _ops_performed = 0;
 
         // Pre-populate
         for i in 0..workload.initial_size {
-            map.insert(format!("key_{}", i), "initial".to_string())
+            map.insert(format!("key_{}", i), "i".repeat(workload.value_size))
                 .unwrap();
             _ops_performed += 1;
         }
 
         // Run mixed workload
         for _ in 0..workload.operations {
-            let op = rng.gen::<u8>() % 100;
-            let key_idx = rng.gen::<usize>() % workload.initial_size;
+            let op = u32::from(rng.gen::<u8>() % 100);
+            let key_idx = rng.gen::<usize>() % workload.key_space;
+            let key = format!("key_{}", key_idx);
 
-            if op < workload.read_pct {
-                let _ = map.get(format!("key_{}", key_idx));
-            } else if op < (workload.read_pct + workload.write_pct) {
-                let _ = map.insert(format!("key_{}", key_idx), "updated".to_string());
+            let start = latency.is_some().then(Instant::now);
+            if op < read_end {
+                let _ = map.get(key);
+            } else if op < write_end {
+                let _ = map.insert(key, "u".repeat(workload.value_size));
+            } else if op < update_end {
+                apply_update(map, key);
             } else {
-                let _ = map.delete(format!("key_{}", key_idx));
+                let _ = map.delete(key);
+            }
+            if let (Some(latency), Some(start)) = (latency.as_deref_mut(), start) {
+                latency.record(start.elapsed());
             }
             _ops_performed += 1;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_behavior_through_trait_objects() {
+        let mut maps: Vec<Box<dyn HashMapBehavior<u32, u32>>> = vec![
+            Box::new(crate::chaining::HashMap::new(16)),
+            Box::new(crate::open_addressing::HashMap::new(16)),
+            Box::new(crate::open_addressing_compact::HashMap::new(16)),
+            Box::new(std::collections::HashMap::new()),
+        ];
+        for map in maps.iter_mut() {
+            assert!(map.is_empty());
+            for i in 0..100 {
+                map.insert(i, i).unwrap();
+            }
+            assert_eq!(map.len(), 100);
+            assert!(map.contains_key(&42));
+            assert!(!map.contains_key(&100));
+
+            let capacity = map.capacity();
+            map.clear();
+            assert!(map.is_empty());
+            assert!(!map.contains_key(&42));
+            assert_eq!(map.capacity(), capacity);
+            map.insert(7, 7).unwrap();
+            assert_eq!(map.get(7), Some(7));
+        }
+    }
+
+    #[test]
+    fn test_baseline_workload_matches_std() {
+        let workload = OperationMixWorkload {
+            initial_size: 200,
+            key_space: 200,
+            operations: 5_000,
+            read_pct: 30,
+            write_pct: 30,
+            update_pct: 20,
+            value_size: 4,
+        };
+        let (stats, matches) =
+            generators::run_baseline_workload::<crate::chaining::HashMap<_, _>>(&workload, 1);
+        assert!(matches);
+        assert_eq!(stats.operations, 5_200);
+        let (_, matches) = generators::run_baseline_workload::<crate::open_addressing::HashMap<_, _>>(
+            &workload, 2,
+        );
+        assert!(matches);
+        let (stats, matches) = generators::run_baseline_workload::<
+            crate::open_addressing_compact::HashMap<_, _>,
+        >(&workload, 3);
+        assert!(matches);
+        assert!(stats.final_size < 200);
+    }
+
+    #[test]
+    fn test_large_key_space_grows_the_map() {
+        let workload = OperationMixWorkload {
+            initial_size: 100,
+            key_space: 5_000,
+            operations: 5_000,
+            read_pct: 30,
+            write_pct: 60,
+            update_pct: 0,
+            value_size: 8,
+        };
+        let initial_capacity =
+            <crate::open_addressing::HashMap<String, String> as HashMapBehavior<_, _>>::new(100)
+                .capacity();
+        let map = generators::run_operation_mix_workload::<crate::open_addressing::HashMap<_, _>>(
+            &workload,
+        );
+        assert!(map.capacity() > initial_capacity);
+        assert!(map.len() > 100);
+        assert!(map.resize_count() > 0);
+
+        // and the custom map still agrees with std when keys can be absent
+        let (_, matches) =
+            generators::run_baseline_workload::<crate::chaining::HashMap<_, _>>(&workload, 4);
+        assert!(matches);
+    }
+
+    #[test]
+    fn test_latency_times_every_operation() {
+        let workload = LoadFactorWorkload {
+            size: 50_000,
+            value_size: 8,
+            preallocate: false,
+        };
+        let stats = generators::run_load_factor_workload_with_latency::<
+            crate::open_addressing::HashMap<_, _>,
+        >(&workload);
+        assert!(stats.resizes > 5);
+        // every insert is timed, resizing ones included; how slow they were
+        // depends on the machine, so only the shape of the summary is checked
+        let latency = stats.latency.unwrap();
+        assert_eq!(latency.samples, 50_000);
+        assert!(latency.p50 <= latency.p99 && latency.p99 <= latency.p999);
+        assert!(latency.p999 <= latency.max);
+        assert!(stats.to_json().contains("\"latency_ns\""));
+
+        let mix = OperationMixWorkload {
+            initial_size: 100,
+            key_space: 10_000,
+            operations: 10_000,
+            read_pct: 20,
+            write_pct: 80,
+            update_pct: 0,
+            value_size: 8,
+        };
+        let stats = generators::run_operation_mix_workload_with_latency::<
+            crate::chaining::HashMap<_, _>,
+        >(&mix);
+        let latency = stats.latency.unwrap();
+        assert!(stats.resizes > 0);
+        // prepopulation is not timed
+        assert_eq!(latency.samples, 10_000);
+        assert!(latency.p50 <= latency.p99 && latency.p99 <= latency.max);
+    }
+
+    #[test]
+    fn test_adversarial_keys_only_collide_without_a_seed() {
+        let keys = adversarial_keys(2_000, 8, |i| i);
+        assert_eq!(keys.len(), 2_000);
+
+        // seed 0 hashes like DefaultHasher::new(), which the keys target
+        let unseeded = generators::run_adversarial_workload::<
+            crate::chaining::HashMap<u64, u64, crate::FixedSeedState>,
+        >(&keys);
+        let (_, longest, _) = unseeded.collision_stats();
+        assert!(longest >= 100, "longest chain {longest}");
+
+        let seeded =
+            generators::run_adversarial_workload::<crate::chaining::HashMap<u64, u64>>(&keys);
+        let (_, longest, _) = seeded.collision_stats();
+        assert!(longest < 20, "longest chain {longest}");
+    }
+
+    #[test]
+    fn test_zipfian_frequencies_are_skewed() {
+        let sampler = ZipfSampler::new(1000, 1.0);
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts = vec![0usize; 1000];
+        for _ in 0..100_000 {
+            counts[sampler.sample(&mut rng)] += 1;
+        }
+        for rank in 0..4 {
+            assert!(counts[rank] > counts[rank + 1]);
+        }
+        assert!(counts[0] > counts[100..].iter().max().copied().unwrap() * 10);
+    }
+
+    #[test]
+    fn test_gaussian_workload_smoke() {
+        let workload = KeyDistributionWorkload {
+            size: 1000,
+            pattern: KeyPattern::Gaussian {
+                mean: 500.0,
+                stddev: 50.0,
+            },
+        };
+        generators::run_key_distribution_workload::<crate::chaining::HashMap<_, _>>(&workload);
+        generators::run_key_distribution_workload::<crate::open_addressing::HashMap<_, _>>(
+            &workload,
+        );
+        generators::run_key_distribution_workload::<crate::open_addressing_compact::HashMap<_, _>>(
+            &workload,
+        );
+        generators::run_key_distribution_workload_integers::<crate::chaining::HashMap<_, _>>(
+            &workload,
+        );
+        generators::run_key_distribution_workload_integers::<crate::open_addressing::HashMap<_, _>>(
+            &workload,
+        );
+        generators::run_key_distribution_workload_integers::<
+            crate::open_addressing_compact::HashMap<_, _>,
+        >(&workload);
+    }
+
+    #[test]
+    fn test_update_mutates_values() {
+        let mut map = crate::open_addressing::HashMap::<String, String>::new(16);
+        map.insert("key_0".to_string(), "initial".to_string())
+            .unwrap();
+        generators::apply_update(&mut map, "key_0".to_string());
+        generators::apply_update(&mut map, "key_1".to_string());
+        assert_eq!(map.get("key_0".to_string()), Some("INITIAL".to_string()));
+        assert_eq!(map.get("key_1".to_string()), None);
+    }
+
+    #[test]
+    fn test_operation_mix_value_size() {
+        let workload = OperationMixWorkload {
+            initial_size: 100,
+            key_space: 100,
+            operations: 1000,
+            read_pct: 0,
+            write_pct: 50,
+            update_pct: 50,
+            value_size: 64,
+        };
+        let map =
+            generators::run_operation_mix_workload::<crate::chaining::HashMap<_, _>>(&workload);
+        for i in 0..workload.initial_size {
+            let value = map.get(format!("key_{}", i)).unwrap();
+            assert_eq!(value.len(), workload.value_size);
+        }
+    }
+
+    #[test]
+    fn test_preallocated_load_factor_does_not_resize() {
+        let workload = LoadFactorWorkload {
+            size: 10_000,
+            value_size: 8,
+            preallocate: true,
+        };
+        let mut reserved = crate::open_addressing::HashMap::<u64, u64>::new(16);
+        reserved.reserve(workload.size);
+        let expected_capacity = reserved.capacity();
+
+        let map = generators::run_load_factor_workload_integers::<
+            crate::open_addressing::HashMap<_, _>,
+        >(&workload);
+        assert_eq!(map.capacity(), expected_capacity);
+        let map = generators::run_load_factor_workload_integers::<
+            crate::open_addressing_compact::HashMap<_, _>,
+        >(&workload);
+        assert_eq!(map.capacity(), expected_capacity);
+        let map = generators::run_load_factor_workload_integers::<crate::chaining::HashMap<_, _>>(
+            &workload,
+        );
+        assert_eq!(map.capacity(), expected_capacity);
+    }
+
+    #[test]
+    fn test_churn_workload_stays_bounded() {
+        fn check<M: HashMapBehavior<String, String>>() {
+            let workload = ChurnWorkload {
+                initial_size: 500,
+                rounds: 50,
+            };
+            let populated = generators::run_churn_workload::<M>(&ChurnWorkload {
+                rounds: 0,
+                ..workload
+            });
+            let map = generators::run_churn_workload::<M>(&workload);
+            assert!(map.capacity() <= populated.capacity() * 2);
+            for i in 0..workload.initial_size {
+                assert_eq!(
+                    map.get(format!("key_{}", i)),
+                    Some(format!("round_{}", workload.rounds - 1))
+                );
+            }
+        }
+        check::<crate::chaining::HashMap<_, _>>();
+        check::<crate::open_addressing::HashMap<_, _>>();
+        check::<crate::open_addressing_compact::HashMap<_, _>>();
+    }
+
+    #[test]
+    fn test_std_hashmap_baseline() {
+        let workload = OperationMixWorkload {
+            initial_size: 100,
+            key_space: 100,
+            operations: 1000,
+            read_pct: 50,
+            write_pct: 50,
+            update_pct: 0,
+            value_size: 16,
+        };
+        let map =
+            generators::run_operation_mix_workload::<std::collections::HashMap<_, _>>(&workload);
+        assert_eq!(map.len(), workload.initial_size);
+        for i in 0..workload.initial_size {
+            let value = HashMapBehavior::get(&map, format!("key_{}", i)).unwrap();
+            assert_eq!(value.len(), workload.value_size);
+        }
+    }
+
+    #[test]
+    fn test_zero_duration_json_is_valid() {
+        for operations in [0, 10] {
+            let stats = WorkloadStats {
+                elapsed: Duration::ZERO,
+                operations,
+                final_size: operations,
+                final_capacity: 16,
+                resizes: 0,
+                latency: None,
+            };
+            let json: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+            assert!(json["throughput"].is_null());
+            assert_eq!(json["operations"], operations);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "add up to more than 100")]
+    fn test_operation_mix_rejects_percentages_over_100() {
+        let workload = OperationMixWorkload {
+            initial_size: 10,
+            key_space: 100,
+            operations: 10,
+            read_pct: 200,
+            write_pct: 100,
+            update_pct: 0,
+            value_size: 8,
+        };
+        generators::run_operation_mix_workload::<crate::chaining::HashMap<_, _>>(&workload);
+    }
+
+    #[test]
+    #[should_panic(expected = "the key space must not be empty")]
+    fn test_operation_mix_rejects_an_empty_key_space() {
+        let workload = OperationMixWorkload {
+            initial_size: 0,
+            key_space: 0,
+            operations: 10,
+            read_pct: 50,
+            write_pct: 50,
+            update_pct: 0,
+            value_size: 8,
+        };
+        generators::run_operation_mix_workload::<crate::chaining::HashMap<_, _>>(&workload);
+    }
+
+    #[test]
+    fn test_adversarial_keys_mask_does_not_overflow() {
+        assert!(adversarial_keys(0, 64, |i| i).is_empty());
+        assert!(adversarial_keys(0, 100, |i| i).is_empty());
+        assert_eq!(adversarial_keys(5, 0, |i| i), vec![0, 1, 2, 3, 4]);
+    }
+}